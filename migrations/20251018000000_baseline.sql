@@ -0,0 +1,5 @@
+-- murack-sync のスキーマの起点
+--
+-- テーブルは murack-core が作成するので、ここでは作成しない。
+-- パスの補完候補などを、DB の照合順序によらずバイト順で読み込むための索引を追加する。
+CREATE INDEX IF NOT EXISTS murack_sync_tracks_path_c ON tracks (path COLLATE "C");
@@ -0,0 +1,291 @@
+mod backup_app;
+
+pub use backup_app::BackupApp;
+
+use std::{
+    fs::File,
+    io::{BufReader, BufWriter},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, anyhow};
+use chrono::{Local, NaiveDateTime};
+use flate2::{Compression, read::GzDecoder, write::GzEncoder};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::PgPool;
+
+use crate::{config, schema};
+
+/// バックアップファイルの形式のバージョン
+const FORMAT_VERSION: u32 = 1;
+
+/// バックアップファイルの拡張子
+const EXTENSION: &str = ".json.gz";
+
+/// ファイル名の日時部分の形式
+const TIMESTAMP_FORMAT: &str = "%Y%m%d-%H%M%S";
+
+/// バックアップ・復元する murack のテーブル (外部キーの参照先が先になる順)
+///
+/// 同じ DB にある他のツールのテーブルには触れない。
+const TABLES: [&str; 3] = ["tracks", "playlists", "playlist_tracks"];
+
+/// バックアップファイルの内容 (gzip 圧縮した JSON)
+#[derive(Serialize, Deserialize)]
+struct BackupFile {
+    format_version: u32,
+    created_at: String,
+    /// 作成時の DB のスキーマのバージョン
+    schema_version: Option<i64>,
+    /// `TABLES` の順
+    tables: Vec<TableData>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct TableData {
+    name: String,
+    /// 1 行ずつ `row_to_json` で取り出した値
+    rows: Vec<Value>,
+}
+
+/// 保存済みのバックアップ 1 件
+#[derive(Debug, Clone)]
+pub struct BackupEntry {
+    pub path: PathBuf,
+    pub created_at: Option<NaiveDateTime>,
+    /// 作成した理由 (manual, before-remove など)
+    pub label: String,
+    pub size: u64,
+}
+
+impl BackupEntry {
+    /// 作成日時の表示 (ファイル名から読み取れなければ空)
+    pub fn created_at_label(&self) -> String {
+        self.created_at
+            .map(|created_at| created_at.format("%Y-%m-%d %H:%M:%S").to_string())
+            .unwrap_or_default()
+    }
+
+    pub fn file_name(&self) -> String {
+        self.path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default()
+    }
+}
+
+/// プロファイルごとのバックアップの保存先
+///
+/// データディレクトリの backups/<プロファイル名>/ に保存する。
+pub struct BackupStore {
+    dir: PathBuf,
+}
+
+impl BackupStore {
+    pub fn open(profile: &str) -> anyhow::Result<Self> {
+        Ok(Self {
+            dir: config::data_dir()?.join("backups").join(profile),
+        })
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// 保存済みのバックアップの一覧 (新しい順)
+    pub fn list(&self) -> anyhow::Result<Vec<BackupEntry>> {
+        if !self.dir.is_dir() {
+            return Ok(vec![]);
+        }
+
+        let mut entries: Vec<BackupEntry> = std::fs::read_dir(&self.dir)
+            .with_context(|| format!("Failed to read {}", self.dir.display()))?
+            .filter_map(|entry| {
+                let entry = entry.ok()?;
+                let name = entry.file_name().to_str()?.to_owned();
+                let stem = name.strip_suffix(EXTENSION)?;
+                let (timestamp, label) = stem.split_once('_').unwrap_or((stem, ""));
+
+                Some(BackupEntry {
+                    created_at: NaiveDateTime::parse_from_str(timestamp, TIMESTAMP_FORMAT).ok(),
+                    label: label.to_owned(),
+                    size: entry.metadata().ok()?.len(),
+                    path: entry.path(),
+                })
+            })
+            .collect();
+
+        entries.sort_by_key(|entry| std::cmp::Reverse(entry.file_name()));
+        Ok(entries)
+    }
+
+    /// DB の全テーブルを書き出す
+    ///
+    /// `label` はファイル名に含める、作成した理由。
+    /// 書き出し中に他のクライアントが変更してもテーブル間で食い違わないよう、
+    /// 全テーブルを 1 つの読み取り専用のスナップショットから読む。
+    pub async fn create(&self, db_pool: &PgPool, label: &str) -> anyhow::Result<PathBuf> {
+        let mut tx = db_pool.begin().await?;
+        sqlx::query("SET TRANSACTION ISOLATION LEVEL REPEATABLE READ, READ ONLY")
+            .execute(&mut *tx)
+            .await?;
+
+        // テーブルの内容と同じ時点のバージョンを記録する
+        let schema_version = schema::current_version(&mut tx).await?;
+
+        let mut tables = vec![];
+        for name in TABLES {
+            let rows: Vec<String> = sqlx::query_scalar(&format!(
+                "SELECT row_to_json(t)::text FROM {} t",
+                quote(name)
+            ))
+            .fetch_all(&mut *tx)
+            .await
+            .with_context(|| format!("Failed to export {name}"))?;
+            let rows = rows
+                .iter()
+                .map(|row| serde_json::from_str(row))
+                .collect::<Result<_, _>>()?;
+
+            tables.push(TableData {
+                name: name.to_owned(),
+                rows,
+            });
+        }
+        tx.commit().await?;
+
+        let now = Local::now();
+        let backup = BackupFile {
+            format_version: FORMAT_VERSION,
+            created_at: now.to_rfc3339(),
+            schema_version,
+            tables,
+        };
+
+        std::fs::create_dir_all(&self.dir)
+            .with_context(|| format!("Failed to create {}", self.dir.display()))?;
+        let path = self.dir.join(format!(
+            "{}_{label}{EXTENSION}",
+            now.format(TIMESTAMP_FORMAT)
+        ));
+
+        // 書き込み途中のファイルが一覧や復元の対象にならないよう、別名で書いてから置き換える
+        let temp_path = path.with_extension("gz.tmp");
+        if let Err(e) = write_backup(&temp_path, &backup) {
+            let _ = std::fs::remove_file(&temp_path);
+            return Err(e);
+        }
+        std::fs::rename(&temp_path, &path)
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+
+        Ok(path)
+    }
+
+    /// バックアップの内容で DB の murack のテーブルを置き換える
+    ///
+    /// スキーマのバージョンが異なるバックアップは復元しない。
+    /// 1 トランザクションで行うので、失敗したら元の状態に戻る。
+    /// 他のツールのテーブルから参照されている場合は、消さずに失敗する。
+    pub async fn restore(&self, db_pool: &PgPool, path: &Path) -> anyhow::Result<()> {
+        let file =
+            File::open(path).with_context(|| format!("Failed to read {}", path.display()))?;
+        let backup: BackupFile = serde_json::from_reader(GzDecoder::new(BufReader::new(file)))
+            .with_context(|| format!("Failed to parse {}", path.display()))?;
+
+        if backup.format_version != FORMAT_VERSION {
+            return Err(anyhow!(
+                "対応していない形式のバックアップです (形式 {})",
+                backup.format_version
+            ));
+        }
+        let tables = TABLES
+            .iter()
+            .map(|name| {
+                backup
+                    .tables
+                    .iter()
+                    .find(|table| table.name == *name)
+                    .ok_or_else(|| anyhow!("バックアップに {name} テーブルがありません"))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        let mut tx = db_pool.begin().await?;
+
+        // 確認してから置き換えるまでの間にスキーマが変わらないよう、同じトランザクションで確認する
+        let current_version = schema::current_version(&mut tx).await?;
+        if backup.schema_version != current_version {
+            return Err(anyhow!(
+                "バックアップのスキーマ (バージョン {}) が現在の DB (バージョン {}) と異なるため復元できません",
+                schema::version_label(backup.schema_version),
+                schema::version_label(current_version)
+            ));
+        }
+
+        // CASCADE にしないので、他のテーブルから参照されていればエラーになる
+        let names: Vec<String> = TABLES.iter().map(|name| quote(name)).collect();
+        sqlx::query(&format!("TRUNCATE {}", names.join(", ")))
+            .execute(&mut *tx)
+            .await?;
+
+        for table in tables {
+            let quoted = quote(&table.name);
+            if !table.rows.is_empty() {
+                // 自身を参照する外部キーもあるので、テーブルごとに 1 文で入れる
+                sqlx::query(&format!(
+                    "INSERT INTO {quoted} OVERRIDING SYSTEM VALUE
+                    SELECT * FROM json_populate_recordset(NULL::{quoted}, $1::json)"
+                ))
+                .bind(serde_json::to_string(&table.rows)?)
+                .execute(&mut *tx)
+                .await
+                .with_context(|| format!("Failed to restore {}", table.name))?;
+            }
+
+            // 連番の続きを復元した行に合わせる
+            let serial_columns: Vec<String> = sqlx::query_scalar(
+                "SELECT attname::text FROM pg_attribute
+                WHERE attrelid = $1::regclass AND attnum > 0 AND NOT attisdropped
+                    AND pg_get_serial_sequence($1, attname) IS NOT NULL",
+            )
+            .bind(&quoted)
+            .fetch_all(&mut *tx)
+            .await?;
+            for column in serial_columns {
+                sqlx::query(&format!(
+                    "SELECT setval(pg_get_serial_sequence($1, $2), COALESCE(MAX({}), 0) + 1, false) FROM {quoted}",
+                    quote(&column)
+                ))
+                .bind(&quoted)
+                .bind(&column)
+                .execute(&mut *tx)
+                .await?;
+            }
+        }
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// バックアップファイルを削除
+    pub fn delete(&self, entry: &BackupEntry) -> anyhow::Result<()> {
+        std::fs::remove_file(&entry.path)
+            .with_context(|| format!("Failed to remove {}", entry.path.display()))
+    }
+}
+
+fn write_backup(path: &Path, backup: &BackupFile) -> anyhow::Result<()> {
+    let file = File::create(path).with_context(|| format!("Failed to write {}", path.display()))?;
+    let mut encoder = GzEncoder::new(BufWriter::new(file), Compression::default());
+    serde_json::to_writer(&mut encoder, backup)?;
+    encoder
+        .finish()
+        .and_then(|writer| writer.into_inner().map_err(|e| e.into_error()))
+        .and_then(|file| file.sync_all())
+        .with_context(|| format!("Failed to write {}", path.display()))
+}
+
+/// SQL の識別子として引用符で囲む
+fn quote(identifier: &str) -> String {
+    format!("\"{}\"", identifier.replace('"', "\"\""))
+}
@@ -0,0 +1,238 @@
+use std::{path::PathBuf, sync::Arc};
+
+use eframe::egui::{self, RichText, mutex::Mutex};
+use sqlx::PgPool;
+
+use super::{BackupEntry, BackupStore};
+use crate::legacy_commands::dry_run::format_bytes;
+
+/// バックアップ・復元の実行状態
+#[derive(Default)]
+enum TaskState {
+    #[default]
+    Idle,
+    Running(&'static str),
+    /// 終了時のメッセージ
+    Finished(Result<String, String>),
+}
+
+/// DB のバックアップ・復元のページ
+pub struct BackupApp {
+    store: Arc<BackupStore>,
+    db_pool: Arc<PgPool>,
+    entries: Vec<BackupEntry>,
+    selected: Option<PathBuf>,
+    /// 復元の確認を表示中か
+    confirming_restore: bool,
+    /// 復元の前に現在の内容をバックアップするか
+    backup_before_restore: bool,
+    task: Arc<Mutex<TaskState>>,
+    /// 一覧の読み込みに失敗した場合などのメッセージ
+    status: Option<String>,
+}
+
+impl BackupApp {
+    pub fn new(store: Arc<BackupStore>, db_pool: Arc<PgPool>) -> Self {
+        let mut app = Self {
+            store,
+            db_pool,
+            entries: vec![],
+            selected: None,
+            confirming_restore: false,
+            backup_before_restore: true,
+            task: Arc::default(),
+            status: None,
+        };
+        app.reload();
+        app
+    }
+
+    fn reload(&mut self) {
+        match self.store.list() {
+            Ok(entries) => {
+                self.entries = entries;
+                self.status = None;
+            }
+            Err(e) => self.status = Some(format!("{e:#}")),
+        }
+        if let Some(selected) = &self.selected {
+            if !self.entries.iter().any(|entry| entry.path == *selected) {
+                self.selected = None;
+            }
+        }
+    }
+
+    /// `busy` の間 (ジョブの実行中) はバックアップ・復元を行わない
+    pub fn show(&mut self, ui: &mut egui::Ui, busy: bool) {
+        // 終了したら一覧に反映する
+        let finished = {
+            let mut task = self.task.lock();
+            match std::mem::take(&mut *task) {
+                TaskState::Finished(result) => Some(result),
+                other => {
+                    *task = other;
+                    None
+                }
+            }
+        };
+        if let Some(result) = finished {
+            self.reload();
+            self.status = Some(result.unwrap_or_else(|e| format!("失敗しました: {e}")));
+        }
+
+        let running = match &*self.task.lock() {
+            TaskState::Running(label) => Some(*label),
+            _ => None,
+        };
+        let enabled = !busy && running.is_none();
+
+        ui.label(RichText::new("バックアップ").heading().strong());
+        ui.label(format!("保存先: {}", self.store.dir().display()));
+        ui.add_space(8.0);
+
+        ui.horizontal(|ui| {
+            if ui
+                .add_enabled(enabled, egui::Button::new("今すぐバックアップ"))
+                .on_disabled_hover_text("ジョブの実行中はバックアップできません")
+                .clicked()
+            {
+                self.start_backup(ui.ctx().clone());
+            }
+            if ui.button("再読み込み").clicked() {
+                self.reload();
+            }
+
+            if let Some(label) = running {
+                ui.spinner();
+                ui.label(format!("{label}..."));
+            }
+        });
+
+        if let Some(status) = &self.status {
+            ui.label(status);
+        }
+
+        ui.separator();
+
+        let mut delete = None;
+        egui::ScrollArea::vertical()
+            .id_salt("backup_list")
+            .max_height(300.0)
+            .auto_shrink([false, true])
+            .show(ui, |ui| {
+                if self.entries.is_empty() {
+                    ui.weak("バックアップはまだありません");
+                }
+
+                egui::Grid::new("backup_list_grid")
+                    .num_columns(4)
+                    .striped(true)
+                    .show(ui, |ui| {
+                        for entry in &self.entries {
+                            let selected = self.selected.as_ref() == Some(&entry.path);
+                            if ui
+                                .selectable_label(selected, entry.created_at_label())
+                                .on_hover_text(entry.file_name())
+                                .clicked()
+                            {
+                                self.selected = Some(entry.path.clone());
+                                self.confirming_restore = false;
+                            }
+                            ui.label(&entry.label);
+                            ui.label(format_bytes(entry.size));
+                            if ui
+                                .add_enabled(enabled, egui::Button::new("削除").small())
+                                .clicked()
+                            {
+                                delete = Some(entry.clone());
+                            }
+                            ui.end_row();
+                        }
+                    });
+            });
+
+        if let Some(entry) = delete {
+            if let Err(e) = self.store.delete(&entry) {
+                self.status = Some(format!("{e:#}"));
+            }
+            self.reload();
+        }
+
+        ui.separator();
+
+        let Some(selected) = self.selected.clone() else {
+            ui.weak("復元するには一覧から選択してください");
+            return;
+        };
+
+        if !self.confirming_restore {
+            if ui
+                .add_enabled(enabled, egui::Button::new("選択したバックアップから復元"))
+                .clicked()
+            {
+                self.confirming_restore = true;
+            }
+            return;
+        }
+
+        ui.colored_label(
+            egui::Color32::LIGHT_YELLOW,
+            "⚠ DB の全テーブルの内容が、選択したバックアップの内容に置き換わります。",
+        );
+        ui.checkbox(
+            &mut self.backup_before_restore,
+            "復元の前に現在の内容をバックアップする",
+        );
+        ui.horizontal(|ui| {
+            if ui
+                .add_enabled(enabled, egui::Button::new(RichText::new("復元").strong()))
+                .clicked()
+            {
+                self.confirming_restore = false;
+                self.start_restore(selected, ui.ctx().clone());
+            }
+            if ui.button("キャンセル").clicked() {
+                self.confirming_restore = false;
+            }
+        });
+    }
+
+    fn start_backup(&self, ctx: egui::Context) {
+        let store = self.store.clone();
+        let db_pool = self.db_pool.clone();
+        let task = self.task.clone();
+        *task.lock() = TaskState::Running("バックアップ中");
+
+        tokio::spawn(async move {
+            let result = store
+                .create(&db_pool, "manual")
+                .await
+                .map(|path| format!("{} に保存しました", path.display()))
+                .map_err(|e| format!("{e:#}"));
+            *task.lock() = TaskState::Finished(result);
+            ctx.request_repaint();
+        });
+    }
+
+    fn start_restore(&self, path: PathBuf, ctx: egui::Context) {
+        let store = self.store.clone();
+        let db_pool = self.db_pool.clone();
+        let task = self.task.clone();
+        let backup_before_restore = self.backup_before_restore;
+        *task.lock() = TaskState::Running("復元中");
+
+        tokio::spawn(async move {
+            let result = async {
+                if backup_before_restore {
+                    store.create(&db_pool, "before-restore").await?;
+                }
+                store.restore(&db_pool, &path).await
+            }
+            .await
+            .map(|()| format!("{} から復元しました", path.display()))
+            .map_err(|e| format!("{e:#}"));
+            *task.lock() = TaskState::Finished(result);
+            ctx.request_repaint();
+        });
+    }
+}
@@ -0,0 +1,291 @@
+mod terminal_cui;
+
+use anyhow::anyhow;
+use clap::{Parser, Subcommand};
+use murack_core_app::{
+    command::{CommandAddArgs, CommandCheckArgs, CommandMoveArgs, CommandRemoveArgs},
+    cui::Cui,
+};
+use murack_core_domain::NonEmptyString;
+use murack_sync::{
+    config, dap_device,
+    legacy_commands::{
+        di_registry::DIRegistry,
+        dry_run::{self, SpaceEstimate},
+        playlist_selection::{self, PlaylistSelection},
+        policy_cui::{Decision, PolicyCui, ResolvePolicy},
+        resolve_step::CheckCuis,
+    },
+    schema::{self, SchemaStatus},
+    startup::AppServices,
+};
+use serde_json::json;
+use sqlx::PgPool;
+
+use crate::terminal_cui::TerminalCui;
+
+/// GUI なしでレガシーコマンドを実行する
+#[derive(Parser)]
+#[command(name = "murack-sync-cli")]
+struct Cli {
+    /// 使用するプロファイル (省略時は GUI で最後に使ったもの)
+    #[arg(long, global = true)]
+    profile: Option<String>,
+    #[command(subcommand)]
+    command: CliCommand,
+}
+
+#[derive(Subcommand)]
+enum CliCommand {
+    /// 曲をライブラリに追加
+    Add {
+        /// 追加する曲のライブラリパス
+        path: String,
+    },
+    /// ライブラリ内で曲のパスを移動
+    Move {
+        /// 移動元のライブラリパス
+        src_path: String,
+        /// 移動先のライブラリパス
+        dest_path: String,
+    },
+    /// ライブラリから曲を削除
+    Remove {
+        /// 削除する曲のライブラリパス
+        path: String,
+    },
+    /// PC・DAP・DBの齟齬を確認・解決
+    Check {
+        /// 確認対象のライブラリパス (省略時は全体)
+        path: Option<String>,
+        /// DAPファイル内容を無視
+        #[arg(short, long)]
+        ignore_dap_content: bool,
+        /// 齟齬の解決方針 (ask, pc_wins, db_wins, skip_all, abort_on_first)
+        ///
+        /// 省略時は sync.toml の check_resolve_policy を使う
+        #[arg(long)]
+        policy: Option<ResolvePolicy>,
+    },
+    /// DAPのプレイリストを更新
+    ///
+    /// プロファイルで除外したプレイリストは書き込まない
+    Playlist {
+        /// 除外の選択を無視し、全てのプレイリストを書き込む
+        #[arg(long)]
+        all: bool,
+    },
+    /// DB のスキーマに未適用のマイグレーションを適用
+    Migrate {
+        /// 確認を省略して適用する (事前にバックアップを取ること)
+        #[arg(long)]
+        yes: bool,
+    },
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    env_logger::init();
+
+    let cli = Cli::parse();
+    let profile = cli.profile.unwrap_or_else(config::active_profile);
+
+    let AppServices {
+        config,
+        config_path,
+        settings,
+        db_pool,
+        journal,
+        backups,
+        schema,
+        ..
+    } = AppServices::load(&profile).await?;
+
+    if let CliCommand::Migrate { yes } = cli.command {
+        return migrate(&db_pool, &schema, yes).await;
+    }
+    if let Some(problem) = schema.describe_problem() {
+        return Err(anyhow!(
+            "{problem}\n`murack-sync-cli migrate` でスキーマを更新してください"
+        ));
+    }
+
+    // 全てのコマンドが DAP 上のファイルを扱うので、途中で失敗しないよう先に確かめる
+    let dap_status = dap_device::detect(
+        &config.dap_lib,
+        &dap_device::DapIdentification::load(&profile)?,
+    );
+    if let Some(reason) = dap_status.absent_reason() {
+        return Err(anyhow!("{reason} ({})", config.dap_lib.display()));
+    }
+
+    let di_registry = DIRegistry::new(
+        TerminalCui,
+        config.clone(),
+        config_path,
+        db_pool.clone(),
+        journal.clone(),
+        settings.auto_backup_before_destructive.then_some(backups),
+    );
+
+    let result = match cli.command {
+        CliCommand::Add { path } => {
+            let track_path = non_empty(path.clone(), "追加する曲のパス")?;
+            let plan = dry_run::plan_add(&config, &db_pool, &path).await?;
+            SpaceEstimate::new(&plan, &config.dap_lib).ensure_fits(di_registry.cui())?;
+            let command = di_registry.command_add(CommandAddArgs { path: track_path });
+            journal
+                .run_planned(
+                    "add",
+                    json!({ "path": path }),
+                    &plan,
+                    &db_pool,
+                    command.run(&db_pool),
+                )
+                .await
+        }
+        CliCommand::Move {
+            src_path,
+            dest_path,
+        } => {
+            let src = non_empty(src_path.clone(), "移動元のパス")?;
+            let dest = non_empty(dest_path.clone(), "移動先のパス")?;
+            let plan = dry_run::plan_move(&config, &db_pool, &src_path, &dest_path).await?;
+            di_registry.backup_before("move").await?;
+            let command = di_registry.command_move(CommandMoveArgs {
+                src_path: src,
+                dest_path: dest,
+            });
+            journal
+                .run_planned(
+                    "move",
+                    json!({ "src_path": src_path, "dest_path": dest_path }),
+                    &plan,
+                    &db_pool,
+                    command.run(&db_pool),
+                )
+                .await
+        }
+        CliCommand::Remove { path } => {
+            let track_path = non_empty(path.clone(), "削除する曲のパス")?;
+            let plan = dry_run::plan_remove(&config, &db_pool, &path).await?;
+            di_registry.backup_before("remove").await?;
+            let command = di_registry.command_remove(CommandRemoveArgs { path: track_path });
+            journal
+                .run_planned(
+                    "remove",
+                    json!({ "path": path }),
+                    &plan,
+                    &db_pool,
+                    command.run(&db_pool),
+                )
+                .await
+        }
+        CliCommand::Check {
+            path,
+            ignore_dap_content,
+            policy,
+        } => {
+            let policy = policy.unwrap_or(settings.check_resolve_policy);
+            let args = json!({
+                "path": path,
+                "ignore_dap_content": ignore_dap_content,
+                "policy": policy.label(),
+            });
+
+            if policy != ResolvePolicy::SkipAll {
+                di_registry.backup_before("check").await?;
+            }
+
+            let cui = PolicyCui::new(di_registry.cui(), policy);
+            let cuis = CheckCuis::new(&cui);
+            let result = di_registry
+                .command_check_with_cui(
+                    CommandCheckArgs {
+                        path: path.and_then(|p| p.try_into().ok()),
+                        ignore_dap_content,
+                    },
+                    &cuis,
+                )
+                .run(&db_pool)
+                .await;
+
+            let effects = cui
+                .decisions()
+                .iter()
+                .filter_map(Decision::effect)
+                .collect();
+            journal.record("check", args, effects, &result).await;
+
+            cui.output_summary()?;
+            result
+        }
+        CliCommand::Playlist { all } => {
+            let selection = if all {
+                PlaylistSelection::default()
+            } else {
+                PlaylistSelection::load(&profile)?
+            };
+            let excluded_ids = selection.resolve(&db_pool).await?;
+            let plan = dry_run::plan_playlist(&config, &db_pool, &excluded_ids).await?;
+            SpaceEstimate::new(&plan, &config.dap_lib).ensure_fits(di_registry.cui())?;
+            let command = di_registry.command_playlist();
+            journal
+                .run_planned(
+                    "playlist",
+                    json!({ "excluded": selection.excluded }),
+                    &plan,
+                    &db_pool,
+                    playlist_selection::run_with_exclusions(
+                        &db_pool,
+                        &excluded_ids,
+                        |pool| async move { command.run(&pool).await },
+                    ),
+                )
+                .await
+        }
+        CliCommand::Migrate { .. } => unreachable!("migrate は DB スキーマの確認前に処理済み"),
+    };
+
+    // 操作履歴に記録できなかったことは、コマンドの成否によらず知らせる
+    for failure in journal.take_failures() {
+        di_registry.cui().errln(format_args!("警告: {failure}"))?;
+    }
+    result
+}
+
+/// 未適用のマイグレーションを表示し、`yes` なら適用する
+async fn migrate(db_pool: &PgPool, status: &SchemaStatus, yes: bool) -> anyhow::Result<()> {
+    if !status.is_compatible() {
+        return Err(anyhow!(status.problems.join("\n")));
+    }
+    if status.pending.is_empty() {
+        println!(
+            "DB のスキーマは最新です (バージョン {})",
+            schema::version_label(status.current_version)
+        );
+        return Ok(());
+    }
+
+    println!("未適用のマイグレーション:");
+    for migration in &status.pending {
+        println!("  {} {}", migration.version, migration.description);
+    }
+    if !yes {
+        println!(
+            "適用すると元に戻せません。DB のバックアップ (pg_dump など) を取ってから --yes を付けて実行してください"
+        );
+        return Ok(());
+    }
+
+    schema::apply_migrations(db_pool).await?;
+    println!(
+        "{} 件のマイグレーションを適用しました",
+        status.pending.len()
+    );
+    Ok(())
+}
+
+fn non_empty(path: String, name: &str) -> anyhow::Result<NonEmptyString> {
+    path.try_into().map_err(|_| anyhow!("{name}が未入力です"))
+}
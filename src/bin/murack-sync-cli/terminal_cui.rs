@@ -0,0 +1,77 @@
+use std::fmt::Arguments;
+use std::io::{self, BufRead, Write};
+
+use anyhow::{Result, anyhow};
+use murack_core_app::cui::Cui;
+use murack_sync::legacy_commands::resolve_step::{ResolveStep, StepCui};
+
+/// ターミナル用の Cui 実装
+pub struct TerminalCui;
+
+impl Cui for TerminalCui {
+    fn out(&self, args: Arguments) -> anyhow::Result<()> {
+        let mut stdout = io::stdout().lock();
+        stdout.write_fmt(args)?;
+        stdout.flush()?;
+
+        Ok(())
+    }
+
+    fn outln(&self, args: Arguments) -> anyhow::Result<()> {
+        println!("{args}");
+
+        Ok(())
+    }
+
+    fn err(&self, args: Arguments) -> anyhow::Result<()> {
+        let mut stderr = io::stderr().lock();
+        stderr.write_fmt(args)?;
+        stderr.flush()?;
+
+        Ok(())
+    }
+
+    fn errln(&self, args: Arguments) -> anyhow::Result<()> {
+        eprintln!("{args}");
+
+        Ok(())
+    }
+
+    fn input_case(&self, cases: &[char], message: &str) -> Result<char> {
+        let cases_text = cases
+            .iter()
+            .map(char::to_string)
+            .collect::<Vec<_>>()
+            .join("/");
+
+        let stdin = io::stdin();
+
+        loop {
+            print!("{message} [{cases_text}]: ");
+            io::stdout().flush()?;
+
+            let mut line = String::new();
+            if stdin.lock().read_line(&mut line)? == 0 {
+                // cron などで標準入力がない場合は、待ち続けずに中断する
+                return Err(anyhow!(
+                    "選択肢の入力が必要ですが、標準入力が閉じられています"
+                ));
+            }
+
+            let mut chars = line.trim().chars();
+            if let (Some(c), None) = (chars.next(), chars.next()) {
+                if cases.contains(&c) {
+                    return Ok(c);
+                }
+            }
+
+            eprintln!("{cases_text} のいずれかを入力してください");
+        }
+    }
+}
+
+impl StepCui for TerminalCui {
+    fn input_case_in(&self, _step: ResolveStep, cases: &[char], message: &str) -> Result<char> {
+        self.input_case(cases, message)
+    }
+}
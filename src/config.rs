@@ -1,9 +1,137 @@
-use anyhow::anyhow;
+use std::path::PathBuf;
+
+use anyhow::{Context, anyhow};
 use directories_next::ProjectDirs;
 use murack_core_app::Config;
 
-pub fn load_config() -> anyhow::Result<Config> {
-    let proj_dirs = ProjectDirs::from("", "murack", "murack-sync").ok_or_else(|| {
+use crate::sync_settings::SyncSettings;
+
+/// 既定のプロファイル名 (config.toml を使う)
+pub const DEFAULT_PROFILE: &str = "default";
+
+pub fn load_config(profile: &str) -> anyhow::Result<Config> {
+    Config::load(&config_file_path(profile)?)
+}
+
+/// murack-core の設定ファイルのパス
+///
+/// 既定のプロファイルは ~/.config/murack-sync/config.toml 、
+/// それ以外は ~/.config/murack-sync/profiles/<プロファイル名>.toml
+pub fn config_file_path(profile: &str) -> anyhow::Result<PathBuf> {
+    if profile == DEFAULT_PROFILE {
+        return Ok(config_dir()?.join("config.toml"));
+    }
+
+    if !is_valid_profile_name(profile) {
+        return Err(anyhow!("Invalid profile name: {profile}"));
+    }
+    Ok(profiles_dir()?.join(format!("{profile}.toml")))
+}
+
+/// プロファイル名に使えるのは英数字と - _ のみ
+pub fn is_valid_profile_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+/// 設定ファイルのあるプロファイルの一覧 (既定のプロファイルは常に含む)
+pub fn list_profiles() -> anyhow::Result<Vec<String>> {
+    let mut profiles = vec![DEFAULT_PROFILE.to_owned()];
+
+    let dir = profiles_dir()?;
+    if dir.is_dir() {
+        let mut names: Vec<String> = std::fs::read_dir(&dir)
+            .with_context(|| format!("Failed to read {}", dir.display()))?
+            .filter_map(|entry| {
+                let path = entry.ok()?.path();
+                if path.extension()? != "toml" {
+                    return None;
+                }
+                let name = path.file_stem()?.to_str()?.to_owned();
+                is_valid_profile_name(&name).then_some(name)
+            })
+            .filter(|name| name != DEFAULT_PROFILE)
+            .collect();
+        names.sort();
+        profiles.extend(names);
+    }
+
+    Ok(profiles)
+}
+
+/// 前回使ったプロファイル
+///
+/// 読み込めなければ既定のプロファイルとする。
+pub fn active_profile() -> String {
+    load_sync_settings()
+        .ok()
+        .and_then(|settings| settings.active_profile)
+        .unwrap_or_else(|| DEFAULT_PROFILE.to_owned())
+}
+
+/// 使用中のプロファイルを sync.toml に記録する
+pub fn save_active_profile(profile: &str) -> anyhow::Result<()> {
+    let mut settings = load_sync_settings()?;
+    settings.active_profile = (profile != DEFAULT_PROFILE).then(|| profile.to_owned());
+
+    settings.save(&sync_settings_path()?)
+}
+
+/// DAP の見分け方の保存先
+///
+/// ~/.config/murack-sync/dap_identifications/<プロファイル名>.toml
+pub fn dap_identification_path(profile: &str) -> anyhow::Result<PathBuf> {
+    if !is_valid_profile_name(profile) {
+        return Err(anyhow!("Invalid profile name: {profile}"));
+    }
+    Ok(config_dir()?
+        .join("dap_identifications")
+        .join(format!("{profile}.toml")))
+}
+
+/// DAP に書き込むプレイリストの選択の保存先
+///
+/// ~/.config/murack-sync/playlist_selections/<プロファイル名>.toml
+pub fn playlist_selection_path(profile: &str) -> anyhow::Result<PathBuf> {
+    if !is_valid_profile_name(profile) {
+        return Err(anyhow!("Invalid profile name: {profile}"));
+    }
+    Ok(config_dir()?
+        .join("playlist_selections")
+        .join(format!("{profile}.toml")))
+}
+
+/// murack-sync 独自の設定を読み込む
+///
+/// ファイルがなければ既定値を使う。
+pub fn load_sync_settings() -> anyhow::Result<SyncSettings> {
+    SyncSettings::load(&sync_settings_path()?)
+}
+
+/// ~/.config/murack-sync/sync.toml
+fn sync_settings_path() -> anyhow::Result<PathBuf> {
+    Ok(config_dir()?.join("sync.toml"))
+}
+
+/// ログやレポートなど、アプリが生成するファイルの保存先
+///
+/// ~/.local/share/murack-sync
+pub fn data_dir() -> anyhow::Result<PathBuf> {
+    Ok(project_dirs()?.data_dir().to_path_buf())
+}
+
+fn config_dir() -> anyhow::Result<PathBuf> {
+    Ok(project_dirs()?.config_dir().to_path_buf())
+}
+
+fn profiles_dir() -> anyhow::Result<PathBuf> {
+    Ok(config_dir()?.join("profiles"))
+}
+
+fn project_dirs() -> anyhow::Result<ProjectDirs> {
+    ProjectDirs::from("", "murack", "murack-sync").ok_or_else(|| {
         anyhow!(
             "Failed to determine config directory path. \
            This usually happens when the HOME environment variable is not 
@@ -11,11 +139,5 @@ pub fn load_config() -> anyhow::Result<Config> {
            Config file should be located at 
   ~/.config/murack-sync/config.toml"
         )
-    })?;
-
-    let config_dir = proj_dirs.config_dir();
-    // ~/.config/murack-sync/config.toml
-    let config_file_path = config_dir.join("config.toml");
-
-    Config::load(&config_file_path)
+    })
 }
@@ -0,0 +1,513 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+
+use anyhow::Context;
+use eframe::egui::{self, mutex::Mutex};
+use tokio::task::AbortHandle;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{config, legacy_commands::dry_run::format_bytes};
+
+/// DAP として登録したデバイスのルートに置く目印のファイル
+pub const MARKER_FILE_NAME: &str = ".murack-dap";
+
+/// マウント状態を確認する間隔
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// DAP の接続状態
+#[derive(Debug, Clone, PartialEq)]
+pub enum DapStatus {
+    /// 登録済みのデバイスがマウントされている
+    Connected {
+        mount_point: PathBuf,
+        /// 目印のファイルか、ボリュームラベルか
+        identified_by: String,
+        space: Option<DiskSpace>,
+    },
+    /// DAP の見分け方が設定されていないので、確認せずに使う
+    Unchecked {
+        mount_point: PathBuf,
+        space: Option<DiskSpace>,
+    },
+    /// マウントされているが、登録済みの DAP と確認できない
+    Unregistered { mount_point: PathBuf },
+    /// DAP のパスが見つからない (未接続)
+    Absent,
+    /// マウント情報を取得できない環境
+    Unsupported,
+}
+
+impl DapStatus {
+    /// DAP を使うコマンドを実行してよいか
+    pub fn is_present(&self) -> bool {
+        matches!(
+            self,
+            DapStatus::Connected { .. } | DapStatus::Unchecked { .. } | DapStatus::Unsupported
+        )
+    }
+
+    /// 実行できない理由
+    pub fn absent_reason(&self) -> Option<&'static str> {
+        match self {
+            DapStatus::Absent => Some("DAP が接続されていません"),
+            DapStatus::Unregistered { .. } => {
+                Some("マウントされたデバイスが登録済みの DAP ではありません")
+            }
+            _ => None,
+        }
+    }
+
+    pub fn space(&self) -> Option<DiskSpace> {
+        match self {
+            DapStatus::Connected { space, .. } | DapStatus::Unchecked { space, .. } => *space,
+            _ => None,
+        }
+    }
+}
+
+/// ファイルシステムの容量
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DiskSpace {
+    pub free_bytes: u64,
+    pub total_bytes: u64,
+}
+
+/// /proc/mounts の 1 行
+#[derive(Debug, Clone)]
+struct MountEntry {
+    device: String,
+    mount_point: PathBuf,
+}
+
+/// 登録済みの DAP の見分け方
+///
+/// プロファイルごとに DAP が違うので、プロファイルごとに保存する。
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DapIdentification {
+    /// マウントのルートに目印のファイルがあるデバイスだけを DAP とみなすか
+    pub require_marker: bool,
+    /// DAP として認識するボリュームラベル
+    pub volume_labels: Vec<String>,
+}
+
+impl DapIdentification {
+    /// プロファイルの見分け方を読み込む (なければ未設定)
+    pub fn load(profile: &str) -> anyhow::Result<Self> {
+        let path = config::dap_identification_path(profile)?;
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+
+        let text = std::fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        toml::from_str(&text).with_context(|| format!("Failed to parse {}", path.display()))
+    }
+
+    pub fn save(&self, profile: &str) -> anyhow::Result<()> {
+        let path = config::dap_identification_path(profile)?;
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)
+                .with_context(|| format!("Failed to create {}", dir.display()))?;
+        }
+
+        let text = toml::to_string_pretty(self)?;
+        std::fs::write(&path, text).with_context(|| format!("Failed to write {}", path.display()))
+    }
+
+    /// 見分け方が設定されているか
+    ///
+    /// 設定されていなければ、DAP のパスがあるだけで使用可能とみなす。
+    pub fn is_configured(&self) -> bool {
+        self.require_marker || !self.volume_labels.is_empty()
+    }
+}
+
+/// DAP のパスの接続状態を調べる
+///
+/// ルートのファイルシステム上のパスは、マウントポイントのディレクトリが残っているだけとみなす。
+/// 見分け方が設定されていれば、どのマウントかによらず、
+/// `dap_lib` を含むマウントのルートに目印のファイルがあるか、
+/// デバイスのボリュームラベルが `volume_labels` のいずれかの場合だけ登録済みの DAP とみなす。
+pub fn detect(dap_lib: &Path, identification: &DapIdentification) -> DapStatus {
+    let Ok(mounts) = read_mounts() else {
+        return DapStatus::Unsupported;
+    };
+    if !dap_lib.is_dir() {
+        return DapStatus::Absent;
+    }
+
+    // dap_lib を含む最も深いマウント
+    let Some(mount) = mounts
+        .iter()
+        .filter(|mount| dap_lib.starts_with(&mount.mount_point))
+        .max_by_key(|mount| mount.mount_point.components().count())
+    else {
+        return DapStatus::Unsupported;
+    };
+
+    // DAP はマウントされていない
+    if mount.mount_point == Path::new("/") {
+        return DapStatus::Absent;
+    }
+
+    if !identification.is_configured() {
+        return DapStatus::Unchecked {
+            mount_point: mount.mount_point.clone(),
+            space: disk_space(dap_lib),
+        };
+    }
+
+    let identified_by = if mount.mount_point.join(MARKER_FILE_NAME).is_file() {
+        Some(MARKER_FILE_NAME.to_owned())
+    } else {
+        volume_label(&mount.device)
+            .filter(|label| identification.volume_labels.contains(label))
+            .map(|label| format!("ラベル {label}"))
+    };
+
+    match identified_by {
+        Some(identified_by) => DapStatus::Connected {
+            mount_point: mount.mount_point.clone(),
+            identified_by,
+            space: disk_space(dap_lib),
+        },
+        None => DapStatus::Unregistered {
+            mount_point: mount.mount_point.clone(),
+        },
+    }
+}
+
+/// マウントのルートに目印のファイルを作り、`profile` の DAP として登録する
+///
+/// 以降はこのプロファイルでは目印のファイルのあるデバイスだけを DAP とみなすよう、
+/// 見分け方にも記録する。
+pub fn register(mount_point: &Path, profile: &str) -> anyhow::Result<DapIdentification> {
+    let path = mount_point.join(MARKER_FILE_NAME);
+    std::fs::write(&path, "murack-sync DAP\n")
+        .with_context(|| format!("Failed to write {}", path.display()))?;
+
+    let mut identification = DapIdentification::load(profile)?;
+    identification.require_marker = true;
+    identification.save(profile)?;
+    Ok(identification)
+}
+
+fn read_mounts() -> anyhow::Result<Vec<MountEntry>> {
+    let text = std::fs::read_to_string("/proc/mounts").context("Failed to read /proc/mounts")?;
+
+    Ok(text
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let device = fields.next()?;
+            let mount_point = fields.next()?;
+            Some(MountEntry {
+                device: unescape_mount_field(device),
+                mount_point: PathBuf::from(unescape_mount_field(mount_point)),
+            })
+        })
+        .collect())
+}
+
+/// /proc/mounts の空白などは \040 のように 8 進数でエスケープされている
+fn unescape_mount_field(field: &str) -> String {
+    unescape(field, "", 3, 8)
+}
+
+/// /dev/disk/by-label から、デバイスのボリュームラベルを探す
+fn volume_label(device: &str) -> Option<String> {
+    let device = std::fs::canonicalize(device).ok()?;
+
+    std::fs::read_dir("/dev/disk/by-label")
+        .ok()?
+        .filter_map(Result::ok)
+        .find(|entry| std::fs::canonicalize(entry.path()).is_ok_and(|target| target == device))
+        .and_then(|entry| entry.file_name().to_str().map(unescape_label))
+}
+
+/// by-label のリンク名の空白などは \x20 のように 16 進数でエスケープされている
+fn unescape_label(name: &str) -> String {
+    unescape(name, "x", 2, 16)
+}
+
+/// `\` と `prefix` に続く `digits` 桁の数値を 1 バイトに戻す
+fn unescape(text: &str, prefix: &str, digits: usize, radix: u32) -> String {
+    let mut bytes = vec![];
+    let mut rest = text;
+
+    while let Some(index) = rest.find('\\') {
+        bytes.extend_from_slice(&rest.as_bytes()[..index]);
+        let escaped = &rest[index + 1..];
+        let code = escaped
+            .strip_prefix(prefix)
+            .and_then(|code| code.get(..digits))
+            .and_then(|code| u8::from_str_radix(code, radix).ok());
+
+        match code {
+            Some(byte) => {
+                bytes.push(byte);
+                rest = &escaped[prefix.len() + digits..];
+            }
+            None => {
+                bytes.push(b'\\');
+                rest = escaped;
+            }
+        }
+    }
+    bytes.extend_from_slice(rest.as_bytes());
+
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+/// `path` のあるファイルシステムの容量
+#[cfg(unix)]
+pub fn disk_space(path: &Path) -> Option<DiskSpace> {
+    use std::{ffi::CString, os::unix::ffi::OsStrExt};
+
+    let path = CString::new(path.as_os_str().as_bytes()).ok()?;
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    // SAFETY: path は NUL 終端の文字列、stat は書き込み可能な領域
+    if unsafe { libc::statvfs(path.as_ptr(), &mut stat) } != 0 {
+        return None;
+    }
+
+    let block_size = stat.f_frsize as u64;
+    Some(DiskSpace {
+        free_bytes: stat.f_bavail as u64 * block_size,
+        total_bytes: stat.f_blocks as u64 * block_size,
+    })
+}
+
+#[cfg(not(unix))]
+pub fn disk_space(_path: &Path) -> Option<DiskSpace> {
+    None
+}
+
+/// DAP の接続状態の共有
+pub struct DapDevice {
+    dap_lib: PathBuf,
+    /// 見分け方を保存するプロファイル
+    profile: String,
+    identification: Mutex<DapIdentification>,
+    status: Mutex<DapStatus>,
+    /// 見分け方の読み込みや登録に失敗したときのエラー
+    error: Mutex<Option<String>>,
+}
+
+impl DapDevice {
+    pub fn status(&self) -> DapStatus {
+        self.status.lock().clone()
+    }
+
+    pub fn is_present(&self) -> bool {
+        self.status.lock().is_present()
+    }
+
+    /// 状態を調べ直し、変化したら true を返す
+    fn refresh(&self) -> bool {
+        let identification = self.identification.lock().clone();
+        let status = detect(&self.dap_lib, &identification);
+        let mut current = self.status.lock();
+        let changed = *current != status;
+        *current = status;
+        changed
+    }
+
+    /// DAP として登録し、状態を調べ直す
+    fn register(&self, mount_point: &Path) {
+        match register(mount_point, &self.profile) {
+            Ok(identification) => {
+                *self.identification.lock() = identification;
+                *self.error.lock() = None;
+                self.refresh();
+            }
+            Err(e) => {
+                *self.error.lock() = Some(format!("DAP の登録に失敗しました: {e:#}"));
+            }
+        }
+    }
+
+    /// 接続状態の表示
+    pub fn show_status(&self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.label("DAP:");
+            match self.status() {
+                DapStatus::Connected {
+                    mount_point,
+                    identified_by,
+                    space,
+                } => {
+                    ui.colored_label(egui::Color32::LIGHT_GREEN, "● 接続中")
+                        .on_hover_text(format!("{identified_by} で確認"));
+                    ui.label(mount_point.display().to_string());
+                    show_space(ui, space);
+                }
+                DapStatus::Unchecked { mount_point, space } => {
+                    ui.colored_label(egui::Color32::LIGHT_GREEN, "● 使用可能")
+                        .on_hover_text("DAP の見分け方が設定されていないため、確認せずに使います");
+                    ui.label(self.dap_lib.display().to_string());
+                    show_space(ui, space);
+                    self.show_register_button(ui, &mount_point);
+                }
+                DapStatus::Unregistered { mount_point } => {
+                    ui.colored_label(egui::Color32::LIGHT_YELLOW, "● 未登録のデバイス");
+                    ui.label(mount_point.display().to_string());
+                    self.show_register_button(ui, &mount_point);
+                }
+                DapStatus::Absent => {
+                    ui.colored_label(egui::Color32::LIGHT_RED, "● 未接続");
+                    if self.dap_lib.is_dir() {
+                        ui.weak(format!(
+                            "{} はルートのファイルシステム上にあり、DAP がマウントされていません",
+                            self.dap_lib.display()
+                        ));
+                    } else {
+                        ui.weak(format!("{} が見つかりません", self.dap_lib.display()));
+                    }
+                }
+                DapStatus::Unsupported => {
+                    ui.weak("接続状態を確認できません");
+                }
+            }
+        });
+
+        let error = self.error.lock().clone();
+        if let Some(error) = error {
+            ui.horizontal(|ui| {
+                ui.colored_label(egui::Color32::LIGHT_RED, error);
+                if ui.small_button("閉じる").clicked() {
+                    *self.error.lock() = None;
+                }
+            });
+        }
+    }
+
+    fn show_register_button(&self, ui: &mut egui::Ui, mount_point: &Path) {
+        if ui
+            .button("DAP として登録")
+            .on_hover_text(format!(
+                "{MARKER_FILE_NAME} を作成し、以降は目印のあるデバイスだけを DAP とみなします"
+            ))
+            .clicked()
+        {
+            self.register(mount_point);
+        }
+    }
+}
+
+fn show_space(ui: &mut egui::Ui, space: Option<DiskSpace>) {
+    if let Some(space) = space {
+        ui.label(format!(
+            "空き {} / {}",
+            format_bytes(space.free_bytes),
+            format_bytes(space.total_bytes)
+        ));
+    }
+}
+
+/// DAP のマウント状態を定期的に確認するタスク
+///
+/// 破棄するとタスクを止める。
+pub struct DapMonitor {
+    device: Arc<DapDevice>,
+    task: AbortHandle,
+}
+
+impl DapMonitor {
+    /// `profile` の見分け方で監視を始める
+    pub fn start(dap_lib: PathBuf, profile: String, ctx: egui::Context) -> Self {
+        let (identification, error) = match DapIdentification::load(&profile) {
+            Ok(identification) => (identification, None),
+            Err(e) => (
+                DapIdentification::default(),
+                Some(format!("DAP の見分け方を読み込めません: {e:#}")),
+            ),
+        };
+
+        let device = Arc::new(DapDevice {
+            status: Mutex::new(detect(&dap_lib, &identification)),
+            dap_lib,
+            profile,
+            identification: Mutex::new(identification),
+            error: Mutex::new(error),
+        });
+
+        let task = tokio::spawn({
+            let device = device.clone();
+            async move {
+                loop {
+                    tokio::time::sleep(POLL_INTERVAL).await;
+                    let changed = tokio::task::block_in_place(|| device.refresh());
+                    if changed {
+                        ctx.request_repaint();
+                    }
+                }
+            }
+        });
+
+        Self {
+            device,
+            task: task.abort_handle(),
+        }
+    }
+
+    pub fn device(&self) -> Arc<DapDevice> {
+        self.device.clone()
+    }
+}
+
+impl Drop for DapMonitor {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unescape_mount_field_decodes_octal_escapes() {
+        assert_eq!(unescape_mount_field("/media/My\\040DAP"), "/media/My DAP");
+    }
+
+    #[test]
+    fn unescape_label_decodes_hex_escapes() {
+        assert_eq!(unescape_label("MY\\x20DAP"), "MY DAP");
+    }
+
+    #[test]
+    fn unescape_keeps_invalid_escapes() {
+        assert_eq!(unescape_mount_field("a\\04"), "a\\04");
+        assert_eq!(unescape_label("a\\xZZ\\"), "a\\xZZ\\");
+    }
+
+    #[test]
+    fn unescape_restores_multibyte_characters() {
+        assert_eq!(unescape_label("\\xe9\\x9f\\xb3"), "音");
+    }
+
+    #[test]
+    fn identification_is_configured_by_marker_or_label() {
+        assert!(!DapIdentification::default().is_configured());
+        assert!(
+            DapIdentification {
+                require_marker: true,
+                volume_labels: vec![],
+            }
+            .is_configured()
+        );
+        assert!(
+            DapIdentification {
+                require_marker: false,
+                volume_labels: vec!["DAP".to_owned()],
+            }
+            .is_configured()
+        );
+    }
+}
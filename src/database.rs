@@ -10,7 +10,7 @@ pub async fn connect_db_pool(database_url: &str) -> sqlx::Result<PgPool> {
 }
 
 /// アプリ起動時の疎通確認
-async fn test_connection(pool: &PgPool) -> sqlx::Result<()> {
+pub async fn test_connection(pool: &PgPool) -> sqlx::Result<()> {
     let row = sqlx::query("SELECT 1 as test").fetch_one(pool).await?;
 
     let test_value: i32 = row.get("test");
@@ -18,3 +18,10 @@ async fn test_connection(pool: &PgPool) -> sqlx::Result<()> {
 
     Ok(())
 }
+
+/// 実行中の疎通確認
+pub async fn ping(pool: &PgPool) -> sqlx::Result<()> {
+    sqlx::query("SELECT 1").execute(pool).await?;
+
+    Ok(())
+}
@@ -0,0 +1,177 @@
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use eframe::egui::{self, mutex::Mutex};
+use sqlx::PgPool;
+use tokio::{sync::Notify, task::AbortHandle};
+
+use crate::database;
+
+/// 接続できている間の疎通確認の間隔
+const CHECK_INTERVAL: Duration = Duration::from_secs(10);
+
+/// 疎通確認を諦めるまでの時間
+const PING_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// 再接続を試みる間隔の上限
+const MAX_RETRY_INTERVAL: Duration = Duration::from_secs(60);
+
+/// DB の接続状態
+#[derive(Debug, Clone)]
+pub enum DbStatus {
+    /// まだ確認していない
+    Unknown,
+    Connected,
+    Unreachable {
+        error: String,
+        /// 連続して失敗した回数
+        attempt: u32,
+        retry_at: Instant,
+    },
+}
+
+/// 共有の `PgPool` の疎通状態
+pub struct DbHealth {
+    status: Mutex<DbStatus>,
+    retry_now: Notify,
+}
+
+impl DbHealth {
+    pub fn status(&self) -> DbStatus {
+        self.status.lock().clone()
+    }
+
+    /// コマンドを実行してよい状態か
+    pub fn is_available(&self) -> bool {
+        !matches!(&*self.status.lock(), DbStatus::Unreachable { .. })
+    }
+
+    /// 待たずにすぐ再接続を試みる
+    pub fn retry_now(&self) {
+        self.retry_now.notify_one();
+    }
+
+    /// 接続状態の表示
+    pub fn show_indicator(&self, ui: &mut egui::Ui) {
+        match self.status() {
+            DbStatus::Unknown => {
+                ui.colored_label(egui::Color32::GRAY, "● DB 確認中");
+            }
+            DbStatus::Connected => {
+                ui.colored_label(egui::Color32::LIGHT_GREEN, "● DB 接続中");
+            }
+            DbStatus::Unreachable {
+                error,
+                attempt,
+                retry_at,
+            } => {
+                let seconds = retry_at.saturating_duration_since(Instant::now()).as_secs();
+                ui.colored_label(
+                    egui::Color32::LIGHT_RED,
+                    format!("● DB 切断 (再接続 {attempt} 回目まで {seconds} 秒)"),
+                )
+                .on_hover_text(error);
+
+                if ui.small_button("今すぐ再接続").clicked() {
+                    self.retry_now();
+                }
+
+                // 残り秒数を更新する
+                ui.ctx().request_repaint_after(Duration::from_secs(1));
+            }
+        }
+    }
+}
+
+/// DB の疎通を定期的に確認するタスク
+///
+/// `PgPool` は接続を取り直すので、切断中は疎通確認を間隔を延ばしながら繰り返すことで再接続する。
+/// 破棄するとタスクを止める。
+pub struct DbHealthMonitor {
+    health: Arc<DbHealth>,
+    task: AbortHandle,
+}
+
+impl DbHealthMonitor {
+    pub fn start(db_pool: Arc<PgPool>, ctx: egui::Context) -> Self {
+        let health = Arc::new(DbHealth {
+            status: Mutex::new(DbStatus::Unknown),
+            retry_now: Notify::new(),
+        });
+
+        let task = tokio::spawn(monitor(db_pool, health.clone(), ctx));
+
+        Self {
+            health,
+            task: task.abort_handle(),
+        }
+    }
+
+    pub fn health(&self) -> Arc<DbHealth> {
+        self.health.clone()
+    }
+}
+
+impl Drop for DbHealthMonitor {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+async fn monitor(db_pool: Arc<PgPool>, health: Arc<DbHealth>, ctx: egui::Context) {
+    let mut attempt = 0;
+
+    loop {
+        let result = match tokio::time::timeout(PING_TIMEOUT, database::ping(&db_pool)).await {
+            Ok(result) => result.map_err(|e| e.to_string()),
+            Err(_) => Err("疎通確認がタイムアウトしました".to_owned()),
+        };
+
+        let wait = match result {
+            Ok(()) => {
+                attempt = 0;
+                *health.status.lock() = DbStatus::Connected;
+                CHECK_INTERVAL
+            }
+            Err(error) => {
+                attempt += 1;
+                let wait = retry_interval(attempt);
+                *health.status.lock() = DbStatus::Unreachable {
+                    error,
+                    attempt,
+                    retry_at: Instant::now() + wait,
+                };
+                wait
+            }
+        };
+        ctx.request_repaint();
+
+        tokio::select! {
+            _ = tokio::time::sleep(wait) => {}
+            _ = health.retry_now.notified() => {}
+        }
+    }
+}
+
+/// 1, 2, 4, ... 秒と、失敗するたびに倍にする
+fn retry_interval(attempt: u32) -> Duration {
+    let seconds = 1u64 << attempt.saturating_sub(1).min(6);
+    Duration::from_secs(seconds).min(MAX_RETRY_INTERVAL)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn retry_interval_doubles_up_to_the_maximum() {
+        let seconds: Vec<u64> = (1..=8)
+            .map(|attempt| retry_interval(attempt).as_secs())
+            .collect();
+
+        assert_eq!(seconds, vec![1, 2, 4, 8, 16, 32, 60, 60]);
+        assert_eq!(retry_interval(u32::MAX), MAX_RETRY_INTERVAL);
+    }
+}
@@ -1,8 +1,25 @@
+pub mod auto_sync;
+mod check_report;
 mod command_pages;
 mod console;
-mod di_registry;
+pub mod di_registry;
+pub mod dry_run;
 mod egui_cui;
+mod job_notifier;
+mod job_queue;
 mod legacy_commands_app;
+mod library_path_source;
+mod log_file;
 mod navigation;
+mod operation_history;
+pub mod operation_journal;
+mod path_input;
+mod playlist_preview;
+pub mod playlist_selection;
+pub mod policy_cui;
+mod progress;
+pub mod resolve_step;
+mod sandbox;
+mod space_preflight;
 
 pub use legacy_commands_app::LegacyCommandsApp;
@@ -0,0 +1,184 @@
+use std::time::Instant;
+
+use anyhow::anyhow;
+use eframe::egui;
+use serde::{Deserialize, Serialize};
+
+use crate::legacy_commands::{
+    command_pages::{CommandRunner, check_runner, playlist_runner},
+    job_queue::{JobId, JobQueue, JobStatus},
+    playlist_selection::PlaylistSelection,
+    policy_cui::ResolvePolicy,
+};
+
+/// DAP の接続時に自動で実行するコマンドの設定
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AutoSyncSettings {
+    /// 登録済みの DAP が接続されたら自動で実行するか
+    pub enabled: bool,
+    /// 順に実行するコマンド
+    pub steps: Vec<AutoSyncStep>,
+}
+
+impl Default for AutoSyncSettings {
+    /// playlist の後に check -i
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            steps: vec![
+                AutoSyncStep::Playlist,
+                AutoSyncStep::Check {
+                    path: None,
+                    ignore_dap_content: true,
+                    policy: None,
+                },
+            ],
+        }
+    }
+}
+
+/// 自動同期で実行するコマンド 1 つ
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "command", rename_all = "snake_case")]
+pub enum AutoSyncStep {
+    Playlist,
+    Check {
+        /// 確認対象のライブラリパス (省略時は全体)
+        #[serde(default)]
+        path: Option<String>,
+        #[serde(default)]
+        ignore_dap_content: bool,
+        /// 齟齬の解決方針 (省略時は解決せずに次へ)
+        ///
+        /// 自動同期では確認に答えられないので、毎回確認は使えない。
+        #[serde(default)]
+        policy: Option<ResolvePolicy>,
+    },
+}
+
+impl AutoSyncStep {
+    /// ジョブの (要約, 実行処理) を作成
+    ///
+    /// playlist は `selection` で除外したプレイリストを書き込まない。
+    /// check の解決方針が毎回確認ならエラーにする。
+    pub fn job(&self, selection: &PlaylistSelection) -> anyhow::Result<(String, CommandRunner)> {
+        match self {
+            AutoSyncStep::Playlist => Ok((
+                "playlist (自動同期)".to_owned(),
+                playlist_runner(false, selection.clone(), vec![]),
+            )),
+            AutoSyncStep::Check {
+                path,
+                ignore_dap_content,
+                policy,
+            } => {
+                let policy = policy.unwrap_or(ResolvePolicy::SkipAll);
+                if policy == ResolvePolicy::Ask {
+                    return Err(anyhow!(
+                        "自動同期の check では解決方針「{}」を使えません",
+                        policy.label()
+                    ));
+                }
+
+                let mut summary = format!("check {}", path.as_deref().unwrap_or_default());
+                if *ignore_dap_content {
+                    summary.push_str(" -i");
+                }
+                summary.push_str(&format!(" ({}) (自動同期)", policy.label()));
+
+                let target_path = path.clone().and_then(|p| p.try_into().ok());
+                let runner = check_runner(target_path, *ignore_dap_content, policy, None, None);
+                Ok((summary, runner))
+            }
+        }
+    }
+}
+
+/// 実行中の自動同期
+pub struct AutoSyncSession {
+    /// (ジョブ ID, 要約)
+    jobs: Vec<(JobId, String)>,
+    started_at: Instant,
+}
+
+/// 全てのジョブが終わった自動同期の結果
+pub struct AutoSyncReport {
+    pub title: String,
+    pub detail: String,
+    pub color: egui::Color32,
+    pub succeeded: bool,
+}
+
+impl AutoSyncSession {
+    /// 設定されたコマンドをキューに積み、自動同期を始める
+    ///
+    /// いずれかのコマンドが完了しなければ、残りのコマンドは実行しない。
+    pub fn start(
+        queue: &mut JobQueue,
+        settings: &AutoSyncSettings,
+        selection: &PlaylistSelection,
+    ) -> anyhow::Result<Self> {
+        let jobs = settings
+            .steps
+            .iter()
+            .map(|step| step.job(selection))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        let summaries: Vec<String> = jobs.iter().map(|(summary, _)| summary.clone()).collect();
+        let ids = queue.push_chain(
+            jobs.into_iter()
+                .map(|(summary, runner)| (summary, runner, true))
+                .collect(),
+        );
+
+        Ok(Self {
+            jobs: ids.into_iter().zip(summaries).collect(),
+            started_at: Instant::now(),
+        })
+    }
+
+    /// 全てのジョブが終わっていれば、まとめた結果を返す
+    pub fn report(&self, queue: &JobQueue) -> Option<AutoSyncReport> {
+        let statuses: Vec<Option<&JobStatus>> =
+            self.jobs.iter().map(|(id, _)| queue.status(*id)).collect();
+        if statuses
+            .iter()
+            .any(|status| matches!(status, Some(JobStatus::Pending | JobStatus::Running)))
+        {
+            return None;
+        }
+
+        let succeeded = statuses
+            .iter()
+            .all(|status| matches!(status, Some(JobStatus::Succeeded)));
+
+        let seconds = self.started_at.elapsed().as_secs();
+        let mut detail = format!("所要時間 {}:{:02}", seconds / 60, seconds % 60);
+        for ((job_id, summary), status) in self.jobs.iter().zip(statuses) {
+            let status_text = match status {
+                Some(JobStatus::Failed(message)) => format!("失敗 ({message})"),
+                Some(JobStatus::Skipped) => "前のコマンドが完了しなかったため未実行".to_owned(),
+                Some(status) => status.label().0.to_owned(),
+                None => "一覧から削除".to_owned(),
+            };
+            detail.push_str(&format!("\n#{job_id} {summary}: {status_text}"));
+        }
+
+        Some(if succeeded {
+            AutoSyncReport {
+                title: "自動同期: 完了".to_owned(),
+                detail,
+                color: egui::Color32::LIGHT_GREEN,
+                succeeded,
+            }
+        } else {
+            AutoSyncReport {
+                title: "自動同期: 完了しなかったコマンドがあります".to_owned(),
+                detail,
+                color: egui::Color32::LIGHT_RED,
+                succeeded,
+            }
+        })
+    }
+}
@@ -0,0 +1,459 @@
+use std::{
+    collections::{BTreeSet, HashMap},
+    fmt::Write as _,
+    path::PathBuf,
+};
+
+use anyhow::Context;
+use eframe::egui;
+use egui_extras::{Column, TableBuilder};
+use murack_core_app::Config;
+use serde::Serialize;
+use sqlx::PgPool;
+
+use crate::{
+    config,
+    legacy_commands::{dry_run, policy_cui::Decision, resolve_step::ResolveStep},
+};
+
+/// check コマンドで見つかった齟齬の種類
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DiscrepancyKind {
+    MissingOnPc,
+    MissingOnDap,
+    MissingInDb,
+    MetadataMismatch,
+    DapContentMismatch,
+    Other,
+}
+
+impl DiscrepancyKind {
+    pub const ALL: [DiscrepancyKind; 6] = [
+        DiscrepancyKind::MissingOnPc,
+        DiscrepancyKind::MissingOnDap,
+        DiscrepancyKind::MissingInDb,
+        DiscrepancyKind::MetadataMismatch,
+        DiscrepancyKind::DapContentMismatch,
+        DiscrepancyKind::Other,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            DiscrepancyKind::MissingOnPc => "PCに存在しない",
+            DiscrepancyKind::MissingOnDap => "DAPに存在しない",
+            DiscrepancyKind::MissingInDb => "DBに存在しない",
+            DiscrepancyKind::MetadataMismatch => "メタデータ不一致",
+            DiscrepancyKind::DapContentMismatch => "DAPファイル内容不一致",
+            DiscrepancyKind::Other => "その他",
+        }
+    }
+
+    /// 確認元の解決処理と、確認前の曲の有無から齟齬の種類を判定
+    fn new(step: Option<ResolveStep>, track: &CheckedTrack) -> Self {
+        match step {
+            Some(ResolveStep::Existance) if !track.on_pc => DiscrepancyKind::MissingOnPc,
+            Some(ResolveStep::Existance) if !track.on_dap => DiscrepancyKind::MissingOnDap,
+            Some(ResolveStep::Existance) if !track.in_db => DiscrepancyKind::MissingInDb,
+            Some(ResolveStep::DataMatch) => DiscrepancyKind::MetadataMismatch,
+            Some(ResolveStep::Dap) => DiscrepancyKind::DapContentMismatch,
+            _ => DiscrepancyKind::Other,
+        }
+    }
+}
+
+/// check コマンドで確認した曲 1 件と、確認前の有無
+#[derive(Debug, Clone)]
+pub struct CheckedTrack {
+    pub path: String,
+    pub on_pc: bool,
+    pub on_dap: bool,
+    pub in_db: bool,
+}
+
+/// 指定したライブラリパス以下の、PC・DAP・DB のいずれかにある曲を列挙
+///
+/// 空のパスならライブラリ全体。
+pub async fn collect_tracks(
+    config: &Config,
+    db_pool: &PgPool,
+    path: &str,
+) -> anyhow::Result<Vec<CheckedTrack>> {
+    let db_paths: BTreeSet<String> = dry_run::fetch_track_paths(db_pool, path)
+        .await?
+        .into_iter()
+        .collect();
+
+    let mut paths = db_paths.clone();
+    paths.extend(dry_run::list_audio_files(&config.pc_lib, path)?);
+    paths.extend(dry_run::list_audio_files(&config.dap_lib, path)?);
+
+    Ok(paths
+        .into_iter()
+        .map(|path| CheckedTrack {
+            on_pc: config.pc_lib.join(&path).is_file(),
+            on_dap: config.dap_lib.join(&path).is_file(),
+            in_db: db_paths.contains(&path),
+            path,
+        })
+        .collect())
+}
+
+/// 曲 1 件の check コマンドでの決定内容
+pub struct TrackDecisions {
+    pub track: CheckedTrack,
+    pub decisions: Vec<Decision>,
+}
+
+/// 齟齬 1 件
+#[derive(Debug, Clone, Serialize)]
+pub struct Discrepancy {
+    pub kind: DiscrepancyKind,
+    /// 対象の曲のライブラリパス
+    pub path: String,
+    /// 確認元の解決処理
+    pub step: Option<ResolveStep>,
+    /// 同じ曲の同じ解決処理の確認のうち、何件目 (0 始まり) か
+    pub ordinal: usize,
+    pub message: String,
+    /// 確認で選べた選択肢
+    #[serde(skip)]
+    pub cases: Vec<char>,
+    /// 選択された解決方法 ('1': PC→DB, '2': DB→PC, '0': 未解決, '-': 中止)
+    pub choice: char,
+    pub automatic: bool,
+    /// レポート上から再解決を要求済みか
+    #[serde(skip)]
+    pub resolve_requested: bool,
+}
+
+impl Discrepancy {
+    /// 曲 1 件の決定内容から、齟齬の一覧を作る
+    fn from_track(track_decisions: TrackDecisions) -> Vec<Self> {
+        let TrackDecisions { track, decisions } = track_decisions;
+        let mut ordinals: HashMap<Option<ResolveStep>, usize> = HashMap::new();
+
+        decisions
+            .into_iter()
+            .map(|decision| {
+                let ordinal = ordinals.entry(decision.step).or_default();
+                let discrepancy = Self {
+                    kind: DiscrepancyKind::new(decision.step, &track),
+                    path: track.path.clone(),
+                    step: decision.step,
+                    ordinal: *ordinal,
+                    message: decision.message,
+                    cases: decision.cases,
+                    choice: decision.choice,
+                    automatic: decision.automatic,
+                    resolve_requested: false,
+                };
+                *ordinal += 1;
+                discrepancy
+            })
+            .collect()
+    }
+
+    pub fn resolution_label(&self) -> &'static str {
+        if self.resolve_requested {
+            return "再解決待ち";
+        }
+        match self.choice {
+            '1' => "PC→DB",
+            '2' => "DB→PC",
+            '0' => "未解決",
+            '-' => "中止",
+            _ => "その他",
+        }
+    }
+}
+
+/// レポートの行に対して要求された再解決
+pub struct ResolveRequest {
+    pub path: String,
+    pub step: ResolveStep,
+    /// 同じ曲の同じ解決処理の確認のうち、何件目 (0 始まり) か
+    pub ordinal: usize,
+    /// PC→DB なら '1'、DB→PC なら '2'
+    pub choice: char,
+}
+
+/// check コマンドの齟齬の一覧
+#[derive(Default)]
+pub struct CheckReport {
+    rows: Vec<Discrepancy>,
+    kind_filter: Option<DiscrepancyKind>,
+    text_filter: String,
+    /// 出力結果などのメッセージ
+    status: Option<String>,
+}
+
+impl CheckReport {
+    /// check コマンドの曲ごとの決定内容でレポートを作り直す
+    pub fn set_decisions(&mut self, tracks: Vec<TrackDecisions>) {
+        self.rows = tracks
+            .into_iter()
+            .flat_map(Discrepancy::from_track)
+            .collect();
+        self.status = None;
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rows.is_empty()
+    }
+
+    fn visible_rows(&self) -> Vec<usize> {
+        let text_filter = self.text_filter.trim().to_lowercase();
+
+        self.rows
+            .iter()
+            .enumerate()
+            .filter(|(_, row)| self.kind_filter.is_none_or(|kind| row.kind == kind))
+            .filter(|(_, row)| {
+                text_filter.is_empty()
+                    || row.path.to_lowercase().contains(&text_filter)
+                    || row.message.to_lowercase().contains(&text_filter)
+            })
+            .map(|(i, _)| i)
+            .collect()
+    }
+
+    /// レポートを表示
+    ///
+    /// 行ごとの解決ボタンが押されたら、その要求を返す。
+    pub fn show(&mut self, ui: &mut egui::Ui) -> Vec<ResolveRequest> {
+        let mut requests = vec![];
+
+        ui.horizontal(|ui| {
+            ui.label("種類:");
+            egui::ComboBox::from_id_salt("check_report_kind")
+                .selected_text(self.kind_filter.map_or("全て", |kind| kind.label()))
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut self.kind_filter, None, "全て");
+                    for kind in DiscrepancyKind::ALL {
+                        ui.selectable_value(&mut self.kind_filter, Some(kind), kind.label());
+                    }
+                });
+
+            ui.label("検索:");
+            ui.text_edit_singleline(&mut self.text_filter);
+        });
+
+        ui.horizontal(|ui| {
+            if ui.button("JSON 出力").clicked() {
+                self.status = Some(report_export_message(self.export_json()));
+            }
+            if ui.button("CSV 出力").clicked() {
+                self.status = Some(report_export_message(self.export_csv()));
+            }
+            if let Some(status) = &self.status {
+                ui.label(status);
+            }
+        });
+
+        let visible = self.visible_rows();
+        let row_height = ui.text_style_height(&egui::TextStyle::Body) + 6.0;
+
+        TableBuilder::new(ui)
+            .id_salt("check_report_table")
+            .striped(true)
+            .resizable(true)
+            .max_scroll_height(240.0)
+            .cell_layout(egui::Layout::left_to_right(egui::Align::Center))
+            .column(Column::initial(140.0).at_least(60.0))
+            .column(Column::initial(320.0).at_least(60.0).clip(true))
+            .column(Column::initial(80.0).at_least(60.0))
+            .column(Column::remainder().at_least(120.0))
+            .header(row_height, |mut header| {
+                for title in ["種類", "パス / メッセージ", "解決", "操作"] {
+                    header.col(|ui| {
+                        ui.strong(title);
+                    });
+                }
+            })
+            .body(|body| {
+                body.rows(row_height, visible.len(), |mut row| {
+                    let discrepancy = &mut self.rows[visible[row.index()]];
+
+                    row.col(|ui| {
+                        ui.label(discrepancy.kind.label());
+                    });
+                    row.col(|ui| {
+                        ui.label(&discrepancy.path)
+                            .on_hover_text(&discrepancy.message);
+                    });
+                    row.col(|ui| {
+                        let mut text = discrepancy.resolution_label().to_owned();
+                        if discrepancy.automatic {
+                            text.push_str(" (自動)");
+                        }
+                        ui.label(text);
+                    });
+                    row.col(|ui| {
+                        let Some(step) = discrepancy.step else {
+                            return;
+                        };
+                        if discrepancy.resolve_requested {
+                            return;
+                        }
+
+                        for (choice, label) in [('1', "PC→DB"), ('2', "DB→PC")] {
+                            if !discrepancy.cases.contains(&choice) {
+                                continue;
+                            }
+                            if ui.small_button(label).clicked() {
+                                requests.push(ResolveRequest {
+                                    path: discrepancy.path.clone(),
+                                    step,
+                                    ordinal: discrepancy.ordinal,
+                                    choice,
+                                });
+                                discrepancy.resolve_requested = true;
+                            }
+                        }
+                    });
+                });
+            });
+
+        requests
+    }
+
+    fn export_json(&self) -> anyhow::Result<PathBuf> {
+        let path = report_file_path("json")?;
+        let json = serde_json::to_string_pretty(&self.rows)?;
+        std::fs::write(&path, json)
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+
+        Ok(path)
+    }
+
+    fn export_csv(&self) -> anyhow::Result<PathBuf> {
+        let path = report_file_path("csv")?;
+
+        let mut csv = String::from("kind,path,step,choice,automatic,message\n");
+        for row in &self.rows {
+            writeln!(
+                csv,
+                "{},{},{},{},{},{}",
+                csv_field(row.kind.label()),
+                csv_field(&row.path),
+                csv_field(row.step.map_or("", |step| step.label())),
+                csv_field(row.resolution_label()),
+                row.automatic,
+                csv_field(&row.message),
+            )?;
+        }
+
+        std::fs::write(&path, csv)
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+
+        Ok(path)
+    }
+}
+
+fn csv_field(value: &str) -> String {
+    format!("\"{}\"", value.replace('"', "\"\""))
+}
+
+/// 出力先: データディレクトリの reports/check-<日時>.<拡張子>
+fn report_file_path(extension: &str) -> anyhow::Result<PathBuf> {
+    let dir = config::data_dir()?.join("reports");
+    std::fs::create_dir_all(&dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+
+    let timestamp = chrono::Local::now().format("%Y%m%d-%H%M%S");
+    Ok(dir.join(format!("check-{timestamp}.{extension}")))
+}
+
+fn report_export_message(result: anyhow::Result<PathBuf>) -> String {
+    match result {
+        Ok(path) => format!("{} に出力しました", path.display()),
+        Err(e) => format!("出力に失敗しました: {e:#}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decision(step: Option<ResolveStep>, choice: char) -> Decision {
+        Decision {
+            step,
+            cases: vec!['0', '1', '2'],
+            message: "message".to_owned(),
+            choice,
+            automatic: true,
+        }
+    }
+
+    #[test]
+    fn from_track_numbers_decisions_per_step() {
+        let track = CheckedTrack {
+            path: "artist/album/01.flac".to_owned(),
+            on_pc: true,
+            on_dap: false,
+            in_db: true,
+        };
+        let rows = Discrepancy::from_track(TrackDecisions {
+            track,
+            decisions: vec![
+                decision(Some(ResolveStep::Existance), '0'),
+                decision(Some(ResolveStep::DataMatch), '0'),
+                decision(Some(ResolveStep::DataMatch), '1'),
+            ],
+        });
+
+        let summary: Vec<_> = rows
+            .iter()
+            .map(|row| (row.kind, row.step, row.ordinal))
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                (
+                    DiscrepancyKind::MissingOnDap,
+                    Some(ResolveStep::Existance),
+                    0
+                ),
+                (
+                    DiscrepancyKind::MetadataMismatch,
+                    Some(ResolveStep::DataMatch),
+                    0
+                ),
+                (
+                    DiscrepancyKind::MetadataMismatch,
+                    Some(ResolveStep::DataMatch),
+                    1
+                ),
+            ]
+        );
+        assert!(rows.iter().all(|row| row.path == "artist/album/01.flac"));
+    }
+
+    #[test]
+    fn kind_of_existance_follows_missing_place() {
+        let track = |on_pc, on_dap, in_db| CheckedTrack {
+            path: "a.flac".to_owned(),
+            on_pc,
+            on_dap,
+            in_db,
+        };
+        let existance = Some(ResolveStep::Existance);
+
+        assert_eq!(
+            DiscrepancyKind::new(existance, &track(false, true, true)),
+            DiscrepancyKind::MissingOnPc
+        );
+        assert_eq!(
+            DiscrepancyKind::new(existance, &track(true, true, false)),
+            DiscrepancyKind::MissingInDb
+        );
+        assert_eq!(
+            DiscrepancyKind::new(existance, &track(true, true, true)),
+            DiscrepancyKind::Other
+        );
+        assert_eq!(
+            DiscrepancyKind::new(None, &track(false, false, false)),
+            DiscrepancyKind::Other
+        );
+    }
+}
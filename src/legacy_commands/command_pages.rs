@@ -5,9 +5,12 @@ pub mod page_move;
 pub mod page_playlist;
 pub mod page_remove;
 
-pub use command_page::{CommandPage, PageType};
+pub use command_page::{
+    CommandPage, CommandRunner, PageType, Planner, dry_run_summary, rehearsal_summary,
+    show_dry_run_checkbox, show_rehearsal_checkbox,
+};
 pub use page_add::PageAdd;
-pub use page_check::PageCheck;
+pub use page_check::{PageCheck, check_runner};
 pub use page_move::PageMove;
-pub use page_playlist::PagePlaylist;
+pub use page_playlist::{PagePlaylist, playlist_runner};
 pub use page_remove::PageRemove;
@@ -1,9 +1,14 @@
-use std::sync::Arc;
+use std::{future::Future, pin::Pin, sync::Arc};
 
 use eframe::egui;
+use murack_core_app::Config;
+use sqlx::PgPool;
 use tokio::task::JoinHandle;
 
-use crate::legacy_commands::di_registry::DIRegistry;
+use crate::legacy_commands::{
+    di_registry::DIRegistry, dry_run::DryRunPlan, egui_cui::EguiCui,
+    library_path_source::LibraryPathSource,
+};
 
 #[derive(PartialEq, Clone, Copy)]
 pub enum PageType {
@@ -14,13 +19,92 @@ pub enum PageType {
     Check,
 }
 
+/// キューで実行を待つコマンドの実行処理
+///
+/// 呼び出すとコマンドのタスクを起動する。
+pub type CommandRunner =
+    Box<dyn FnOnce(Arc<DIRegistry<EguiCui>>) -> JoinHandle<anyhow::Result<()>> + Send>;
+
+/// フォームの入力内容での実行予定の作成処理
+///
+/// 実行前に DAP の空き容量を確認するために使う。
+pub type Planner = Box<
+    dyn FnOnce(
+            Arc<Config>,
+            Arc<PgPool>,
+        ) -> Pin<Box<dyn Future<Output = anyhow::Result<DryRunPlan>> + Send>>
+        + Send,
+>;
+
+/// 見積もりのみのチェックボックスを表示
+///
+/// コマンドは実行せず、実行前の DB とファイルから推定した変更を一覧表示する。
+pub fn show_dry_run_checkbox(ui: &mut egui::Ui, dry_run: &mut bool) {
+    ui.horizontal(|ui| {
+        ui.checkbox(dry_run, "見積もりのみ (実行せず、推定した変更の一覧を表示)");
+    });
+}
+
+/// 見積もりのみならジョブの要約にその旨を付ける
+pub fn dry_run_summary(summary: String, dry_run: bool) -> String {
+    if dry_run {
+        format!("{summary} (見積もりのみ)")
+    } else {
+        summary
+    }
+}
+
+/// 試しに実行するチェックボックスを表示
+///
+/// DB の変更を取り消す接続と一時フォルダでコマンドを実行し、行われた変更を一覧表示する。
+pub fn show_rehearsal_checkbox(ui: &mut egui::Ui, rehearse: &mut bool) {
+    ui.horizontal(|ui| {
+        ui.checkbox(
+            rehearse,
+            "試しに実行 (DB とファイルの変更は取り消し、行われた変更の一覧を表示)",
+        );
+    });
+}
+
+/// 試しに実行するならジョブの要約にその旨を付ける
+pub fn rehearsal_summary(summary: String, rehearse: bool) -> String {
+    if rehearse {
+        format!("{summary} (試しに実行)")
+    } else {
+        summary
+    }
+}
+
 /// レガシーコマンド 1 つを扱うページの抽象化 trait
 pub trait CommandPage {
     fn page_type(&self) -> PageType;
 
     fn page_discription(&self) -> &str;
 
-    fn show_form(&mut self, ui: &mut egui::Ui);
+    fn show_form(&mut self, ui: &mut egui::Ui, path_source: &LibraryPathSource);
+
+    /// 実行に DAP の接続が必要か
+    ///
+    /// DAP が未接続の間は実行させない。
+    fn requires_dap(&self) -> bool {
+        true
+    }
+
+    /// ジョブ一覧に表示する、フォームの入力内容の要約
+    fn job_summary(&self) -> String;
+
+    /// DAP にファイルを書き込むコマンドなら、実行予定の作成処理を返す
+    fn create_planner(&self) -> Option<Planner> {
+        None
+    }
+
+    /// フォームの入力内容から、コマンドの実行処理を作成
+    fn create_runner(&self) -> CommandRunner;
 
-    fn run_command(&mut self, di_registry: Arc<DIRegistry>) -> JoinHandle<anyhow::Result<()>>;
+    /// ページ上の操作で追加実行を要求されたジョブを取り出す
+    ///
+    /// (要約, 実行処理) のリスト。
+    fn take_requested_jobs(&mut self) -> Vec<(String, CommandRunner)> {
+        vec![]
+    }
 }
@@ -1,20 +1,23 @@
-use std::sync::Arc;
-
 use anyhow::anyhow;
 use eframe::egui::Ui;
-use murack_core_app::command::CommandAddArgs;
+use murack_core_app::{command::CommandAddArgs, cui::Cui};
 use murack_core_domain::{EmptyStringError, NonEmptyString};
-use tokio::task::JoinHandle;
+use serde_json::json;
 
 use crate::legacy_commands::{
-    command_pages::{CommandPage, PageType},
-    di_registry::DIRegistry,
+    command_pages::{
+        CommandPage, CommandRunner, PageType, Planner, rehearsal_summary, show_rehearsal_checkbox,
+    },
+    dry_run::{self, Rehearsal, SpaceEstimate},
+    library_path_source::LibraryPathSource,
+    path_input::PathInput,
 };
 
 /// add コマンドのページ
 #[derive(Default)]
 pub struct PageAdd {
-    tracks_path: String,
+    tracks_path: PathInput,
+    dry_run: bool,
 }
 
 impl CommandPage for PageAdd {
@@ -26,26 +29,77 @@ impl CommandPage for PageAdd {
         "曲をライブラリに追加"
     }
 
-    fn show_form(&mut self, ui: &mut Ui) {
-        ui.horizontal(|ui| {
-            ui.label("追加する曲のライブラリパス:");
-            ui.text_edit_singleline(&mut self.tracks_path);
-        });
+    fn show_form(&mut self, ui: &mut Ui, path_source: &LibraryPathSource) {
+        self.tracks_path
+            .show(ui, "追加する曲のライブラリパス:", path_source);
+        show_rehearsal_checkbox(ui, &mut self.dry_run);
+    }
+
+    fn requires_dap(&self) -> bool {
+        // 試しに実行するだけなら DAP のファイルに触れない
+        !self.dry_run
+    }
+
+    fn job_summary(&self) -> String {
+        rehearsal_summary(format!("add {}", self.tracks_path.value()), self.dry_run)
     }
 
-    fn run_command(&mut self, di_registry: Arc<DIRegistry>) -> JoinHandle<anyhow::Result<()>> {
-        let tracks_path = self.tracks_path.clone();
+    fn create_planner(&self) -> Option<Planner> {
+        let tracks_path = self.tracks_path.value();
+
+        Some(Box::new(move |config, db_pool| {
+            Box::pin(async move { dry_run::plan_add(&config, &db_pool, &tracks_path).await })
+        }))
+    }
+
+    fn create_runner(&self) -> CommandRunner {
+        let tracks_path = self.tracks_path.value();
+        let dry_run = self.dry_run;
+
+        Box::new(move |di_registry| {
+            tokio::spawn(async move {
+                let path: NonEmptyString = match tracks_path.clone().try_into() {
+                    Ok(s) => s,
+                    Err(EmptyStringError) => return Err(anyhow!("追加する曲のパスが未入力です")),
+                };
+
+                let config = di_registry.config();
+                if dry_run {
+                    let plan = dry_run::rehearse(
+                        &Rehearsal::Add { path: tracks_path },
+                        &config,
+                        di_registry.config_path(),
+                        &di_registry.db_pool(),
+                        di_registry.cui(),
+                    )
+                    .await?;
+                    plan.output(di_registry.cui())?;
+                    let estimate = SpaceEstimate::new(&plan, &config.dap_lib);
+                    return di_registry.cui().outln(format_args!(
+                        "{} {}",
+                        plan.tag(),
+                        estimate.describe()
+                    ));
+                }
 
-        tokio::spawn(async move {
-            let tracks_path: NonEmptyString = match tracks_path.try_into() {
-                Ok(s) => s,
-                Err(EmptyStringError) => return Err(anyhow!("追加する曲のパスが未入力です")),
-            };
+                let plan = dry_run::plan_add(&config, &di_registry.db_pool(), &tracks_path).await?;
+                let estimate = SpaceEstimate::new(&plan, &config.dap_lib);
+                estimate.ensure_fits(di_registry.cui())?;
 
-            let command = di_registry.command_add(CommandAddArgs { path: tracks_path });
-            let db_pool = di_registry.db_pool();
+                let command = di_registry.command_add(CommandAddArgs { path });
+                let db_pool = di_registry.db_pool();
 
-            command.run(&db_pool).await
+                di_registry
+                    .journal()
+                    .run_planned(
+                        "add",
+                        json!({ "path": tracks_path }),
+                        &plan,
+                        &db_pool,
+                        command.run(&db_pool),
+                    )
+                    .await
+            })
         })
     }
 }
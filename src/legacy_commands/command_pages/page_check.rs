@@ -1,20 +1,42 @@
 use std::sync::Arc;
 
-use eframe::egui::Ui;
+use eframe::egui::{self, Ui, mutex::Mutex};
 use murack_core_app::command::CommandCheckArgs;
 use murack_core_domain::NonEmptyString;
-use tokio::task::JoinHandle;
+use serde_json::json;
 
 use crate::legacy_commands::{
-    command_pages::{CommandPage, PageType},
-    di_registry::DIRegistry,
+    check_report::{self, CheckReport, TrackDecisions},
+    command_pages::{CommandPage, CommandRunner, PageType, dry_run_summary, show_dry_run_checkbox},
+    library_path_source::LibraryPathSource,
+    path_input::PathInput,
+    policy_cui::{Decision, PolicyCui, ResolvePolicy, ResolveTarget},
+    resolve_step::CheckCuis,
 };
 
 /// check コマンドのページ
-#[derive(Default)]
 pub struct PageCheck {
-    target_path: String,
+    target_path: PathInput,
     ignore_dap_content: bool,
+    resolve_policy: ResolvePolicy,
+    dry_run: bool,
+    /// 直近の check コマンドで見つかった齟齬
+    report: Arc<Mutex<CheckReport>>,
+    /// レポート上から要求された再解決のジョブ
+    requested_jobs: Vec<(String, CommandRunner)>,
+}
+
+impl PageCheck {
+    pub fn new(resolve_policy: ResolvePolicy, report: Arc<Mutex<CheckReport>>) -> Self {
+        Self {
+            target_path: PathInput::default(),
+            ignore_dap_content: false,
+            resolve_policy,
+            dry_run: false,
+            report,
+            requested_jobs: vec![],
+        }
+    }
 }
 
 impl CommandPage for PageCheck {
@@ -26,29 +48,197 @@ impl CommandPage for PageCheck {
         "PC・DAP・DBの齟齬を確認・解決"
     }
 
-    fn show_form(&mut self, ui: &mut Ui) {
+    fn show_form(&mut self, ui: &mut Ui, path_source: &LibraryPathSource) {
+        self.target_path
+            .show(ui, "確認対象のライブラリパス:", path_source);
+
         ui.horizontal(|ui| {
-            ui.label("確認対象のライブラリパス:");
-            ui.text_edit_singleline(&mut self.target_path);
+            ui.checkbox(&mut self.ignore_dap_content, "DAPファイル内容を無視 (-i)");
         });
 
         ui.horizontal(|ui| {
-            ui.checkbox(&mut self.ignore_dap_content, "DAPファイル内容を無視 (-i)");
+            if self.dry_run {
+                // 確認のみで解決はしない
+                ui.disable();
+            }
+
+            ui.label("齟齬の解決方針:");
+            egui::ComboBox::from_id_salt("check_resolve_policy")
+                .selected_text(self.resolve_policy.label())
+                .show_ui(ui, |ui| {
+                    for policy in ResolvePolicy::ALL {
+                        ui.selectable_value(&mut self.resolve_policy, policy, policy.label());
+                    }
+                });
         });
+
+        show_dry_run_checkbox(ui, &mut self.dry_run);
+
+        let mut report = self.report.lock();
+        if report.is_empty() {
+            return;
+        }
+
+        egui::CollapsingHeader::new("前回の確認結果")
+            .default_open(true)
+            .show(ui, |ui| {
+                for request in report.show(ui) {
+                    let policy = match request.choice {
+                        '1' => ResolvePolicy::PcWins,
+                        _ => ResolvePolicy::DbWins,
+                    };
+                    let summary = format!(
+                        "check {} ({} {}件目: {})",
+                        request.path,
+                        request.step.label(),
+                        request.ordinal + 1,
+                        policy.label()
+                    );
+                    // 選んだ齟齬だけを解決し、同じ曲の他の齟齬は解決しない
+                    let target = ResolveTarget {
+                        step: request.step,
+                        ordinal: request.ordinal,
+                        policy,
+                    };
+                    let runner = check_runner(
+                        request.path.try_into().ok(),
+                        self.ignore_dap_content,
+                        ResolvePolicy::SkipAll,
+                        Some(target),
+                        // 1 件だけの再解決で、全体のレポートを上書きしない
+                        None,
+                    );
+
+                    self.requested_jobs.push((summary, runner));
+                }
+            });
+    }
+
+    fn job_summary(&self) -> String {
+        let mut summary = format!("check {}", self.target_path.value());
+        if self.ignore_dap_content {
+            summary.push_str(" -i");
+        }
+        if self.resolve_policy != ResolvePolicy::Ask && !self.dry_run {
+            summary.push_str(&format!(" ({})", self.resolve_policy.label()));
+        }
+        dry_run_summary(summary, self.dry_run)
     }
 
-    fn run_command(&mut self, di_registry: Arc<DIRegistry>) -> JoinHandle<anyhow::Result<()>> {
-        let target_path: Option<NonEmptyString> = self.target_path.clone().try_into().ok();
-        let ignore_dap_content = self.ignore_dap_content;
+    fn create_runner(&self) -> CommandRunner {
+        // 見積もりのみなら全ての齟齬を解決せずにスキップし、レポートのみ作成する
+        let resolve_policy = if self.dry_run {
+            ResolvePolicy::SkipAll
+        } else {
+            self.resolve_policy
+        };
 
+        check_runner(
+            self.target_path.value().try_into().ok(),
+            self.ignore_dap_content,
+            resolve_policy,
+            None,
+            Some(self.report.clone()),
+        )
+    }
+
+    fn take_requested_jobs(&mut self) -> Vec<(String, CommandRunner)> {
+        std::mem::take(&mut self.requested_jobs)
+    }
+}
+
+/// check コマンドの実行処理を作成
+///
+/// 齟齬がどの曲のものか分かるように、対象の曲ごとに check コマンドを実行する。
+/// `resolve_target` を指定すると、その齟齬だけを指定の方針で解決する。
+/// `report` を指定すると、実行後に見つかった齟齬をレポートに記録する。
+pub fn check_runner(
+    target_path: Option<NonEmptyString>,
+    ignore_dap_content: bool,
+    resolve_policy: ResolvePolicy,
+    resolve_target: Option<ResolveTarget>,
+    report: Option<Arc<Mutex<CheckReport>>>,
+) -> CommandRunner {
+    Box::new(move |di_registry| {
         tokio::spawn(async move {
-            let command = di_registry.command_check(CommandCheckArgs {
-                path: target_path,
-                ignore_dap_content,
+            let target = target_path.as_ref().map(ToString::to_string);
+            let args = json!({
+                "path": target,
+                "ignore_dap_content": ignore_dap_content,
+                "policy": resolve_policy.label(),
+                "target": resolve_target.map(|t| json!({
+                    "step": t.step,
+                    "ordinal": t.ordinal,
+                    "policy": t.policy.label(),
+                })),
             });
+
+            let config = di_registry.config();
             let db_pool = di_registry.db_pool();
+            let target = target.unwrap_or_default();
+            let tracks = check_report::collect_tracks(&config, &db_pool, &target).await?;
+            di_registry.cui().progress().set_total(tracks.len());
 
-            command.run(&db_pool).await
+            // 齟齬を解決しない場合は DB を変更しない
+            if resolve_policy != ResolvePolicy::SkipAll || resolve_target.is_some() {
+                di_registry.backup_before("check").await?;
+            }
+
+            let mut cui = PolicyCui::new(di_registry.cui(), resolve_policy);
+            if let Some(resolve_target) = resolve_target {
+                cui = cui.with_target(resolve_target);
+            }
+            let cuis = CheckCuis::new(&cui);
+
+            let mut checked = vec![];
+            let mut result = Ok(());
+            for track in tracks {
+                let Ok(path) = NonEmptyString::try_from(track.path.clone()) else {
+                    continue;
+                };
+                di_registry.cui().progress().begin_item(&track.path);
+                let start = cui.decision_count();
+                let command = di_registry.command_check_with_cui(
+                    CommandCheckArgs {
+                        path: Some(path),
+                        ignore_dap_content,
+                    },
+                    &cuis,
+                );
+                result = command.run(&db_pool).await;
+                di_registry.cui().progress().complete_item();
+
+                checked.push(TrackDecisions {
+                    track,
+                    decisions: cui.decisions_since(start),
+                });
+                if result.is_err() {
+                    break;
+                }
+            }
+
+            let effects = checked
+                .iter()
+                .flat_map(|checked| {
+                    checked
+                        .decisions
+                        .iter()
+                        .filter_map(Decision::effect)
+                        .map(|effect| format!("{effect} ({})", checked.track.path))
+                })
+                .collect();
+            di_registry
+                .journal()
+                .record("check", args, effects, &result)
+                .await;
+
+            // 中断された場合も、それまでに見つかった分は記録する
+            if let Some(report) = report {
+                report.lock().set_decisions(checked);
+            }
+
+            cui.output_summary()?;
+            result
         })
-    }
+    })
 }
@@ -1,21 +1,34 @@
-use std::sync::Arc;
-
 use anyhow::anyhow;
 use eframe::egui::Ui;
 use murack_core_app::command::CommandMoveArgs;
 use murack_core_domain::{EmptyStringError, NonEmptyString};
-use tokio::task::JoinHandle;
+use serde_json::json;
 
 use crate::legacy_commands::{
-    command_pages::{CommandPage, PageType},
-    di_registry::DIRegistry,
+    command_pages::{
+        CommandPage, CommandRunner, PageType, rehearsal_summary, show_rehearsal_checkbox,
+    },
+    dry_run::{self, Rehearsal},
+    library_path_source::LibraryPathSource,
+    path_input::PathInput,
 };
 
 /// move コマンドのページ
-#[derive(Default)]
 pub struct PageMove {
-    src_path: String,
-    dest_path: String,
+    src_path: PathInput,
+    dest_path: PathInput,
+    dry_run: bool,
+}
+
+impl Default for PageMove {
+    fn default() -> Self {
+        Self {
+            src_path: PathInput::default(),
+            // 移動先はまだ存在しないのが普通
+            dest_path: PathInput::allowing_missing(),
+            dry_run: false,
+        }
+    }
 }
 
 impl CommandPage for PageMove {
@@ -27,44 +40,94 @@ impl CommandPage for PageMove {
         "ライブラリ内で曲のパスを移動"
     }
 
-    fn show_form(&mut self, ui: &mut Ui) {
-        ui.horizontal(|ui| {
-            ui.label("移動元のライブラリパス:");
-            ui.text_edit_singleline(&mut self.src_path);
-        });
+    fn show_form(&mut self, ui: &mut Ui, path_source: &LibraryPathSource) {
+        self.src_path
+            .show(ui, "移動元のライブラリパス:", path_source);
+        self.dest_path
+            .show(ui, "移動先のライブラリパス:", path_source);
+        show_rehearsal_checkbox(ui, &mut self.dry_run);
+    }
+
+    fn requires_dap(&self) -> bool {
+        // 試しに実行するだけなら DAP のファイルに触れない
+        !self.dry_run
+    }
 
-        ui.horizontal(|ui| {
-            ui.label("移動先のライブラリパス:");
-            ui.text_edit_singleline(&mut self.dest_path);
-        });
+    fn job_summary(&self) -> String {
+        rehearsal_summary(
+            format!(
+                "move {} → {}",
+                self.src_path.value(),
+                self.dest_path.value()
+            ),
+            self.dry_run,
+        )
     }
 
-    fn run_command(&mut self, di_registry: Arc<DIRegistry>) -> JoinHandle<anyhow::Result<()>> {
-        let src_path = self.src_path.clone();
-        let dest_path = self.dest_path.clone();
+    fn create_runner(&self) -> CommandRunner {
+        let src_path = self.src_path.value();
+        let dest_path = self.dest_path.value();
+        let dry_run = self.dry_run;
 
-        tokio::spawn(async move {
-            let src_path: NonEmptyString = match src_path.try_into() {
-                Ok(s) => s,
-                Err(EmptyStringError) => {
-                    return Err(anyhow!("移動元のパスが未入力です"));
-                }
-            };
+        Box::new(move |di_registry| {
+            tokio::spawn(async move {
+                let src: NonEmptyString = match src_path.clone().try_into() {
+                    Ok(s) => s,
+                    Err(EmptyStringError) => {
+                        return Err(anyhow!("移動元のパスが未入力です"));
+                    }
+                };
 
-            let dest_path: NonEmptyString = match dest_path.try_into() {
-                Ok(s) => s,
-                Err(EmptyStringError) => {
-                    return Err(anyhow!("移動先のパスが未入力です"));
+                let dest: NonEmptyString = match dest_path.clone().try_into() {
+                    Ok(s) => s,
+                    Err(EmptyStringError) => {
+                        return Err(anyhow!("移動先のパスが未入力です"));
+                    }
+                };
+
+                if dry_run {
+                    let rehearsal = Rehearsal::Move {
+                        src_path,
+                        dest_path,
+                    };
+                    return dry_run::rehearse(
+                        &rehearsal,
+                        &di_registry.config(),
+                        di_registry.config_path(),
+                        &di_registry.db_pool(),
+                        di_registry.cui(),
+                    )
+                    .await?
+                    .output(di_registry.cui());
                 }
-            };
 
-            let command = di_registry.command_move(CommandMoveArgs {
-                src_path,
-                dest_path,
-            });
-            let db_pool = di_registry.db_pool();
+                let plan = dry_run::plan_move(
+                    &di_registry.config(),
+                    &di_registry.db_pool(),
+                    &src_path,
+                    &dest_path,
+                )
+                .await?;
+                // DB の移動元以下の全てのパスを書き換えるので、remove と同様にバックアップする
+                di_registry.backup_before("move").await?;
+
+                let command = di_registry.command_move(CommandMoveArgs {
+                    src_path: src,
+                    dest_path: dest,
+                });
+                let db_pool = di_registry.db_pool();
 
-            command.run(&db_pool).await
+                di_registry
+                    .journal()
+                    .run_planned(
+                        "move",
+                        json!({ "src_path": src_path, "dest_path": dest_path }),
+                        &plan,
+                        &db_pool,
+                        command.run(&db_pool),
+                    )
+                    .await
+            })
         })
     }
 }
@@ -1,16 +1,188 @@
-use std::sync::Arc;
+use std::{path::PathBuf, sync::Arc};
 
-use eframe::egui::Ui;
-use tokio::task::JoinHandle;
+use eframe::egui::{self, Ui, mutex::Mutex};
+use murack_core_app::{Config, cui::Cui};
+use serde_json::json;
+use sqlx::PgPool;
 
 use crate::legacy_commands::{
-    command_pages::{CommandPage, PageType},
-    di_registry::DIRegistry,
+    command_pages::{
+        CommandPage, CommandRunner, PageType, Planner, dry_run_summary, show_dry_run_checkbox,
+    },
+    dry_run::{self, SpaceEstimate},
+    library_path_source::LibraryPathSource,
+    playlist_preview::{self, PlaylistPreview, SkippedPlaylists},
+    playlist_selection::{self, PlaylistNode, PlaylistSelection},
 };
 
-/// playlist コマンドのページ
+/// DB のプレイリスト一覧の読み込み状態
 #[derive(Default)]
-pub struct PagePlaylist;
+enum PlaylistsState {
+    #[default]
+    NotLoaded,
+    Loading,
+    Loaded(Vec<PlaylistNode>),
+    Failed(String),
+}
+
+/// playlist コマンドのページ
+pub struct PagePlaylist {
+    dry_run: bool,
+    config: Arc<Config>,
+    /// プレビューで試しに実行するときの設定の元にする
+    config_path: PathBuf,
+    db_pool: Arc<PgPool>,
+    /// 選択を保存するプロファイル
+    profile: String,
+    playlists: Arc<Mutex<PlaylistsState>>,
+    selection: PlaylistSelection,
+    /// 選択の読み込み・保存のエラー
+    selection_error: Option<String>,
+    preview: PlaylistPreview,
+}
+
+impl PagePlaylist {
+    pub fn new(
+        config: Arc<Config>,
+        config_path: PathBuf,
+        db_pool: Arc<PgPool>,
+        profile: String,
+    ) -> Self {
+        let (selection, selection_error) = match PlaylistSelection::load(&profile) {
+            Ok(selection) => (selection, None),
+            Err(e) => (PlaylistSelection::default(), Some(format!("{e:#}"))),
+        };
+
+        Self {
+            dry_run: false,
+            config,
+            config_path,
+            db_pool,
+            profile,
+            playlists: Arc::default(),
+            selection,
+            selection_error,
+            preview: PlaylistPreview::default(),
+        }
+    }
+
+    fn load_playlists(&self, ctx: egui::Context) {
+        let playlists = self.playlists.clone();
+        let db_pool = self.db_pool.clone();
+        *playlists.lock() = PlaylistsState::Loading;
+
+        tokio::spawn(async move {
+            let state = match playlist_selection::fetch_playlists(&db_pool).await {
+                Ok(list) => PlaylistsState::Loaded(list),
+                Err(e) => PlaylistsState::Failed(format!("{e:#}")),
+            };
+            *playlists.lock() = state;
+            ctx.request_repaint();
+        });
+    }
+
+    fn save_selection(&mut self) {
+        self.selection_error = self
+            .selection
+            .save(&self.profile)
+            .err()
+            .map(|e| format!("{e:#}"));
+    }
+
+    /// プレイリストの選択欄
+    fn show_selection(&mut self, ui: &mut Ui) {
+        if matches!(&*self.playlists.lock(), PlaylistsState::NotLoaded) {
+            self.load_playlists(ui.ctx().clone());
+        }
+
+        let mut changed = false;
+        let mut reload = false;
+        ui.horizontal(|ui| {
+            if ui.button("全て選択").clicked() && !self.selection.excluded.is_empty() {
+                self.selection.excluded.clear();
+                changed = true;
+            }
+            reload = ui.button("再読み込み").clicked();
+            ui.weak(format!("プロファイル {} に保存", self.profile));
+        });
+        if reload {
+            self.load_playlists(ui.ctx().clone());
+        }
+
+        match &*self.playlists.lock() {
+            PlaylistsState::NotLoaded | PlaylistsState::Loading => {
+                ui.spinner();
+            }
+            PlaylistsState::Failed(message) => {
+                ui.colored_label(egui::Color32::LIGHT_RED, message);
+            }
+            PlaylistsState::Loaded(playlists) => {
+                egui::ScrollArea::vertical()
+                    .id_salt("playlist_selection")
+                    .max_height(240.0)
+                    .show(ui, |ui| {
+                        changed |= show_children(ui, playlists, None, &mut self.selection, false);
+                    });
+            }
+        }
+
+        if changed {
+            self.save_selection();
+        }
+        if let Some(message) = &self.selection_error {
+            ui.colored_label(egui::Color32::LIGHT_RED, message);
+        }
+    }
+}
+
+/// `parent` 直下のプレイリストのチェックボックスを表示し、選択を変えたら true を返す
+///
+/// `parent_excluded` なら、親のフォルダごと除外されているので変更できない。
+fn show_children(
+    ui: &mut Ui,
+    playlists: &[PlaylistNode],
+    parent: Option<i32>,
+    selection: &mut PlaylistSelection,
+    parent_excluded: bool,
+) -> bool {
+    let mut changed = false;
+
+    for playlist in playlists.iter().filter(|p| p.parent_id == parent) {
+        let is_folder = playlists.iter().any(|p| p.parent_id == Some(playlist.id));
+        let excluded = parent_excluded || selection.excluded.contains(&playlist.id);
+
+        let mut checked = !excluded;
+        let label = if is_folder {
+            format!("📁 {}", playlist.name)
+        } else {
+            playlist.name.clone()
+        };
+        let enabled = !parent_excluded && (is_folder || playlist.save_dap);
+        let response = ui.add_enabled(enabled, egui::Checkbox::new(&mut checked, label));
+        let response = if !is_folder && !playlist.save_dap {
+            response.on_disabled_hover_text("DB で DAP に保存しない設定のプレイリストです")
+        } else {
+            response
+        };
+
+        if response.changed() {
+            if checked {
+                selection.include(playlists, playlist.id);
+            } else {
+                selection.excluded.insert(playlist.id);
+            }
+            changed = true;
+        }
+
+        if is_folder {
+            ui.indent(playlist.id, |ui| {
+                changed |= show_children(ui, playlists, Some(playlist.id), selection, excluded);
+            });
+        }
+    }
+
+    changed
+}
 
 impl CommandPage for PagePlaylist {
     fn page_type(&self) -> PageType {
@@ -21,14 +193,140 @@ impl CommandPage for PagePlaylist {
         "DAPのプレイリストを更新"
     }
 
-    fn show_form(&mut self, _ui: &mut Ui) {}
+    fn show_form(&mut self, ui: &mut Ui, _path_source: &LibraryPathSource) {
+        egui::CollapsingHeader::new("DAP に書き込むプレイリスト")
+            .default_open(!self.selection.excluded.is_empty())
+            .show(ui, |ui| self.show_selection(ui));
+        self.preview.show(
+            ui,
+            &self.config,
+            &self.config_path,
+            &self.db_pool,
+            &self.selection,
+        );
+
+        show_dry_run_checkbox(ui, &mut self.dry_run);
+    }
+
+    fn requires_dap(&self) -> bool {
+        // 見積もりのみなら DAP のファイルに触れない
+        !self.dry_run
+    }
+
+    fn job_summary(&self) -> String {
+        let mut summary = match self.selection.excluded.len() {
+            0 => "playlist".to_owned(),
+            count => format!("playlist ({count} 件除外)"),
+        };
+        let skipped = self.preview.skipped_files(&self.selection).len();
+        if skipped > 0 {
+            summary.push_str(&format!(" ({skipped} 件スキップ)"));
+        }
+        dry_run_summary(summary, self.dry_run)
+    }
+
+    fn create_planner(&self) -> Option<Planner> {
+        let selection = self.selection.clone();
+
+        Some(Box::new(move |config, db_pool| {
+            Box::pin(async move {
+                let excluded_ids = selection.resolve(&db_pool).await?;
+                dry_run::plan_playlist(&config, &db_pool, &excluded_ids).await
+            })
+        }))
+    }
 
-    fn run_command(&mut self, di_registry: Arc<DIRegistry>) -> JoinHandle<anyhow::Result<()>> {
+    fn create_runner(&self) -> CommandRunner {
+        playlist_runner(
+            self.dry_run,
+            self.selection.clone(),
+            self.preview.skipped_files(&self.selection),
+        )
+    }
+}
+
+/// playlist コマンドの実行処理を作成
+///
+/// `selection` で除外したプレイリストは DAP に書き込まない。
+/// `skipped` のプレイリストのファイル (プレイリストフォルダからの相対パス) は、
+/// DAP 上のファイルを実行前の内容のままにする。
+pub fn playlist_runner(
+    dry_run: bool,
+    selection: PlaylistSelection,
+    skipped: Vec<PathBuf>,
+) -> CommandRunner {
+    Box::new(move |di_registry| {
         tokio::spawn(async move {
-            let command = di_registry.command_playlist();
+            let config = di_registry.config();
             let db_pool = di_registry.db_pool();
+            let excluded_ids = selection.resolve(&db_pool).await?;
+            let mut plan = dry_run::plan_playlist(&config, &db_pool, &excluded_ids).await?;
+            let estimate = SpaceEstimate::new(&plan, &config.dap_lib);
+            if dry_run {
+                plan.output(di_registry.cui())?;
+                let cui = di_registry.cui();
+                let diffs = playlist_preview::preview(
+                    &config,
+                    di_registry.config_path(),
+                    &db_pool,
+                    &excluded_ids,
+                )
+                .await?;
+                for diff in diffs {
+                    let warning = if diff.empties() {
+                        " ⚠ 空になります"
+                    } else {
+                        ""
+                    };
+                    let skip = if skipped.contains(&diff.file) {
+                        " (スキップ)"
+                    } else {
+                        ""
+                    };
+                    cui.outln(format_args!(
+                        "[見積もり] {}: {}{warning}{skip}",
+                        diff.name(),
+                        diff.describe()
+                    ))?;
+                }
+                return cui.outln(format_args!("[見積もり] {}", estimate.describe()));
+            }
+            estimate.ensure_fits(di_registry.cui())?;
+            if !skipped.is_empty() {
+                plan.notes.push(format!(
+                    "{} 件のプレイリストのファイルは、書き込んだ後に実行前の内容へ戻します",
+                    skipped.len()
+                ));
+            }
 
-            command.run(&db_pool).await
+            let command = di_registry.command_playlist();
+            let skipped_files = SkippedPlaylists::take(
+                &config.dap_playlist,
+                &skipped,
+                di_registry.cui().console(),
+            )?;
+            let run = async {
+                let result = playlist_selection::run_with_exclusions(
+                    &db_pool,
+                    &excluded_ids,
+                    |pool| async move { command.run(&pool).await },
+                )
+                .await;
+                // 失敗した場合も、スキップしたプレイリストは元に戻す
+                let restore_result = skipped_files.restore();
+                result.and(restore_result)
+            };
+
+            di_registry
+                .journal()
+                .run_planned(
+                    "playlist",
+                    json!({ "excluded": selection.excluded, "skipped": skipped }),
+                    &plan,
+                    &db_pool,
+                    run,
+                )
+                .await
         })
-    }
+    })
 }
@@ -1,20 +1,23 @@
-use std::sync::Arc;
-
 use anyhow::anyhow;
 use eframe::egui::Ui;
 use murack_core_app::command::CommandRemoveArgs;
 use murack_core_domain::{EmptyStringError, NonEmptyString};
-use tokio::task::JoinHandle;
+use serde_json::json;
 
 use crate::legacy_commands::{
-    command_pages::{CommandPage, PageType},
-    di_registry::DIRegistry,
+    command_pages::{
+        CommandPage, CommandRunner, PageType, rehearsal_summary, show_rehearsal_checkbox,
+    },
+    dry_run::{self, Rehearsal},
+    library_path_source::LibraryPathSource,
+    path_input::PathInput,
 };
 
 /// remove コマンドのページ
 #[derive(Default)]
 pub struct PageRemove {
-    target_path: String,
+    target_path: PathInput,
+    dry_run: bool,
 }
 
 impl CommandPage for PageRemove {
@@ -26,26 +29,66 @@ impl CommandPage for PageRemove {
         "ライブラリから曲を削除"
     }
 
-    fn show_form(&mut self, ui: &mut Ui) {
-        ui.horizontal(|ui| {
-            ui.label("削除する曲のライブラリパス:");
-            ui.text_edit_singleline(&mut self.target_path);
-        });
+    fn show_form(&mut self, ui: &mut Ui, path_source: &LibraryPathSource) {
+        self.target_path
+            .show(ui, "削除する曲のライブラリパス:", path_source);
+        show_rehearsal_checkbox(ui, &mut self.dry_run);
+    }
+
+    fn requires_dap(&self) -> bool {
+        // 試しに実行するだけなら DAP のファイルに触れない
+        !self.dry_run
     }
 
-    fn run_command(&mut self, di_registry: Arc<DIRegistry>) -> JoinHandle<anyhow::Result<()>> {
-        let target_path = self.target_path.clone();
+    fn job_summary(&self) -> String {
+        rehearsal_summary(format!("remove {}", self.target_path.value()), self.dry_run)
+    }
+
+    fn create_runner(&self) -> CommandRunner {
+        let target_path = self.target_path.value();
+        let dry_run = self.dry_run;
+
+        Box::new(move |di_registry| {
+            tokio::spawn(async move {
+                let path: NonEmptyString = match target_path.clone().try_into() {
+                    Ok(s) => s,
+                    Err(EmptyStringError) => return Err(anyhow!("削除する曲のパスが未入力です")),
+                };
+
+                if dry_run {
+                    return dry_run::rehearse(
+                        &Rehearsal::Remove { path: target_path },
+                        &di_registry.config(),
+                        di_registry.config_path(),
+                        &di_registry.db_pool(),
+                        di_registry.cui(),
+                    )
+                    .await?
+                    .output(di_registry.cui());
+                }
 
-        tokio::spawn(async move {
-            let target_path: NonEmptyString = match target_path.try_into() {
-                Ok(s) => s,
-                Err(EmptyStringError) => return Err(anyhow!("削除する曲のパスが未入力です")),
-            };
+                let plan = dry_run::plan_remove(
+                    &di_registry.config(),
+                    &di_registry.db_pool(),
+                    &target_path,
+                )
+                .await?;
+                di_registry.backup_before("remove").await?;
 
-            let command = di_registry.command_remove(CommandRemoveArgs { path: target_path });
-            let db_pool = di_registry.db_pool();
+                let command = di_registry.command_remove(CommandRemoveArgs { path });
+                let db_pool = di_registry.db_pool();
 
-            command.run(&db_pool).await
+                di_registry
+                    .journal()
+                    .run_planned(
+                        "remove",
+                        json!({ "path": target_path }),
+                        &plan,
+                        &db_pool,
+                        command.run(&db_pool),
+                    )
+                    .await
+            })
         })
     }
 }
@@ -1,57 +1,301 @@
-use std::collections::VecDeque;
+use std::collections::{BTreeMap, VecDeque};
 
+use chrono::{DateTime, Local};
 use eframe::egui::{self};
 
-#[derive(Clone)]
-enum MessageType {
-    Log,
+use crate::legacy_commands::{job_queue::JobId, log_file::LogFile};
+
+/// 画面上に保持するメッセージの最大数 (ログファイルには全て残る)
+const MAX_MESSAGES: usize = 1000;
+
+/// メッセージの重要度
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LogLevel {
+    Debug,
+    Info,
+    Warn,
     Error,
 }
 
-impl MessageType {
+impl LogLevel {
+    pub const ALL: [LogLevel; 4] = [
+        LogLevel::Debug,
+        LogLevel::Info,
+        LogLevel::Warn,
+        LogLevel::Error,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            LogLevel::Debug => "DEBUG",
+            LogLevel::Info => "INFO",
+            LogLevel::Warn => "WARN",
+            LogLevel::Error => "ERROR",
+        }
+    }
+
     pub fn color(&self) -> egui::Color32 {
         match self {
-            MessageType::Log => egui::Color32::LIGHT_GRAY,
-            MessageType::Error => egui::Color32::LIGHT_RED,
+            LogLevel::Debug => egui::Color32::GRAY,
+            LogLevel::Info => egui::Color32::LIGHT_GRAY,
+            LogLevel::Warn => egui::Color32::LIGHT_YELLOW,
+            LogLevel::Error => egui::Color32::LIGHT_RED,
         }
     }
 }
 
 #[derive(Clone)]
 struct Message {
-    message_type: MessageType,
+    level: LogLevel,
+    timestamp: DateTime<Local>,
     text: String,
+    /// 出力元のジョブ
+    job_id: Option<JobId>,
+}
+
+impl Message {
+    /// ログファイルやクリップボード用の 1 行
+    fn formatted(&self) -> String {
+        let timestamp = self.timestamp.format("%Y-%m-%d %H:%M:%S%.3f");
+        match self.job_id {
+            Some(job_id) => format!(
+                "{timestamp} [{}] #{job_id} {}",
+                self.level.label(),
+                self.text
+            ),
+            None => format!("{timestamp} [{}] {}", self.level.label(), self.text),
+        }
+    }
 }
 
-#[derive(Default)]
 pub struct Console {
     messages: VecDeque<Message>,
+    /// 実行中のジョブ
+    current_job: Option<JobId>,
+    /// ジョブごとにまとめて表示するときの見出し
+    job_summaries: BTreeMap<JobId, String>,
+    log_file: Option<LogFile>,
+    /// 最後の行が改行で終わっていないか
+    line_open: bool,
+    /// 最後の書き込みが `\r` で終わったか (続きが `\n` でなければ行頭から上書きする)
+    pending_cr: bool,
+    /// 表示する最低の重要度
+    min_level: LogLevel,
+    search_text: String,
+    group_by_job: bool,
+}
+
+impl Default for Console {
+    fn default() -> Self {
+        Self {
+            messages: VecDeque::new(),
+            current_job: None,
+            job_summaries: BTreeMap::new(),
+            log_file: None,
+            line_open: false,
+            pending_cr: false,
+            min_level: LogLevel::Info,
+            search_text: String::new(),
+            group_by_job: false,
+        }
+    }
 }
 
 impl Console {
-    pub fn add_log(&mut self, text: String) {
-        self.messages.push_back(Message {
-            message_type: MessageType::Log,
-            text,
-        });
-        // Keep only last 1000 messages
-        if self.messages.len() > 1000 {
-            self.messages.pop_front();
+    /// データディレクトリのログファイルにも出力するコンソール
+    ///
+    /// ログファイルを開けなければ、画面表示のみで続ける。
+    pub fn with_log_file() -> Self {
+        match LogFile::open_default() {
+            Ok(log_file) => Self {
+                log_file: Some(log_file),
+                ..Self::default()
+            },
+            Err(e) => {
+                let mut console = Self::default();
+                console.add_error(format!(
+                    "ログファイルを開けないため、画面にのみ表示します: {e:#}"
+                ));
+                console
+            }
+        }
+    }
+
+    /// 1 行のメッセージを追加
+    ///
+    /// 改行で終わっていない行があれば、その続きとして追加してから行を閉じる。
+    pub fn add(&mut self, level: LogLevel, text: String) {
+        self.write(level, &text);
+        self.open_line(level);
+        self.close_line();
+    }
+
+    /// 改行せずにテキストを追加
+    ///
+    /// `\n` (`\r\n` も) で行を閉じ、それ以外の `\r` で現在の行を先頭から上書きする。
+    pub fn write(&mut self, level: LogLevel, text: &str) {
+        for (index, segment) in text.split('\n').enumerate() {
+            if index > 0 {
+                self.open_line(level);
+                self.close_line();
+            }
+            self.write_segment(level, segment);
         }
     }
 
+    fn write_segment(&mut self, level: LogLevel, segment: &str) {
+        if segment.is_empty() {
+            return;
+        }
+
+        // 前回の書き込みの末尾の \r の後が改行でなかったので、行頭に戻って上書き
+        if std::mem::take(&mut self.pending_cr) {
+            self.open_line(level).text.clear();
+        }
+
+        // 末尾の \r は、次の書き込みが \n で始まれば改行の一部なので、まだ上書きしない
+        let (segment, trailing_cr) = match segment.strip_suffix('\r') {
+            Some(segment) => (segment, true),
+            None => (segment, false),
+        };
+
+        let segment = match segment.rsplit_once('\r') {
+            Some((_, rest)) => {
+                // 行頭に戻って上書き (進捗表示など)
+                self.open_line(level).text.clear();
+                rest
+            }
+            None => segment,
+        };
+
+        if !segment.is_empty() {
+            self.open_line(level).text.push_str(segment);
+        }
+        self.pending_cr = trailing_cr;
+    }
+
+    /// 改行で終わっていない最後の行を取得
+    ///
+    /// 重要度や出力元のジョブが違う場合は、前の行を閉じて新しい行を始める。
+    fn open_line(&mut self, level: LogLevel) -> &mut Message {
+        let continues = self.line_open
+            && self
+                .messages
+                .back()
+                .is_some_and(|last| last.level == level && last.job_id == self.current_job);
+
+        if !continues {
+            self.close_line();
+            self.messages.push_back(Message {
+                level,
+                timestamp: Local::now(),
+                text: String::new(),
+                job_id: self.current_job,
+            });
+            if self.messages.len() > MAX_MESSAGES {
+                self.messages.pop_front();
+            }
+            self.line_open = true;
+        }
+
+        self.messages.back_mut().expect("line was just pushed")
+    }
+
+    /// 最後の行を閉じ、ログファイルに書き込む
+    fn close_line(&mut self) {
+        self.pending_cr = false;
+        if !self.line_open {
+            return;
+        }
+        self.line_open = false;
+
+        let (Some(log_file), Some(message)) = (&mut self.log_file, self.messages.back()) else {
+            return;
+        };
+        if let Err(e) = log_file.write_line(&message.formatted()) {
+            // 以降はログファイルに書かないので、このエラーの行は画面にのみ表示される
+            self.log_file = None;
+            self.add_error(format!(
+                "ログファイルに書き込めないため、以降は画面にのみ表示します: {e}"
+            ));
+        }
+    }
+
+    pub fn add_debug(&mut self, text: String) {
+        self.add(LogLevel::Debug, text);
+    }
+
+    pub fn add_log(&mut self, text: String) {
+        self.add(LogLevel::Info, text);
+    }
+
+    pub fn add_warn(&mut self, text: String) {
+        self.add(LogLevel::Warn, text);
+    }
+
     pub fn add_error(&mut self, text: String) {
-        self.messages.push_back(Message {
-            message_type: MessageType::Error,
-            text,
+        self.add(LogLevel::Error, text);
+    }
+
+    /// ジョブの開始を記録し、以降のメッセージをそのジョブの出力として扱う
+    pub fn begin_job(&mut self, job_id: JobId, summary: &str) {
+        self.close_line();
+        self.current_job = Some(job_id);
+        self.job_summaries.insert(job_id, summary.to_owned());
+        self.add_log(format!("===== #{job_id} {summary} ====="));
+    }
+
+    pub fn end_job(&mut self) {
+        self.close_line();
+        self.current_job = None;
+    }
+
+    fn is_visible(&self, message: &Message, job_filter: Option<JobId>, search: &str) -> bool {
+        message.level >= self.min_level
+            && (job_filter.is_none() || message.job_id == job_filter)
+            && (search.is_empty() || message.text.to_lowercase().contains(search))
+    }
+
+    /// コンソールを表示
+    ///
+    /// `job_filter` を指定すると、そのジョブの出力のみ表示する。
+    pub fn show(&mut self, ui: &mut egui::Ui, job_filter: Option<JobId>) {
+        let mut copy_clicked = false;
+
+        ui.horizontal(|ui| {
+            ui.label("レベル:");
+            egui::ComboBox::from_id_salt("console_min_level")
+                .selected_text(self.min_level.label())
+                .show_ui(ui, |ui| {
+                    for level in LogLevel::ALL {
+                        ui.selectable_value(&mut self.min_level, level, level.label());
+                    }
+                });
+
+            ui.label("検索:");
+            ui.text_edit_singleline(&mut self.search_text);
+
+            ui.checkbox(&mut self.group_by_job, "ジョブごとにまとめる");
+
+            copy_clicked = ui.button("コピー").clicked();
         });
-        // Keep only last 1000 messages
-        if self.messages.len() > 1000 {
-            self.messages.pop_front();
+
+        let search = self.search_text.trim().to_lowercase();
+        let visible: Vec<&Message> = self
+            .messages
+            .iter()
+            .filter(|m| self.is_visible(m, job_filter, &search))
+            .collect();
+
+        if copy_clicked {
+            let text = visible
+                .iter()
+                .map(|m| m.formatted())
+                .collect::<Vec<_>>()
+                .join("\n");
+            ui.ctx().copy_text(text);
         }
-    }
 
-    pub fn show(&self, ui: &mut egui::Ui) {
         egui::Frame::new()
             .fill(egui::Color32::from_rgb(34, 34, 34))
             .stroke(egui::Stroke::new(1.0, egui::Color32::WHITE))
@@ -61,11 +305,100 @@ impl Console {
                     .stick_to_bottom(true)
                     .auto_shrink([false, false])
                     .show(ui, |ui| {
-                        for message in self.messages.iter() {
-                            let color = message.message_type.color();
-                            ui.colored_label(color, &message.text);
+                        if self.group_by_job {
+                            self.show_grouped(ui, &visible);
+                        } else {
+                            for message in &visible {
+                                show_message(ui, message);
+                            }
                         }
                     });
             });
     }
+
+    /// ジョブごとに折りたたみ可能な見出しをつけて表示
+    fn show_grouped(&self, ui: &mut egui::Ui, visible: &[&Message]) {
+        let mut groups: Vec<(Option<JobId>, Vec<&Message>)> = vec![];
+        for &message in visible {
+            match groups.last_mut() {
+                Some((job_id, messages)) if *job_id == message.job_id => messages.push(message),
+                _ => groups.push((message.job_id, vec![message])),
+            }
+        }
+
+        for (index, (job_id, messages)) in groups.iter().enumerate() {
+            let title = match job_id {
+                Some(job_id) => {
+                    let summary = self.job_summaries.get(job_id).map_or("", String::as_str);
+                    format!("#{job_id} {summary} ({} 件)", messages.len())
+                }
+                None => format!("ジョブ外 ({} 件)", messages.len()),
+            };
+
+            egui::CollapsingHeader::new(title)
+                .id_salt(("console_group", job_id, index))
+                .default_open(true)
+                .show(ui, |ui| {
+                    for message in messages {
+                        show_message(ui, message);
+                    }
+                });
+        }
+    }
+}
+
+fn show_message(ui: &mut egui::Ui, message: &Message) {
+    let text = format!("{} {}", message.timestamp.format("%H:%M:%S"), message.text);
+    ui.colored_label(message.level.color(), text)
+        .on_hover_text(message.level.label());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn texts(console: &Console) -> Vec<&str> {
+        console
+            .messages
+            .iter()
+            .map(|message| message.text.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn crlf_closes_lines_without_erasing() {
+        let mut console = Console::default();
+        console.write(LogLevel::Info, "first\r\nsecond\r\n");
+
+        assert_eq!(texts(&console), ["first", "second"]);
+    }
+
+    #[test]
+    fn crlf_split_across_writes_closes_line() {
+        let mut console = Console::default();
+        console.write(LogLevel::Info, "first\r");
+        console.write(LogLevel::Info, "\nsecond");
+
+        assert_eq!(texts(&console), ["first", "second"]);
+    }
+
+    #[test]
+    fn bare_cr_overwrites_line() {
+        let mut console = Console::default();
+        console.write(LogLevel::Info, "10%\r20%");
+        assert_eq!(texts(&console), ["20%"]);
+
+        console.write(LogLevel::Info, "\r");
+        console.write(LogLevel::Info, "30%");
+        assert_eq!(texts(&console), ["30%"]);
+    }
+
+    #[test]
+    fn add_ignores_trailing_cr() {
+        let mut console = Console::default();
+        console.add_log("done\r".to_owned());
+        console.add_log("next".to_owned());
+
+        assert_eq!(texts(&console), ["done", "next"]);
+    }
 }
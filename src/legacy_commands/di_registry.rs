@@ -1,6 +1,8 @@
-use std::sync::Arc;
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
 
-use eframe::egui::mutex::Mutex;
 use murack_core_app::{
     Config,
     command::{
@@ -8,74 +10,123 @@ use murack_core_app::{
         CommandPlaylist, CommandRemove, CommandRemoveArgs, ResolveDapImpl, ResolveDataMatchImpl,
         ResolveExistanceImpl,
     },
+    cui::Cui,
 };
 use sqlx::PgPool;
 
-use crate::legacy_commands::{
-    console::Console,
-    egui_cui::{CommandState, EguiCui},
+use crate::{
+    backup::BackupStore,
+    legacy_commands::{
+        operation_journal::OperationJournal,
+        resolve_step::{CheckCuis, ResolveStepCui, StepCui},
+    },
 };
 
 /// DI の依存関係の解決
-pub struct DIRegistry {
-    cui: EguiCui,
+///
+/// GUI では `EguiCui`、CLI ではターミナル用の Cui を注入して使う。
+pub struct DIRegistry<C: Cui> {
+    cui: C,
     config: Arc<Config>,
+    /// `config` を読み込んだ設定ファイル
+    config_path: PathBuf,
     db_pool: Arc<PgPool>,
-    #[allow(dead_code)]
-    console: Arc<Mutex<Console>>,
+    journal: Arc<OperationJournal>,
+    /// 破壊的なコマンドの前の自動バックアップの保存先 (無効なら None)
+    auto_backup: Option<Arc<BackupStore>>,
 }
 
-impl DIRegistry {
+impl<C: Cui> DIRegistry<C> {
     pub fn new(
-        console: Arc<Mutex<Console>>,
-        command_state: Arc<Mutex<CommandState>>,
+        cui: C,
         config: Arc<Config>,
+        config_path: PathBuf,
         db_pool: Arc<PgPool>,
+        journal: Arc<OperationJournal>,
+        auto_backup: Option<Arc<BackupStore>>,
     ) -> Self {
         Self {
-            cui: EguiCui::new(console.clone(), command_state),
+            cui,
             config,
+            config_path,
             db_pool,
-            console,
+            journal,
+            auto_backup,
         }
     }
 
+    pub fn config(&self) -> Arc<Config> {
+        self.config.clone()
+    }
+
+    pub fn config_path(&self) -> &Path {
+        &self.config_path
+    }
+
     pub fn db_pool(&self) -> Arc<PgPool> {
         self.db_pool.clone()
     }
 
-    #[allow(dead_code)]
-    pub fn console(&self) -> Arc<Mutex<Console>> {
-        self.console.clone()
+    pub fn journal(&self) -> Arc<OperationJournal> {
+        self.journal.clone()
+    }
+
+    pub fn cui(&self) -> &C {
+        &self.cui
+    }
+
+    /// 自動バックアップが有効なら、`command` の実行前に DB をバックアップする
+    ///
+    /// 作成したバックアップのパスを出力する。バックアップに失敗したらコマンドも実行しない。
+    pub async fn backup_before(&self, command: &str) -> anyhow::Result<()> {
+        let Some(store) = &self.auto_backup else {
+            return Ok(());
+        };
+
+        let path = store
+            .create(&self.db_pool, &format!("before-{command}"))
+            .await?;
+        self.cui.outln(format_args!(
+            "DB をバックアップしました: {}",
+            path.display()
+        ))
     }
 
     // -----------------------------
     // Commands
 
-    pub fn command_add(&self, args: CommandAddArgs) -> TypeCommandAdd {
+    pub fn command_add(&self, args: CommandAddArgs) -> TypeCommandAdd<'_, '_, C> {
         CommandAdd::new(args, &self.config, &self.cui)
     }
 
-    pub fn command_check(&self, args: CommandCheckArgs) -> TypeCommandCheck {
+    /// 解決処理ごとの Cui を指定して check コマンドを作成
+    ///
+    /// 確認がどの解決処理からのものかを Cui が区別できるように、解決処理ごとに別の Cui を渡す。
+    /// 解決方針に従って自動で答える `PolicyCui` などを使う場合は、それを包んだ `CheckCuis` を渡す。
+    pub fn command_check_with_cui<'a, D: StepCui>(
+        &'a self,
+        args: CommandCheckArgs,
+        cuis: &'a CheckCuis<'a, D>,
+    ) -> TypeCommandCheck<'a, 'a, D> {
         CommandCheck::new(
             args,
             &self.config,
-            ResolveExistanceImpl::new(&self.config, &self.cui),
-            ResolveDataMatchImpl::new(&self.config, &self.cui),
-            ResolveDapImpl::new(&self.config, &self.cui),
-            &self.cui,
+            ResolveExistanceImpl::new(&self.config, &cuis.existance),
+            ResolveDataMatchImpl::new(&self.config, &cuis.data_match),
+            ResolveDapImpl::new(&self.config, &cuis.dap),
+            cuis.inner,
         )
     }
 
-    pub fn command_move(&self, args: CommandMoveArgs) -> TypeCommandMove {
+    pub fn command_move(&self, args: CommandMoveArgs) -> TypeCommandMove<'_> {
         CommandMove::new(args, &self.config)
     }
 
-    pub fn command_remove(&self, args: CommandRemoveArgs) -> TypeCommandRemove {
+    pub fn command_remove(&self, args: CommandRemoveArgs) -> TypeCommandRemove<'_, '_, C> {
         CommandRemove::new(args, &self.config, &self.cui)
     }
 
-    pub fn command_playlist(&self) -> TypeCommandPlaylist {
+    pub fn command_playlist(&self) -> TypeCommandPlaylist<'_, '_, C> {
         CommandPlaylist {
             config: &self.config,
             cui: &self.cui,
@@ -83,15 +134,15 @@ impl DIRegistry {
     }
 }
 
-pub type TypeCommandAdd<'config, 'cui> = CommandAdd<'config, 'cui, EguiCui>;
-pub type TypeCommandCheck<'config, 'cui> = CommandCheck<
+pub type TypeCommandAdd<'config, 'cui, C> = CommandAdd<'config, 'cui, C>;
+pub type TypeCommandCheck<'config, 'cui, C> = CommandCheck<
     'config,
     'cui,
-    EguiCui,
-    ResolveExistanceImpl<'config, 'cui, EguiCui>,
-    ResolveDataMatchImpl<'config, 'cui, EguiCui>,
-    ResolveDapImpl<'config, 'cui, EguiCui>,
+    C,
+    ResolveExistanceImpl<'config, 'cui, ResolveStepCui<'cui, C>>,
+    ResolveDataMatchImpl<'config, 'cui, ResolveStepCui<'cui, C>>,
+    ResolveDapImpl<'config, 'cui, ResolveStepCui<'cui, C>>,
 >;
 pub type TypeCommandMove<'config> = CommandMove<'config>;
-pub type TypeCommandRemove<'config, 'cui> = CommandRemove<'config, 'cui, EguiCui>;
-pub type TypeCommandPlaylist<'config, 'cui> = CommandPlaylist<'config, 'cui, EguiCui>;
+pub type TypeCommandRemove<'config, 'cui, C> = CommandRemove<'config, 'cui, C>;
+pub type TypeCommandPlaylist<'config, 'cui, C> = CommandPlaylist<'config, 'cui, C>;
@@ -0,0 +1,942 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    fmt::{self, Arguments},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result, anyhow};
+use eframe::egui::mutex::Mutex;
+use murack_core_app::{
+    Config,
+    command::{
+        CommandAdd, CommandAddArgs, CommandMove, CommandMoveArgs, CommandRemove, CommandRemoveArgs,
+    },
+    cui::Cui,
+};
+use murack_core_domain::{EmptyStringError, NonEmptyString};
+use sqlx::PgPool;
+
+use crate::{
+    dap_device,
+    legacy_commands::sandbox::{self, MirrorKind, SandboxDb},
+};
+
+/// 対象とする音声ファイルの拡張子
+pub const AUDIO_EXTENSIONS: [&str; 3] = ["flac", "mp3", "m4a"];
+
+/// 書き込み後も DAP に残しておく空き容量 (ファイルシステムの管理領域などの分)
+const SPACE_MARGIN_BYTES: u64 = 64 * 1024 * 1024;
+
+/// コマンドが実行する予定の変更 1 件
+#[derive(Debug, Clone)]
+pub enum PlannedAction {
+    FileCopy {
+        from: PathBuf,
+        to: PathBuf,
+        bytes: u64,
+    },
+    FileMove {
+        from: PathBuf,
+        to: PathBuf,
+    },
+    FileDelete {
+        path: PathBuf,
+    },
+    DbInsert {
+        path: String,
+    },
+    DbMove {
+        path: String,
+        new_path: String,
+    },
+    DbDelete {
+        path: String,
+    },
+    PlaylistWrite {
+        name: String,
+    },
+}
+
+impl fmt::Display for PlannedAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PlannedAction::FileCopy { from, to, bytes } => write!(
+                f,
+                "ファイルコピー: {} → {} ({})",
+                from.display(),
+                to.display(),
+                format_bytes(*bytes)
+            ),
+            PlannedAction::FileMove { from, to } => {
+                write!(f, "ファイル移動: {} → {}", from.display(), to.display())
+            }
+            PlannedAction::FileDelete { path } => write!(f, "ファイル削除: {}", path.display()),
+            PlannedAction::DbInsert { path } => write!(f, "DB追加: {path}"),
+            PlannedAction::DbMove { path, new_path } => {
+                write!(f, "DB更新: {path} (パスを {new_path} に変更)")
+            }
+            PlannedAction::DbDelete { path } => write!(f, "DB削除: {path}"),
+            PlannedAction::PlaylistWrite { name } => write!(f, "プレイリスト書き込み: {name}"),
+        }
+    }
+}
+
+impl PlannedAction {
+    /// ファイルの変更が行われたことを確認できるか (DB の変更なら false)
+    fn file_change_confirmed(&self, succeeded: bool) -> bool {
+        match self {
+            PlannedAction::FileCopy { to, .. } => to.exists(),
+            PlannedAction::FileMove { from, to } => to.exists() && !from.exists(),
+            PlannedAction::FileDelete { path } => !path.exists(),
+            PlannedAction::PlaylistWrite { .. } => succeeded,
+            PlannedAction::DbInsert { .. }
+            | PlannedAction::DbMove { .. }
+            | PlannedAction::DbDelete { .. } => false,
+        }
+    }
+}
+
+/// コマンドの実行予定
+///
+/// 実行前の DB とファイルから推定したものか、`rehearse` でコマンドを試しに実行して記録したもの。
+#[derive(Debug, Clone, Default)]
+pub struct DryRunPlan {
+    pub actions: Vec<PlannedAction>,
+    /// 変更はないが利用者に伝えるべきこと
+    pub notes: Vec<String>,
+    /// 試しに実行して記録したものか
+    pub rehearsed: bool,
+}
+
+impl DryRunPlan {
+    /// DAP などへコピーする予定のバイト数の合計
+    pub fn copy_bytes(&self) -> u64 {
+        self.actions
+            .iter()
+            .map(|action| match action {
+                PlannedAction::FileCopy { bytes, .. } => *bytes,
+                _ => 0,
+            })
+            .sum()
+    }
+
+    /// `root` 以下に新たに書き込むバイト数
+    ///
+    /// 上書きするファイルは、既存のファイルとの差分だけ数える。
+    pub fn bytes_to_write(&self, root: &Path) -> u64 {
+        self.actions
+            .iter()
+            .map(|action| match action {
+                PlannedAction::FileCopy { to, bytes, .. } if to.starts_with(root) => {
+                    let existing = std::fs::metadata(to).map(|m| m.len()).unwrap_or(0);
+                    bytes.saturating_sub(existing)
+                }
+                _ => 0,
+            })
+            .sum()
+    }
+
+    /// 実行予定のうち、コマンドの実行後に行われたことを確認できた変更
+    ///
+    /// 操作履歴には推定ではなく、実際に起きた変更だけを記録する。
+    /// プレイリストの書き込みは名前からファイルを特定できないので、成功した場合のみ含める。
+    /// DB の変更を確認できなければエラーを返す。
+    pub async fn confirmed_effects(
+        &self,
+        db_pool: &PgPool,
+        succeeded: bool,
+    ) -> Result<Vec<String>> {
+        let db_paths: Vec<&str> = self
+            .actions
+            .iter()
+            .filter_map(|action| match action {
+                PlannedAction::DbInsert { path } | PlannedAction::DbDelete { path } => {
+                    Some(path.as_str())
+                }
+                PlannedAction::DbMove { new_path, .. } => Some(new_path.as_str()),
+                _ => None,
+            })
+            .collect();
+        let registered: Vec<String> = if db_paths.is_empty() {
+            vec![]
+        } else {
+            sqlx::query_scalar("SELECT path FROM tracks WHERE path = ANY($1)")
+                .bind(&db_paths)
+                .fetch_all(db_pool)
+                .await?
+        };
+        let is_registered = |path: &String| registered.contains(path);
+
+        Ok(self
+            .actions
+            .iter()
+            .filter(|action| match action {
+                PlannedAction::DbInsert { path } => is_registered(path),
+                PlannedAction::DbMove { new_path, .. } => is_registered(new_path),
+                PlannedAction::DbDelete { path } => !is_registered(path),
+                _ => action.file_change_confirmed(succeeded),
+            })
+            .map(ToString::to_string)
+            .collect())
+    }
+
+    /// 実行予定のうち、行われたことを確認できたファイルの変更
+    pub fn confirmed_file_effects(&self, succeeded: bool) -> Vec<String> {
+        self.actions
+            .iter()
+            .filter(|action| action.file_change_confirmed(succeeded))
+            .map(ToString::to_string)
+            .collect()
+    }
+
+    /// 実行予定を一覧で出力
+    pub fn output(&self, cui: &impl Cui) -> Result<()> {
+        let tag = self.tag();
+        if self.rehearsed {
+            cui.outln(format_args!(
+                "{tag} コマンドを試しに実行して記録した変更です (DB とファイルの変更は全て取り消しました)"
+            ))?;
+        } else {
+            cui.outln(format_args!(
+                "{tag} 実行前の DB とファイルから推定した変更です (実行はしていません。実際の結果とは異なる場合があります)"
+            ))?;
+        }
+
+        for action in &self.actions {
+            cui.outln(format_args!("{tag} {action}"))?;
+        }
+        for note in &self.notes {
+            cui.outln(format_args!("{tag} ※ {note}"))?;
+        }
+
+        let count = |f: fn(&PlannedAction) -> bool| self.actions.iter().filter(|a| f(a)).count();
+        cui.outln(format_args!(
+            "{tag} ファイルコピー {} 件 ({}), ファイル移動 {} 件, ファイル削除 {} 件, DB追加 {} 件, DB更新 {} 件, DB削除 {} 件, プレイリスト {} 件",
+            count(|a| matches!(a, PlannedAction::FileCopy { .. })),
+            format_bytes(self.copy_bytes()),
+            count(|a| matches!(a, PlannedAction::FileMove { .. })),
+            count(|a| matches!(a, PlannedAction::FileDelete { .. })),
+            count(|a| matches!(a, PlannedAction::DbInsert { .. })),
+            count(|a| matches!(a, PlannedAction::DbMove { .. })),
+            count(|a| matches!(a, PlannedAction::DbDelete { .. })),
+            count(|a| matches!(a, PlannedAction::PlaylistWrite { .. })),
+        ))
+    }
+
+    /// 出力する行の先頭に付ける印
+    pub fn tag(&self) -> &'static str {
+        if self.rehearsed {
+            "[試行]"
+        } else {
+            "[見積もり]"
+        }
+    }
+}
+
+/// DAP への書き込み量と空き容量の見積もり
+#[derive(Debug, Clone, Copy)]
+pub struct SpaceEstimate {
+    pub required_bytes: u64,
+    /// 空き容量 (取得できなければ None)
+    pub free_bytes: Option<u64>,
+}
+
+impl SpaceEstimate {
+    pub fn new(plan: &DryRunPlan, dap_root: &Path) -> Self {
+        Self {
+            required_bytes: plan.bytes_to_write(dap_root),
+            free_bytes: dap_device::disk_space(dap_root).map(|space| space.free_bytes),
+        }
+    }
+
+    /// 書き込んでも空き容量が残るか (空き容量が分からなければ収まるものとする)
+    ///
+    /// 新たに書き込むものがなければ、空きがほとんどなくても収まるものとする。
+    pub fn fits(&self) -> bool {
+        self.required_bytes == 0
+            || self
+                .free_bytes
+                .is_none_or(|free| self.required_bytes + SPACE_MARGIN_BYTES <= free)
+    }
+
+    /// 収まるが、書き込み後の空きが今の 1 割を切るか
+    pub fn is_tight(&self) -> bool {
+        self.fits()
+            && self
+                .free_bytes
+                .is_some_and(|free| self.required_bytes > free / 10 * 9)
+    }
+
+    pub fn describe(&self) -> String {
+        match self.free_bytes {
+            Some(free) => format!(
+                "DAP への書き込み {} / 空き {}",
+                format_bytes(self.required_bytes),
+                format_bytes(free)
+            ),
+            None => format!(
+                "DAP への書き込み {} (空き容量は不明)",
+                format_bytes(self.required_bytes)
+            ),
+        }
+    }
+
+    /// 収まらなければエラー、空きが少なくなるなら警告を出力する
+    ///
+    /// コピーの途中で容量が尽きて DAP が中途半端な状態にならないよう、実行前に呼ぶ。
+    pub fn ensure_fits(&self, cui: &impl Cui) -> Result<()> {
+        if !self.fits() {
+            return Err(anyhow!("DAP の空き容量が足りません ({})", self.describe()));
+        }
+        if self.is_tight() {
+            cui.outln(format_args!(
+                "警告: 書き込み後の DAP の空き容量が少なくなります ({})",
+                self.describe()
+            ))?;
+        }
+        Ok(())
+    }
+}
+
+/// add コマンドの実行予定
+pub async fn plan_add(config: &Config, db_pool: &PgPool, path: &str) -> Result<DryRunPlan> {
+    let mut plan = DryRunPlan::default();
+
+    let track_paths = list_audio_files(&config.pc_lib, path)?;
+    if track_paths.is_empty() {
+        plan.notes
+            .push(format!("{path} に追加できる曲ファイルがありません"));
+    }
+
+    let registered = fetch_track_paths(db_pool, path).await?;
+
+    for track_path in track_paths {
+        if registered.contains(&track_path) {
+            plan.notes.push(format!("{track_path} は登録済みです"));
+            continue;
+        }
+
+        let from = config.pc_lib.join(&track_path);
+        let bytes = std::fs::metadata(&from).map(|m| m.len()).unwrap_or(0);
+
+        plan.actions.push(PlannedAction::DbInsert {
+            path: track_path.clone(),
+        });
+        plan.actions.push(PlannedAction::FileCopy {
+            from,
+            to: config.dap_lib.join(&track_path),
+            bytes,
+        });
+    }
+
+    Ok(plan)
+}
+
+/// move コマンドの実行予定
+pub async fn plan_move(
+    config: &Config,
+    db_pool: &PgPool,
+    src_path: &str,
+    dest_path: &str,
+) -> Result<DryRunPlan> {
+    let mut plan = DryRunPlan::default();
+
+    for lib_root in [&config.pc_lib, &config.dap_lib] {
+        let from = lib_root.join(src_path);
+        if from.exists() {
+            plan.actions.push(PlannedAction::FileMove {
+                from,
+                to: lib_root.join(dest_path),
+            });
+        }
+    }
+
+    let registered = fetch_track_paths(db_pool, src_path).await?;
+    if registered.is_empty() {
+        plan.notes
+            .push(format!("{src_path} の曲は DB に登録されていません"));
+    }
+
+    for track_path in registered {
+        let new_path = format!("{dest_path}{}", &track_path[src_path.len()..]);
+        plan.actions.push(PlannedAction::DbMove {
+            path: track_path,
+            new_path,
+        });
+    }
+
+    Ok(plan)
+}
+
+/// remove コマンドの実行予定
+pub async fn plan_remove(config: &Config, db_pool: &PgPool, path: &str) -> Result<DryRunPlan> {
+    let mut plan = DryRunPlan::default();
+
+    for lib_root in [&config.pc_lib, &config.dap_lib] {
+        let target = lib_root.join(path);
+        if target.exists() {
+            plan.actions
+                .push(PlannedAction::FileDelete { path: target });
+        }
+    }
+
+    let registered = fetch_track_paths(db_pool, path).await?;
+    if registered.is_empty() {
+        plan.notes
+            .push(format!("{path} の曲は DB に登録されていません"));
+    }
+
+    for track_path in registered {
+        plan.actions
+            .push(PlannedAction::DbDelete { path: track_path });
+    }
+
+    Ok(plan)
+}
+
+/// 試しに実行するコマンド
+pub enum Rehearsal {
+    Add { path: String },
+    Move { src_path: String, dest_path: String },
+    Remove { path: String },
+}
+
+impl Rehearsal {
+    /// コマンドがファイルを変更し得るライブラリパス
+    fn targets(&self) -> Vec<&str> {
+        match self {
+            Rehearsal::Add { path } | Rehearsal::Remove { path } => vec![path],
+            Rehearsal::Move {
+                src_path,
+                dest_path,
+            } => vec![src_path, dest_path],
+        }
+    }
+
+    async fn run(&self, config: &Config, db_pool: &PgPool, cui: &impl Cui) -> Result<()> {
+        match self {
+            Rehearsal::Add { path } => {
+                let args = CommandAddArgs {
+                    path: non_empty(path)?,
+                };
+                CommandAdd::new(args, config, cui).run(db_pool).await
+            }
+            Rehearsal::Move {
+                src_path,
+                dest_path,
+            } => {
+                let args = CommandMoveArgs {
+                    src_path: non_empty(src_path)?,
+                    dest_path: non_empty(dest_path)?,
+                };
+                CommandMove::new(args, config).run(db_pool).await
+            }
+            Rehearsal::Remove { path } => {
+                let args = CommandRemoveArgs {
+                    path: non_empty(path)?,
+                };
+                CommandRemove::new(args, config, cui).run(db_pool).await
+            }
+        }
+    }
+}
+
+fn non_empty(path: &str) -> Result<NonEmptyString> {
+    path.to_owned()
+        .try_into()
+        .map_err(|EmptyStringError| anyhow!("パスが未入力です"))
+}
+
+/// コマンドを試しに実行し、行われた変更を記録する
+///
+/// DB は最後に変更を取り消す接続で、ファイルは対象のパスだけを一時フォルダに写した設定で実行するので、
+/// 実際の DB とファイルは変わらない。
+/// PC 側のファイルは内容を読めるようリンクで、コピー先になる DAP 側のファイルは代わりのファイルで写す。
+/// コマンドの確認には `cui` で答えてもらう。
+pub async fn rehearse(
+    rehearsal: &Rehearsal,
+    config: &Config,
+    config_path: &Path,
+    db_pool: &PgPool,
+    cui: &(impl Cui + Sync),
+) -> Result<DryRunPlan> {
+    let scratch = tempfile::Builder::new()
+        .prefix("murack-sync-dry-run-")
+        .tempdir()
+        .context("Failed to create a temporary directory")?;
+    let scratch_config = sandbox::scratch_config(config_path, scratch.path())?;
+
+    let roots = [
+        ScratchRoot {
+            real: &config.pc_lib,
+            scratch: &scratch_config.pc_lib,
+            kind: MirrorKind::Link,
+        },
+        ScratchRoot {
+            real: &config.dap_lib,
+            scratch: &scratch_config.dap_lib,
+            kind: MirrorKind::Placeholder,
+        },
+    ];
+    for root in &roots {
+        for target in rehearsal.targets() {
+            sandbox::mirror(root.real, root.scratch, target, root.kind)?;
+        }
+    }
+
+    let sandbox = SandboxDb::connect(db_pool, &[]).await?;
+    let result = rehearse_in(rehearsal, &scratch_config, &roots, &sandbox, cui).await;
+    sandbox.rollback().await?;
+    result
+}
+
+/// 一時フォルダに写したライブラリ
+struct ScratchRoot<'a> {
+    real: &'a Path,
+    scratch: &'a Path,
+    kind: MirrorKind,
+}
+
+async fn rehearse_in(
+    rehearsal: &Rehearsal,
+    scratch_config: &Config,
+    roots: &[ScratchRoot<'_>],
+    sandbox: &SandboxDb,
+    cui: &(impl Cui + Sync),
+) -> Result<DryRunPlan> {
+    let tracks_before = fetch_track_ids(sandbox.pool()).await?;
+    let files_before: Vec<_> = roots
+        .iter()
+        .map(|root| snapshot_files(root.scratch, &rehearsal.targets()))
+        .collect::<Result<_>>()?;
+
+    let recording = RecordingCui::new(cui);
+    rehearsal
+        .run(scratch_config, sandbox.pool(), &recording)
+        .await
+        .context("試しに実行したコマンドが失敗しました")?;
+
+    let tracks_after = fetch_track_ids(sandbox.pool()).await?;
+    let mut plan = DryRunPlan {
+        actions: track_changes(&tracks_before, &tracks_after),
+        notes: recording.into_errors(),
+        rehearsed: true,
+    };
+    for (root, before) in roots.iter().zip(&files_before) {
+        let after = snapshot_files(root.scratch, &rehearsal.targets())?;
+        // コマンドがコピーするのは PC 側の同じパスのファイル
+        plan.actions
+            .extend(file_changes(root.real, roots[0].real, before, &after));
+    }
+
+    if plan.actions.is_empty() {
+        plan.notes.push("変更はありません".to_owned());
+    }
+    Ok(plan)
+}
+
+/// DB の曲の ID とパス
+async fn fetch_track_ids(db_pool: &PgPool) -> Result<BTreeMap<i64, String>> {
+    let rows: Vec<(i64, String)> = sqlx::query_as("SELECT id::bigint, path FROM tracks")
+        .fetch_all(db_pool)
+        .await?;
+    Ok(rows.into_iter().collect())
+}
+
+/// 実行前後の DB の曲から、追加・パスの変更・削除を求める
+fn track_changes(
+    before: &BTreeMap<i64, String>,
+    after: &BTreeMap<i64, String>,
+) -> Vec<PlannedAction> {
+    let mut actions = vec![];
+    for (id, path) in after {
+        match before.get(id) {
+            None => actions.push(PlannedAction::DbInsert { path: path.clone() }),
+            Some(old_path) if old_path != path => actions.push(PlannedAction::DbMove {
+                path: old_path.clone(),
+                new_path: path.clone(),
+            }),
+            Some(_) => {}
+        }
+    }
+    for (id, path) in before {
+        if !after.contains_key(id) {
+            actions.push(PlannedAction::DbDelete { path: path.clone() });
+        }
+    }
+    actions
+}
+
+/// 一時フォルダのファイル 1 つ
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct ScratchFile {
+    /// 写した元のファイル (コマンドが書き込んだファイルなら None)
+    mirrored_from: Option<PathBuf>,
+    bytes: u64,
+}
+
+/// `scratch_root` 以下の `targets` にあるファイル (`scratch_root` からの相対パス → ファイル)
+fn snapshot_files(scratch_root: &Path, targets: &[&str]) -> Result<BTreeMap<PathBuf, ScratchFile>> {
+    let mut files = BTreeMap::new();
+    for target in targets {
+        collect_scratch_files(scratch_root, &scratch_root.join(target), &mut files)?;
+    }
+    Ok(files)
+}
+
+fn collect_scratch_files(
+    scratch_root: &Path,
+    path: &Path,
+    files: &mut BTreeMap<PathBuf, ScratchFile>,
+) -> Result<()> {
+    let Ok(metadata) = std::fs::symlink_metadata(path) else {
+        return Ok(());
+    };
+    if metadata.is_dir() {
+        for entry in std::fs::read_dir(path)? {
+            collect_scratch_files(scratch_root, &entry?.path(), files)?;
+        }
+        return Ok(());
+    }
+
+    let Ok(relative) = path.strip_prefix(scratch_root) else {
+        return Ok(());
+    };
+    let mirrored_from = sandbox::mirrored_from(path);
+    let bytes = match &mirrored_from {
+        Some(real) => std::fs::metadata(real).map(|m| m.len()).unwrap_or(0),
+        None => metadata.len(),
+    };
+    files.insert(
+        relative.to_path_buf(),
+        ScratchFile {
+            mirrored_from,
+            bytes,
+        },
+    );
+    Ok(())
+}
+
+/// 実行前後の一時フォルダのファイルから、`real_root` で行われるファイルの変更を求める
+///
+/// 写した元が同じファイルは、パスが変わっていれば移動とみなす。
+fn file_changes(
+    real_root: &Path,
+    copy_source_root: &Path,
+    before: &BTreeMap<PathBuf, ScratchFile>,
+    after: &BTreeMap<PathBuf, ScratchFile>,
+) -> Vec<PlannedAction> {
+    let mut actions = vec![];
+    for (relative, file) in after {
+        if before.get(relative) == Some(file) {
+            continue;
+        }
+        let to = real_root.join(relative);
+        match &file.mirrored_from {
+            Some(from) if *from != to => actions.push(PlannedAction::FileMove {
+                from: from.clone(),
+                to,
+            }),
+            Some(_) => {}
+            None => actions.push(PlannedAction::FileCopy {
+                from: copy_source_root.join(relative),
+                to,
+                bytes: file.bytes,
+            }),
+        }
+    }
+
+    let remaining: HashSet<&PathBuf> = after
+        .values()
+        .filter_map(|file| file.mirrored_from.as_ref())
+        .collect();
+    actions.extend(
+        before
+            .values()
+            .filter_map(|file| file.mirrored_from.as_ref())
+            .filter(|from| !remaining.contains(from))
+            .map(|from| PlannedAction::FileDelete { path: from.clone() }),
+    );
+    actions
+}
+
+/// 試しに実行したコマンドの出力を記録する Cui
+///
+/// 通常の出力は捨て、エラー出力だけを残す。確認は元の Cui に尋ねる。
+struct RecordingCui<'a, C: Cui> {
+    inner: &'a C,
+    errors: Mutex<Vec<String>>,
+}
+
+impl<'a, C: Cui> RecordingCui<'a, C> {
+    fn new(inner: &'a C) -> Self {
+        Self {
+            inner,
+            errors: Mutex::new(vec![]),
+        }
+    }
+
+    fn into_errors(self) -> Vec<String> {
+        std::mem::take(&mut *self.errors.lock())
+    }
+
+    fn record_error(&self, args: Arguments) {
+        let text = args.to_string();
+        if !text.trim().is_empty() {
+            self.errors.lock().push(text.trim().to_owned());
+        }
+    }
+}
+
+impl<C: Cui> Cui for RecordingCui<'_, C> {
+    fn out(&self, _args: Arguments) -> Result<()> {
+        Ok(())
+    }
+
+    fn outln(&self, _args: Arguments) -> Result<()> {
+        Ok(())
+    }
+
+    fn err(&self, args: Arguments) -> Result<()> {
+        self.record_error(args);
+        Ok(())
+    }
+
+    fn errln(&self, args: Arguments) -> Result<()> {
+        self.record_error(args);
+        Ok(())
+    }
+
+    fn input_case(&self, cases: &[char], message: &str) -> Result<char> {
+        self.inner.input_case(cases, message)
+    }
+}
+
+/// playlist コマンドの実行予定
+pub async fn plan_playlist(
+    config: &Config,
+    db_pool: &PgPool,
+    excluded_ids: &[i32],
+) -> Result<DryRunPlan> {
+    let mut plan = DryRunPlan::default();
+
+    let names: Vec<String> = sqlx::query_scalar(
+        "SELECT name FROM playlists WHERE save_dap AND NOT (id = ANY($1)) ORDER BY parent_id NULLS FIRST, in_folder_order",
+    )
+    .bind(excluded_ids)
+    .fetch_all(db_pool)
+    .await?;
+
+    for name in names {
+        plan.actions.push(PlannedAction::PlaylistWrite { name });
+    }
+
+    // プレイリストの曲のうち、まだ DAP にないものはコピーされる
+    let track_paths: Vec<String> = sqlx::query_scalar(
+        "SELECT DISTINCT t.path FROM tracks t JOIN playlist_tracks pt ON pt.track_id = t.id JOIN playlists p ON p.id = pt.playlist_id WHERE p.save_dap AND NOT (p.id = ANY($1)) ORDER BY t.path",
+    )
+    .bind(excluded_ids)
+    .fetch_all(db_pool)
+    .await?;
+
+    for track_path in track_paths {
+        let to = config.dap_lib.join(&track_path);
+        if to.exists() {
+            continue;
+        }
+
+        let from = config.pc_lib.join(&track_path);
+        let bytes = std::fs::metadata(&from).map(|m| m.len()).unwrap_or(0);
+        plan.actions
+            .push(PlannedAction::FileCopy { from, to, bytes });
+    }
+
+    plan.notes.push(format!(
+        "プレイリストは {} に書き込まれます",
+        config.dap_playlist.display()
+    ));
+    plan.notes
+        .push("コピーする曲は、DB に保存されているプレイリストの曲から見積もります".to_owned());
+    if !excluded_ids.is_empty() {
+        plan.notes.push(format!(
+            "{} 件のプレイリストは選択により書き込みません",
+            excluded_ids.len()
+        ));
+    }
+
+    Ok(plan)
+}
+
+/// 指定したライブラリパス (ファイルまたはディレクトリ) 以下の、DB 登録済みの曲パス
+///
+/// 空のパスならライブラリ全体。
+pub async fn fetch_track_paths(db_pool: &PgPool, path: &str) -> Result<Vec<String>> {
+    let dir_pattern = if path.is_empty() {
+        "%".to_owned()
+    } else {
+        format!("{}/%", escape_like(path))
+    };
+
+    let paths =
+        sqlx::query_scalar("SELECT path FROM tracks WHERE path = $1 OR path LIKE $2 ORDER BY path")
+            .bind(path)
+            .bind(dir_pattern)
+            .fetch_all(db_pool)
+            .await?;
+
+    Ok(paths)
+}
+
+fn escape_like(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
+
+/// ライブラリ内の指定パス以下の音声ファイルを、ライブラリパスで列挙
+pub fn list_audio_files(lib_root: &Path, path: &str) -> Result<Vec<String>> {
+    let mut paths = vec![];
+    collect_audio_files(lib_root, &lib_root.join(path), &mut paths)?;
+    paths.sort();
+
+    Ok(paths)
+}
+
+fn collect_audio_files(lib_root: &Path, target: &Path, paths: &mut Vec<String>) -> Result<()> {
+    if target.is_dir() {
+        for entry in std::fs::read_dir(target)? {
+            collect_audio_files(lib_root, &entry?.path(), paths)?;
+        }
+        return Ok(());
+    }
+
+    let is_audio = target
+        .extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| AUDIO_EXTENSIONS.contains(&e.to_lowercase().as_str()));
+    if !is_audio {
+        return Ok(());
+    }
+
+    if let Ok(relative) = target.strip_prefix(lib_root) {
+        // ライブラリパスは OS によらず / 区切り
+        let components: Vec<_> = relative
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy())
+            .collect();
+        paths.push(components.join("/"));
+    }
+
+    Ok(())
+}
+
+/// バイト数の表示用文字列
+pub fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KB", "MB", "GB", "TB"];
+
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+
+    if unit == 0 {
+        format!("{bytes} B")
+    } else {
+        format!("{value:.1} {}", UNITS[unit])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nothing_to_write_fits_a_nearly_full_dap() {
+        let estimate = SpaceEstimate {
+            required_bytes: 0,
+            free_bytes: Some(1024),
+        };
+
+        assert!(estimate.fits());
+        assert!(!estimate.is_tight());
+    }
+
+    #[test]
+    fn track_changes_follow_ids() {
+        let before = BTreeMap::from([(1, "a/1.flac".to_owned()), (2, "a/2.flac".to_owned())]);
+        let after = BTreeMap::from([(1, "b/1.flac".to_owned()), (3, "a/3.flac".to_owned())]);
+
+        let actions: Vec<String> = track_changes(&before, &after)
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+
+        assert_eq!(
+            actions,
+            [
+                "DB更新: a/1.flac (パスを b/1.flac に変更)",
+                "DB追加: a/3.flac",
+                "DB削除: a/2.flac",
+            ]
+        );
+    }
+
+    #[test]
+    fn file_changes_are_recorded_against_the_real_library() -> Result<()> {
+        let real = tempfile::tempdir()?;
+        let scratch = tempfile::tempdir()?;
+        std::fs::create_dir(real.path().join("a"))?;
+        std::fs::write(real.path().join("a/1.flac"), "one")?;
+        std::fs::write(real.path().join("a/2.flac"), "two")?;
+        sandbox::mirror(real.path(), scratch.path(), "a", MirrorKind::Placeholder)?;
+        sandbox::mirror(real.path(), scratch.path(), "b", MirrorKind::Placeholder)?;
+
+        let targets = ["a", "b"];
+        let before = snapshot_files(scratch.path(), &targets)?;
+        std::fs::create_dir(scratch.path().join("b"))?;
+        std::fs::rename(
+            scratch.path().join("a/1.flac"),
+            scratch.path().join("b/1.flac"),
+        )?;
+        std::fs::remove_file(scratch.path().join("a/2.flac"))?;
+        std::fs::write(scratch.path().join("a/3.flac"), "three")?;
+        let after = snapshot_files(scratch.path(), &targets)?;
+
+        let actions: Vec<String> = file_changes(real.path(), Path::new("/pc"), &before, &after)
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+
+        let real_path = |path: &str| real.path().join(path).display().to_string();
+        assert_eq!(
+            actions,
+            [
+                format!(
+                    "ファイルコピー: /pc/a/3.flac → {} (5 B)",
+                    real_path("a/3.flac")
+                ),
+                format!(
+                    "ファイル移動: {} → {}",
+                    real_path("a/1.flac"),
+                    real_path("b/1.flac")
+                ),
+                format!("ファイル削除: {}", real_path("a/2.flac")),
+            ]
+        );
+        // 実際のライブラリは変わらない
+        assert!(real.path().join("a/2.flac").exists());
+        Ok(())
+    }
+
+    #[test]
+    fn writes_keep_the_margin_free() {
+        let estimate = SpaceEstimate {
+            required_bytes: 1,
+            free_bytes: Some(SPACE_MARGIN_BYTES),
+        };
+
+        assert!(!estimate.fits());
+    }
+}
@@ -1,12 +1,18 @@
+use std::collections::HashMap;
 use std::fmt::Arguments;
 use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::mpsc::{self, Sender};
 
-use anyhow::Result;
+use anyhow::{Result, anyhow};
 use eframe::egui::mutex::Mutex;
 use murack_core_app::cui::Cui;
 
-use crate::legacy_commands::console::Console;
+use crate::legacy_commands::{
+    console::{Console, LogLevel},
+    progress::Progress,
+    resolve_step::{ResolveStep, StepCui},
+};
 
 /// コマンドの実行状態
 #[derive(Debug, Default)]
@@ -17,14 +23,49 @@ pub enum CommandState {
     Choice {
         available_choices: Vec<char>,
         message: String,
-        choice_sender: Sender<char>,
+        /// 確認元の解決処理 (分かる場合のみ「以降にも適用」できる)
+        step: Option<ResolveStep>,
+        choice_sender: Sender<ChoiceAnswer>,
     },
 }
 
+/// 選択肢への回答
+#[derive(Debug, Clone, Copy)]
+pub struct ChoiceAnswer {
+    pub choice: char,
+    /// 同じコマンド内の以降の同種の確認にも、同じ選択を適用するか
+    pub apply_to_remaining: bool,
+}
+
+/// 「以降にも適用」で回答を使い回す確認の種類
+///
+/// メッセージの文面は言語や曲によって変わるので使わず、
+/// 確認元の解決処理と選択肢で区別する。
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct ChoiceCategory {
+    step: ResolveStep,
+    cases: Vec<char>,
+}
+
+impl ChoiceCategory {
+    fn new(step: ResolveStep, cases: &[char]) -> Self {
+        Self {
+            step,
+            cases: cases.to_vec(),
+        }
+    }
+}
+
 /// egui 用の Cui 実装
 pub struct EguiCui {
     console: Arc<Mutex<Console>>,
     command_state: Arc<Mutex<CommandState>>,
+    /// 実行中のコマンドの中止が要求されたか
+    cancelled: AtomicBool,
+    /// 「以降にも適用」で記憶した回答
+    remembered_choices: Mutex<HashMap<ChoiceCategory, char>>,
+    /// 実行中のコマンドの進捗
+    progress: Progress,
 }
 
 impl EguiCui {
@@ -32,49 +73,260 @@ impl EguiCui {
         Self {
             console,
             command_state,
+            cancelled: AtomicBool::new(false),
+            remembered_choices: Mutex::default(),
+            progress: Progress::default(),
+        }
+    }
+
+    /// 新しいコマンドの実行開始時に、前回のコマンドの状態を消去する
+    pub fn begin_job(&self) {
+        self.cancelled.store(false, Ordering::SeqCst);
+        self.remembered_choices.lock().clear();
+        self.progress.begin();
+    }
+
+    /// コマンドの実行終了時に進捗の表示を消し、処理した件数を返す (数えないコマンドなら None)
+    pub fn end_job(&self) -> Option<usize> {
+        self.progress.finish()
+    }
+
+    pub fn progress(&self) -> &Progress {
+        &self.progress
+    }
+
+    /// コマンドの出力先のコンソール (Cui を通さずに知らせたいことに使う)
+    pub fn console(&self) -> Arc<Mutex<Console>> {
+        self.console.clone()
+    }
+
+    /// 実行中のコマンドの中止を要求
+    ///
+    /// 以降の Cui の呼び出しはエラーを返すので、コマンドはそこで中断される。
+    /// 選択肢の入力待ちも解除する。
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+
+        // 送信側を破棄して、input_case の待機を解除する
+        let mut command_state = self.command_state.lock();
+        if matches!(&*command_state, CommandState::Choice { .. }) {
+            *command_state = CommandState::Running;
+        }
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+
+    fn check_cancelled(&self) -> Result<()> {
+        if self.is_cancelled() {
+            Err(anyhow!("コマンドが中止されました"))
+        } else {
+            Ok(())
         }
     }
 }
 
 impl Cui for EguiCui {
     fn out(&self, args: Arguments) -> anyhow::Result<()> {
-        // ほんとは改行せず連結すべきだけど、改行なしの追加ができる設計になってないのでとりあえず
-        self.outln(args)
+        self.check_cancelled()?;
+        let text = args.to_string();
+        self.console.lock().write(LogLevel::Info, &text);
+
+        Ok(())
     }
 
     fn outln(&self, args: Arguments) -> anyhow::Result<()> {
-        self.console.lock().add_log(args.to_string());
+        self.check_cancelled()?;
+        let text = args.to_string();
+        self.console.lock().add_log(text);
 
         Ok(())
     }
 
     fn err(&self, args: Arguments) -> anyhow::Result<()> {
-        // ほんとは改行せず連結すべきだけど、改行なしの追加ができる設計になってないのでとりあえず
-        self.errln(args)
+        self.check_cancelled()?;
+        let text = args.to_string();
+        self.console.lock().write(LogLevel::Error, &text);
+
+        Ok(())
     }
 
     fn errln(&self, args: Arguments) -> anyhow::Result<()> {
-        self.console.lock().add_error(args.to_string());
+        self.check_cancelled()?;
+        let text = args.to_string();
+        self.console.lock().add_error(text);
 
         Ok(())
     }
 
     fn input_case(&self, cases: &[char], message: &str) -> Result<char> {
+        self.ask(None, cases, message)
+    }
+}
+
+impl StepCui for EguiCui {
+    fn input_case_in(&self, step: ResolveStep, cases: &[char], message: &str) -> Result<char> {
+        self.ask(Some(step), cases, message)
+    }
+}
+
+impl EguiCui {
+    /// 選択肢を表示して回答を待つ
+    ///
+    /// `step` が分かる確認だけ、「以降にも適用」した回答を記憶・使用する。
+    fn ask(&self, step: Option<ResolveStep>, cases: &[char], message: &str) -> Result<char> {
+        self.check_cancelled()?;
+
+        let category = step.map(|step| ChoiceCategory::new(step, cases));
+        let remembered = category
+            .as_ref()
+            .and_then(|category| self.remembered_choices.lock().get(category).copied());
+        if let Some(choice) = remembered {
+            self.outln(format_args!("{message}"))?;
+            self.console
+                .lock()
+                .add_debug(format!("→ 以前の回答に従い {choice} を選択"));
+            return Ok(choice);
+        }
+
         let (choice_sender, choice_receiver) = mpsc::channel();
 
         // 選択肢状態を設定
-        *self.command_state.lock() = CommandState::Choice {
-            available_choices: cases.to_vec(),
-            message: message.to_string(),
-            choice_sender,
-        };
+        //
+        // cancel() は中止フラグを立ててから command_state をロックするので、
+        // ロック中にフラグを確認すれば、中止後に送信側を残して待ち続けることはない。
+        {
+            let mut command_state = self.command_state.lock();
+            self.check_cancelled()?;
+            *command_state = CommandState::Choice {
+                available_choices: cases.to_vec(),
+                message: message.to_string(),
+                step,
+                choice_sender,
+            };
+        }
 
-        // 選択されるまで待機
-        let choice = choice_receiver.recv()?;
+        // 選択されるまで待機 (中止されたら送信側が破棄されてエラーになる)
+        let received = choice_receiver.recv();
+        self.check_cancelled()?;
+        let answer = received?;
+
+        if let (true, Some(category)) = (answer.apply_to_remaining, category) {
+            self.remembered_choices
+                .lock()
+                .insert(category, answer.choice);
+        }
 
         // UI に選択終了を通知
         *self.command_state.lock() = CommandState::Running;
 
-        Ok(choice)
+        Ok(answer.choice)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 選択肢の入力待ちになるまで待って回答する
+    fn answer(cui: &EguiCui, choice: char, apply_to_remaining: bool) {
+        loop {
+            if let CommandState::Choice { choice_sender, .. } = &*cui.command_state.lock() {
+                choice_sender
+                    .send(ChoiceAnswer {
+                        choice,
+                        apply_to_remaining,
+                    })
+                    .unwrap();
+                return;
+            }
+            std::thread::yield_now();
+        }
+    }
+
+    fn new_cui() -> EguiCui {
+        EguiCui::new(Arc::default(), Arc::default())
+    }
+
+    #[test]
+    fn category_ignores_message() {
+        assert_eq!(
+            ChoiceCategory::new(ResolveStep::DataMatch, &['0', '1', '2']),
+            ChoiceCategory::new(ResolveStep::DataMatch, &['0', '1', '2']),
+        );
+        assert_ne!(
+            ChoiceCategory::new(ResolveStep::DataMatch, &['0', '1', '2']),
+            ChoiceCategory::new(ResolveStep::Dap, &['0', '1', '2']),
+        );
+        assert_ne!(
+            ChoiceCategory::new(ResolveStep::Existance, &['0', '1']),
+            ChoiceCategory::new(ResolveStep::Existance, &['0', '1', '2']),
+        );
+    }
+
+    #[test]
+    fn remembered_choice_applies_only_to_same_step() {
+        let cui = new_cui();
+        let cases = ['0', '1', '2'];
+
+        std::thread::scope(|scope| {
+            let first = scope.spawn(|| {
+                cui.input_case_in(ResolveStep::DataMatch, &cases, "曲Aのタイトルが違います")
+            });
+            answer(&cui, '1', true);
+            assert_eq!(first.join().unwrap().unwrap(), '1');
+        });
+
+        // 文面が違っても同じ解決処理なら記憶した回答を使う
+        let second = cui
+            .input_case_in(
+                ResolveStep::DataMatch,
+                &cases,
+                "曲Bのアーティストが違います",
+            )
+            .unwrap();
+        assert_eq!(second, '1');
+
+        // 別の解決処理では改めて確認する
+        std::thread::scope(|scope| {
+            let third =
+                scope.spawn(|| cui.input_case_in(ResolveStep::Dap, &cases, "曲Aの内容が違います"));
+            answer(&cui, '0', false);
+            assert_eq!(third.join().unwrap().unwrap(), '0');
+        });
+    }
+
+    #[test]
+    fn plain_input_case_is_not_remembered() {
+        let cui = new_cui();
+        let cases = ['0', '1'];
+
+        for choice in ['1', '0'] {
+            std::thread::scope(|scope| {
+                let asked = scope.spawn(|| cui.input_case(&cases, "確認"));
+                answer(&cui, choice, true);
+                assert_eq!(asked.join().unwrap().unwrap(), choice);
+            });
+        }
+    }
+
+    #[test]
+    fn begin_job_forgets_remembered_choices() {
+        let cui = new_cui();
+        let cases = ['0', '1', '2'];
+
+        std::thread::scope(|scope| {
+            let first = scope.spawn(|| cui.input_case_in(ResolveStep::Existance, &cases, "a"));
+            answer(&cui, '2', true);
+            assert_eq!(first.join().unwrap().unwrap(), '2');
+        });
+        cui.begin_job();
+
+        std::thread::scope(|scope| {
+            let second = scope.spawn(|| cui.input_case_in(ResolveStep::Existance, &cases, "b"));
+            answer(&cui, '0', false);
+            assert_eq!(second.join().unwrap().unwrap(), '0');
+        });
     }
 }
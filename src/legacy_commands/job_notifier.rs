@@ -0,0 +1,153 @@
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use eframe::egui::{self, mutex::Mutex};
+
+use crate::legacy_commands::{
+    console::Console,
+    job_queue::{JobId, JobStatus},
+};
+
+/// トーストを表示しておく時間
+const TOAST_DURATION: Duration = Duration::from_secs(8);
+
+/// 終了したジョブの結果の要約
+pub struct JobReport {
+    pub job_id: JobId,
+    pub summary: String,
+    pub status: JobStatus,
+    pub duration: Duration,
+    /// 処理した曲の数 (件数を数えないコマンドなら None)
+    pub item_count: Option<usize>,
+}
+
+impl JobReport {
+    fn title(&self) -> String {
+        format!(
+            "#{} {}: {}",
+            self.job_id,
+            self.summary,
+            self.status.label().0
+        )
+    }
+
+    fn detail(&self) -> String {
+        let seconds = self.duration.as_secs();
+        let mut detail = format!("所要時間 {}:{:02}", seconds / 60, seconds % 60);
+        if let Some(item_count) = self.item_count {
+            detail.push_str(&format!(", {item_count} 件処理"));
+        }
+        if let JobStatus::Failed(message) = &self.status {
+            detail.push('\n');
+            detail.push_str(message);
+        }
+        detail
+    }
+}
+
+struct Toast {
+    title: String,
+    detail: String,
+    color: egui::Color32,
+    shown_at: Instant,
+}
+
+/// ジョブの終了をウィンドウ内のトーストとデスクトップ通知で知らせる
+pub struct JobNotifier {
+    toasts: Mutex<Vec<Toast>>,
+    /// デスクトップ通知も出すか
+    desktop_notification: bool,
+    /// デスクトップ通知を表示できなかったことを知らせるコンソール
+    console: Arc<Mutex<Console>>,
+}
+
+impl JobNotifier {
+    pub fn new(desktop_notification: bool, console: Arc<Mutex<Console>>) -> Self {
+        Self {
+            toasts: Mutex::default(),
+            desktop_notification,
+            console,
+        }
+    }
+
+    pub fn notify(&self, report: JobReport) {
+        let (_, color) = report.status.label();
+        self.notify_message(report.title(), report.detail(), color);
+    }
+
+    /// ジョブ 1 件に限らない通知 (自動同期のまとめなど)
+    pub fn notify_message(&self, title: String, detail: String, color: egui::Color32) {
+        if self.desktop_notification {
+            let title = title.clone();
+            let detail = detail.clone();
+            let console = self.console.clone();
+            tokio::task::spawn_blocking(move || {
+                if let Err(e) = show_desktop_notification(&title, &detail) {
+                    console
+                        .lock()
+                        .add_warn(format!("デスクトップ通知を表示できませんでした: {e}"));
+                }
+            });
+        }
+
+        self.toasts.lock().push(Toast {
+            title,
+            detail,
+            color,
+            shown_at: Instant::now(),
+        });
+    }
+
+    /// トーストをウィンドウの右下に表示
+    pub fn show(&self, ctx: &egui::Context) {
+        let mut toasts = self.toasts.lock();
+        toasts.retain(|toast| toast.shown_at.elapsed() < TOAST_DURATION);
+        if toasts.is_empty() {
+            return;
+        }
+
+        // 表示時間が過ぎたら消えるよう再描画
+        ctx.request_repaint_after(Duration::from_secs(1));
+
+        let mut dismissed = None;
+        egui::Area::new(egui::Id::new("job_toasts"))
+            .anchor(egui::Align2::RIGHT_BOTTOM, [-12.0, -12.0])
+            .order(egui::Order::Foreground)
+            .show(ctx, |ui| {
+                for (index, toast) in toasts.iter().enumerate() {
+                    egui::Frame::popup(ui.style()).show(ui, |ui| {
+                        ui.set_max_width(320.0);
+                        ui.horizontal(|ui| {
+                            ui.colored_label(toast.color, &toast.title);
+                            if ui.small_button("×").clicked() {
+                                dismissed = Some(index);
+                            }
+                        });
+                        ui.label(&toast.detail);
+                    });
+                }
+            });
+
+        if let Some(index) = dismissed {
+            toasts.remove(index);
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn show_desktop_notification(title: &str, detail: &str) -> anyhow::Result<()> {
+    notify_rust::Notification::new()
+        .appname("Murack Sync")
+        .summary(title)
+        .body(detail)
+        .show()?;
+
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn show_desktop_notification(_title: &str, _detail: &str) -> anyhow::Result<()> {
+    Ok(())
+}
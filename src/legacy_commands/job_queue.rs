@@ -0,0 +1,414 @@
+use std::{sync::Arc, time::Instant};
+
+use anyhow::anyhow;
+use eframe::egui::{self, mutex::Mutex};
+use serde_json::json;
+use tokio::task::AbortHandle;
+
+use crate::dap_device::DapDevice;
+use crate::legacy_commands::{
+    command_pages::CommandRunner,
+    console::Console,
+    di_registry::DIRegistry,
+    egui_cui::{CommandState, EguiCui},
+    job_notifier::{JobNotifier, JobReport},
+    library_path_source::LibraryPathSource,
+};
+
+pub type JobId = u64;
+
+/// ジョブの実行状態
+#[derive(Debug, Clone, PartialEq)]
+pub enum JobStatus {
+    Pending,
+    Running,
+    Succeeded,
+    Failed(String),
+    Cancelled,
+    /// 同じ連続実行の前のジョブが完了しなかったため実行しなかった
+    Skipped,
+}
+
+impl JobStatus {
+    pub fn label(&self) -> (&'static str, egui::Color32) {
+        match self {
+            JobStatus::Pending => ("待機中", egui::Color32::GRAY),
+            JobStatus::Running => ("実行中", egui::Color32::LIGHT_BLUE),
+            JobStatus::Succeeded => ("完了", egui::Color32::LIGHT_GREEN),
+            JobStatus::Failed(_) => ("失敗", egui::Color32::LIGHT_RED),
+            JobStatus::Cancelled => ("中止", egui::Color32::YELLOW),
+            JobStatus::Skipped => ("未実行", egui::Color32::GRAY),
+        }
+    }
+}
+
+/// キューに積まれたコマンド 1 回分の実行
+pub struct Job {
+    pub id: JobId,
+    pub summary: String,
+    pub status: JobStatus,
+    /// 実行に DAP の接続が必要か
+    pub requires_dap: bool,
+    /// 連続実行の先頭のジョブの ID (1 つが完了しなければ残りを実行しない)
+    chain: Option<JobId>,
+    runner: Option<CommandRunner>,
+}
+
+/// 順番に実行するコマンドのキュー
+#[derive(Default)]
+pub struct JobQueue {
+    jobs: Vec<Job>,
+    next_id: JobId,
+    /// ワーカーがジョブを処理中か
+    worker_running: bool,
+    /// 実行中のジョブのタスク
+    running_task: Option<AbortHandle>,
+}
+
+impl JobQueue {
+    /// ジョブを末尾に追加
+    pub fn push(&mut self, summary: String, runner: CommandRunner, requires_dap: bool) -> JobId {
+        self.next_id += 1;
+        let id = self.next_id;
+
+        self.jobs.push(Job {
+            id,
+            summary,
+            status: JobStatus::Pending,
+            requires_dap,
+            chain: None,
+            runner: Some(runner),
+        });
+
+        id
+    }
+
+    /// 一連のジョブを末尾に追加
+    ///
+    /// いずれかが完了しなければ、残りのジョブは実行しない。
+    pub fn push_chain(&mut self, jobs: Vec<(String, CommandRunner, bool)>) -> Vec<JobId> {
+        let ids: Vec<JobId> = jobs
+            .into_iter()
+            .map(|(summary, runner, requires_dap)| self.push(summary, runner, requires_dap))
+            .collect();
+
+        if let Some(&head) = ids.first() {
+            for job in self.jobs.iter_mut().filter(|j| ids.contains(&j.id)) {
+                job.chain = Some(head);
+            }
+        }
+
+        ids
+    }
+
+    pub fn is_working(&self) -> bool {
+        self.worker_running
+    }
+
+    pub fn is_job_running(&self) -> bool {
+        self.running_task.is_some()
+    }
+
+    /// 実行中のジョブの中止を要求
+    ///
+    /// コマンドは次に Cui を呼び出した時点で中断される。入力待ちも解除する。
+    pub fn cancel_running(&self, di_registry: &DIRegistry<EguiCui>) {
+        if self.running_task.is_some() {
+            di_registry.cui().cancel();
+        }
+    }
+
+    /// 実行中のジョブのタスクを強制終了
+    ///
+    /// 中止を要求しても Cui を呼び出さずに処理を続けるコマンドのための最後の手段。
+    /// 処理の途中で止まるので、ワーカーが途中までの実行として操作履歴に記録する。
+    pub fn abort_running(&self) {
+        if let Some(task) = &self.running_task {
+            task.abort();
+        }
+    }
+
+    /// ジョブの状態 (一覧から取り除かれていれば None)
+    pub fn status(&self, id: JobId) -> Option<&JobStatus> {
+        self.jobs.iter().find(|j| j.id == id).map(|j| &j.status)
+    }
+
+    pub fn has_pending(&self) -> bool {
+        self.jobs.iter().any(|j| j.status == JobStatus::Pending)
+    }
+
+    pub fn pending_count(&self) -> usize {
+        self.jobs
+            .iter()
+            .filter(|j| j.status == JobStatus::Pending)
+            .count()
+    }
+
+    /// 待機中のジョブを 1 つ前に移動
+    pub fn move_up(&mut self, id: JobId) {
+        let Some(i) = self.pending_index(id) else {
+            return;
+        };
+        if i > 0 && self.jobs[i - 1].status == JobStatus::Pending {
+            self.jobs.swap(i - 1, i);
+        }
+    }
+
+    /// 待機中のジョブを 1 つ後ろに移動
+    pub fn move_down(&mut self, id: JobId) {
+        let Some(i) = self.pending_index(id) else {
+            return;
+        };
+        if i + 1 < self.jobs.len() && self.jobs[i + 1].status == JobStatus::Pending {
+            self.jobs.swap(i, i + 1);
+        }
+    }
+
+    /// 実行中以外のジョブを一覧から取り除く
+    pub fn remove(&mut self, id: JobId) {
+        self.jobs
+            .retain(|j| j.id != id || j.status == JobStatus::Running);
+    }
+
+    /// 終了したジョブを一覧から取り除く
+    pub fn clear_finished(&mut self) {
+        self.jobs
+            .retain(|j| matches!(j.status, JobStatus::Pending | JobStatus::Running));
+    }
+
+    fn pending_index(&self, id: JobId) -> Option<usize> {
+        self.jobs
+            .iter()
+            .position(|j| j.id == id && j.status == JobStatus::Pending)
+    }
+
+    /// 次に実行するジョブを取り出して実行中にする
+    ///
+    /// 待機中のジョブがなければワーカーを停止状態にする。
+    fn take_next(&mut self) -> Option<(JobId, String, bool, CommandRunner)> {
+        let next = self
+            .jobs
+            .iter_mut()
+            .find(|j| j.status == JobStatus::Pending)
+            .and_then(|job| {
+                let runner = job.runner.take()?;
+                job.status = JobStatus::Running;
+                Some((job.id, job.summary.clone(), job.requires_dap, runner))
+            });
+
+        if next.is_none() {
+            self.worker_running = false;
+        }
+
+        next
+    }
+
+    fn finish(&mut self, id: JobId, status: JobStatus) {
+        let Some(job) = self.jobs.iter_mut().find(|j| j.id == id) else {
+            return;
+        };
+        let chain = job.chain.filter(|_| status != JobStatus::Succeeded);
+        job.status = status;
+
+        // 連続実行の途中で完了しなかったら、残りは実行しない
+        if let Some(chain) = chain {
+            for job in self
+                .jobs
+                .iter_mut()
+                .filter(|j| j.chain == Some(chain) && j.status == JobStatus::Pending)
+            {
+                job.status = JobStatus::Skipped;
+                job.runner = None;
+            }
+        }
+    }
+
+    /// ジョブ一覧を表示
+    ///
+    /// `console_filter` はコンソールに表示するジョブの選択状態。
+    /// キューの実行が要求されたら true を返す。
+    pub fn show(&mut self, ui: &mut egui::Ui, console_filter: &mut Option<JobId>) -> bool {
+        let mut run_requested = false;
+
+        ui.horizontal(|ui| {
+            ui.label(format!("キュー: {} 件", self.jobs.len()));
+
+            if ui
+                .add_enabled(
+                    !self.worker_running && self.has_pending(),
+                    egui::Button::new("キューを実行"),
+                )
+                .clicked()
+            {
+                run_requested = true;
+            }
+
+            if ui.button("終了したジョブを消去").clicked() {
+                self.clear_finished();
+            }
+        });
+
+        let mut move_up = None;
+        let mut move_down = None;
+        let mut remove = None;
+
+        egui::ScrollArea::vertical()
+            .id_salt("job_queue")
+            .max_height(120.0)
+            .auto_shrink([false, true])
+            .show(ui, |ui| {
+                for job in &self.jobs {
+                    ui.horizontal(|ui| {
+                        let (status_text, status_color) = job.status.label();
+                        ui.colored_label(status_color, status_text);
+
+                        let selected = *console_filter == Some(job.id);
+                        let response = ui
+                            .selectable_label(selected, format!("#{} {}", job.id, job.summary))
+                            .on_hover_text("クリックでこのジョブの出力のみ表示");
+                        if response.clicked() {
+                            *console_filter = if selected { None } else { Some(job.id) };
+                        }
+
+                        if let JobStatus::Failed(message) = &job.status {
+                            ui.colored_label(egui::Color32::LIGHT_RED, message);
+                        }
+
+                        if job.status == JobStatus::Pending {
+                            if ui.small_button("↑").clicked() {
+                                move_up = Some(job.id);
+                            }
+                            if ui.small_button("↓").clicked() {
+                                move_down = Some(job.id);
+                            }
+                        }
+                        if job.status != JobStatus::Running && ui.small_button("✖").clicked() {
+                            remove = Some(job.id);
+                        }
+                    });
+                }
+            });
+
+        if let Some(id) = move_up {
+            self.move_up(id);
+        }
+        if let Some(id) = move_down {
+            self.move_down(id);
+        }
+        if let Some(id) = remove {
+            self.remove(id);
+            if *console_filter == Some(id) {
+                *console_filter = None;
+            }
+        }
+
+        run_requested
+    }
+}
+
+/// キューのジョブを順番に実行するワーカーを起動
+///
+/// 既にワーカーが動いていれば何もしない (追加されたジョブはそのワーカーが実行する)。
+pub fn start_worker(
+    queue: Arc<Mutex<JobQueue>>,
+    console: Arc<Mutex<Console>>,
+    command_state: Arc<Mutex<CommandState>>,
+    di_registry: Arc<DIRegistry<EguiCui>>,
+    path_source: Arc<LibraryPathSource>,
+    notifier: Arc<JobNotifier>,
+    dap: Arc<DapDevice>,
+) {
+    {
+        let mut queue = queue.lock();
+        if queue.worker_running || !queue.has_pending() {
+            return;
+        }
+        queue.worker_running = true;
+    }
+
+    tokio::spawn(async move {
+        loop {
+            let next = queue.lock().take_next();
+            let Some((job_id, summary, requires_dap, runner)) = next else {
+                break;
+            };
+
+            // 待機中に DAP が外された場合は、途中で失敗させずに実行前に止める
+            if let Some(reason) = dap.status().absent_reason().filter(|_| requires_dap) {
+                console
+                    .lock()
+                    .add_error(format!("#{job_id} {summary}: {reason}"));
+                queue
+                    .lock()
+                    .finish(job_id, JobStatus::Failed(reason.to_owned()));
+                continue;
+            }
+
+            *command_state.lock() = CommandState::Running;
+            console.lock().begin_job(job_id, &summary);
+            di_registry.cui().begin_job();
+            let started_at = Instant::now();
+
+            let task = runner(di_registry.clone());
+            queue.lock().running_task = Some(task.abort_handle());
+
+            let command_result = task.await;
+            queue.lock().running_task = None;
+            let item_count = di_registry.cui().end_job();
+
+            let aborted = matches!(&command_result, Err(e) if e.is_cancelled());
+            if aborted {
+                let message = "強制終了したため、変更が途中までしか行われていない可能性があります";
+                console
+                    .lock()
+                    .add_warn(format!("#{job_id} を強制終了しました。{message}"));
+                di_registry
+                    .journal()
+                    .record(
+                        "abort",
+                        json!({ "job_id": job_id, "summary": summary }),
+                        vec![],
+                        &Err(anyhow!(message)),
+                    )
+                    .await;
+            }
+
+            let status = match command_result {
+                _ if di_registry.cui().is_cancelled() => JobStatus::Cancelled,
+                Ok(Ok(())) => JobStatus::Succeeded,
+                Ok(Err(e)) => JobStatus::Failed(e.to_string()),
+                Err(e) if e.is_cancelled() => JobStatus::Cancelled,
+                Err(e) => JobStatus::Failed(e.to_string()),
+            };
+
+            {
+                let mut console = console.lock();
+                match &status {
+                    JobStatus::Succeeded => console.add_log(format!("#{job_id} が完了しました")),
+                    JobStatus::Failed(message) => console.add_error(message.clone()),
+                    JobStatus::Cancelled => console.add_warn(format!("#{job_id} を中止しました")),
+                    _ => {}
+                }
+                for failure in di_registry.journal().take_failures() {
+                    console.add_warn(failure);
+                }
+                console.end_job();
+            }
+
+            *command_state.lock() = CommandState::NotRunning;
+
+            // コマンドでライブラリが変わっている可能性があるので、補完候補を更新
+            path_source.reload_db_paths();
+
+            notifier.notify(JobReport {
+                job_id,
+                summary,
+                status: status.clone(),
+                duration: started_at.elapsed(),
+                item_count,
+            });
+
+            queue.lock().finish(job_id, status);
+        }
+    });
+}
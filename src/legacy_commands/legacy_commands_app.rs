@@ -1,49 +1,167 @@
-use std::sync::Arc;
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::Duration,
+};
 
 use eframe::egui::{self, RichText, mutex::Mutex};
-use murack_core_app::Config;
-use sqlx::PgPool;
 
 use crate::legacy_commands::{
-    command_pages::CommandPage, console::Console, di_registry::DIRegistry, egui_cui::CommandState,
+    auto_sync::{AutoSyncSession, AutoSyncSettings},
+    console::Console,
+    di_registry::DIRegistry,
+    egui_cui::{ChoiceAnswer, CommandState, EguiCui},
+    job_notifier::JobNotifier,
+    job_queue::{self, JobId, JobQueue},
+    library_path_source::LibraryPathSource,
     navigation::LegacyCommandsNavigation,
+    operation_history::OperationHistory,
+    playlist_selection::PlaylistSelection,
+    space_preflight::SpacePreflight,
+};
+use crate::{
+    dap_device::{DapDevice, DapStatus},
+    db_health::DbHealth,
+    startup::AppServices,
 };
 
 pub struct LegacyCommandsApp {
     console: Arc<Mutex<Console>>,
-    di_registry: Arc<DIRegistry>,
+    di_registry: Arc<DIRegistry<EguiCui>>,
+    path_source: Arc<LibraryPathSource>,
     navigation: LegacyCommandsNavigation,
     command_state: Arc<Mutex<CommandState>>,
+    job_queue: Arc<Mutex<JobQueue>>,
+    history: OperationHistory,
+    notifier: Arc<JobNotifier>,
+    /// 最後に設定したウィンドウタイトル
+    window_title: String,
+    /// 前回の描画時にキューが処理中だったか
+    was_working: bool,
+    /// コンソールに出力を表示するジョブ (None なら全て)
+    console_filter: Option<JobId>,
+    /// 選択肢の回答を以降の同種の確認にも適用するか
+    apply_choice_to_remaining: bool,
+    /// 中止を待っているジョブの強制終了を確認中か
+    confirming_abort: bool,
+    /// DB に接続できない間は実行させない
+    db_health: Arc<DbHealth>,
+    /// DB に接続できないためにキューの開始を見送ったか (再接続したら開始する)
+    start_deferred: AtomicBool,
+    /// DAP が未接続の間は DAP を使うコマンドを実行させない
+    dap: Arc<DapDevice>,
+    /// 前回の描画時に登録済みの DAP が接続されていたか
+    dap_was_connected: bool,
+    auto_sync: AutoSyncSettings,
+    /// 実行中の自動同期 (全てのジョブが終わったら結果を知らせて None に戻す)
+    auto_sync_session: Option<AutoSyncSession>,
+    space_preflight: SpacePreflight,
+    /// 自動同期で書き込むプレイリストの選択を読み込むプロファイル
+    profile: String,
 }
 
 impl LegacyCommandsApp {
-    pub fn new(config: Arc<Config>, db_pool: Arc<PgPool>) -> Self {
-        let console = Arc::<Mutex<Console>>::default();
+    pub fn new(services: &AppServices, db_health: Arc<DbHealth>, dap: Arc<DapDevice>) -> Self {
+        let console = Arc::new(Mutex::new(Console::with_log_file()));
         let command_state = Arc::<Mutex<CommandState>>::default();
-        let di_registry = DIRegistry::new(console.clone(), command_state.clone(), config, db_pool);
+        let settings = &services.settings;
+        let path_source = LibraryPathSource::new(
+            services.config.pc_lib.clone(),
+            services.db_pool.clone(),
+            console.clone(),
+        );
+        let cui = EguiCui::new(console.clone(), command_state.clone());
+        let auto_backup = settings
+            .auto_backup_before_destructive
+            .then(|| services.backups.clone());
+        let navigation = LegacyCommandsNavigation::new(
+            settings.clone(),
+            services.config.clone(),
+            services.config_path.clone(),
+            services.db_pool.clone(),
+            services.profile.clone(),
+        );
+        let di_registry = DIRegistry::new(
+            cui,
+            services.config.clone(),
+            services.config_path.clone(),
+            services.db_pool.clone(),
+            services.journal.clone(),
+            auto_backup,
+        );
+        let notifier = JobNotifier::new(settings.desktop_notification, console.clone());
+        let history = OperationHistory::new(services.journal.clone(), console.clone());
+        let auto_sync = settings.auto_sync.clone();
+        // 起動前から接続されている DAP では自動同期しない
+        let dap_was_connected = matches!(dap.status(), DapStatus::Connected { .. });
 
         Self {
             di_registry: Arc::new(di_registry),
-            navigation: LegacyCommandsNavigation::default(),
+            path_source: Arc::new(path_source),
+            navigation,
             console,
             command_state,
+            job_queue: Arc::default(),
+            history,
+            notifier: Arc::new(notifier),
+            window_title: String::new(),
+            was_working: false,
+            console_filter: None,
+            apply_choice_to_remaining: false,
+            confirming_abort: false,
+            start_deferred: AtomicBool::new(false),
+            db_health,
+            dap,
+            dap_was_connected,
+            auto_sync,
+            auto_sync_session: None,
+            space_preflight: SpacePreflight::default(),
+            profile: services.profile.clone(),
         }
     }
 
     pub fn show(&mut self, ui: &mut egui::Ui) {
-        let command_running = !matches!(&*self.command_state.lock(), CommandState::NotRunning);
+        let working = self.job_queue.lock().is_working();
+        if working {
+            // バックグラウンドのジョブの状態変化を反映するため、定期的に再描画
+            ui.ctx().request_repaint_after(Duration::from_millis(200));
+        } else if self.was_working {
+            // キューの処理が終わったら、実行した操作を履歴に反映
+            self.history.reload();
+        }
+        self.was_working = working;
 
         ui.vertical(|ui| {
-            self.navigation.show_tab(ui, !command_running);
+            ui.horizontal(|ui| {
+                self.dap.show_status(ui);
+
+                if !self.auto_sync.steps.is_empty() {
+                    let enabled = self.auto_sync_session.is_none() && self.dap.is_present();
+                    if ui
+                        .add_enabled(enabled, egui::Button::new("同期"))
+                        .on_hover_text("自動同期と同じコマンドを順に実行します")
+                        .clicked()
+                    {
+                        self.start_auto_sync();
+                    }
+                    if self.auto_sync.enabled {
+                        ui.weak("接続時に自動同期");
+                    }
+                }
+            });
+            ui.separator();
+
+            self.navigation.show_tab(ui);
             let page = &mut *self.navigation.current_page;
 
             ui.separator();
 
-            ui.vertical_centered(|ui| {
-                if command_running {
-                    ui.disable();
-                }
+            let mut run_clicked = false;
+            let mut enqueue_clicked = false;
 
+            ui.vertical_centered(|ui| {
                 ui.add_space(4.0);
 
                 // コマンドの簡易説明タイトル
@@ -52,34 +170,106 @@ impl LegacyCommandsApp {
                 ui.add_space(10.0);
 
                 // パラメータの入力欄
-                page.show_form(ui);
+                page.show_form(ui, &self.path_source);
+
+                // DAP への書き込み量の見積もり
+                self.space_preflight.show(ui, &*page, &self.di_registry);
 
                 ui.add_space(10.0);
 
-                // 実行ボタン
-                let button = ui.button(RichText::new("実行").heading());
-                if button.clicked() {
-                    run_command(
-                        page,
-                        self.console.clone(),
-                        self.command_state.clone(),
-                        self.di_registry.clone(),
-                    );
-                }
+                let dap_absent_reason = self
+                    .dap
+                    .status()
+                    .absent_reason()
+                    .filter(|_| page.requires_dap());
+
+                ui.horizontal(|ui| {
+                    // 実行ボタン
+                    let db_available = self.db_health.is_available();
+                    let button = ui
+                        .add_enabled(
+                            db_available && dap_absent_reason.is_none(),
+                            egui::Button::new(RichText::new("実行").heading()),
+                        )
+                        .on_disabled_hover_text(
+                            dap_absent_reason.unwrap_or("DB に接続できないため実行できません"),
+                        );
+                    run_clicked = button.clicked();
+
+                    enqueue_clicked = ui.button("キューに追加").clicked();
+
+                    let job_running = self.job_queue.lock().is_job_running();
+                    let cancel_requested = job_running && self.di_registry.cui().is_cancelled();
+                    if !cancel_requested {
+                        self.confirming_abort = false;
+                        if ui
+                            .add_enabled(job_running, egui::Button::new("中止"))
+                            .on_hover_text("コマンドが次に出力や確認をした時点で中止します")
+                            .clicked()
+                        {
+                            self.job_queue.lock().cancel_running(&self.di_registry);
+                        }
+                    } else if !self.confirming_abort {
+                        ui.label("中止を待っています");
+                        if ui.button("強制終了...").clicked() {
+                            self.confirming_abort = true;
+                        }
+                    } else {
+                        ui.colored_label(
+                            egui::Color32::LIGHT_RED,
+                            "強制終了すると、DB とファイルが途中の状態で残ることがあります",
+                        );
+                        if ui.button("強制終了する").clicked() {
+                            self.job_queue.lock().abort_running();
+                            self.confirming_abort = false;
+                        }
+                        if ui.button("やめる").clicked() {
+                            self.confirming_abort = false;
+                        }
+                    }
+                });
 
                 ui.add_space(4.0);
             });
 
+            if run_clicked || enqueue_clicked {
+                self.enqueue_current_page();
+            }
+
+            // ページ上の操作で要求されたジョブは、すぐに実行する
+            let requested_jobs = self.navigation.current_page.take_requested_jobs();
+            let has_requested_jobs = !requested_jobs.is_empty();
+            for (summary, runner) in requested_jobs {
+                // 前回の確認結果からの再解決 (check) なので DAP を使う
+                self.job_queue.lock().push(summary, runner, true);
+            }
+
+            if run_clicked || has_requested_jobs {
+                self.start_queue();
+            }
+
             // 選択肢が待機中なら表示
             if let CommandState::Choice {
                 available_choices,
                 message,
+                step,
                 choice_sender,
             } = &*self.command_state.lock()
             {
                 ui.separator();
                 ui.label(message);
 
+                // 確認元の解決処理が分かる場合だけ、回答を使い回せる
+                if let Some(step) = step {
+                    ui.checkbox(
+                        &mut self.apply_choice_to_remaining,
+                        format!(
+                            "このコマンドの以降の確認 ({}) にも同じ選択を適用",
+                            step.label()
+                        ),
+                    );
+                }
+
                 ui.horizontal(|ui| {
                     for &choice in available_choices {
                         let button_text = match choice {
@@ -91,9 +281,14 @@ impl LegacyCommandsApp {
                         };
 
                         if ui.button(button_text).clicked() {
-                            if let Err(e) = choice_sender.send(choice) {
+                            let answer = ChoiceAnswer {
+                                choice,
+                                apply_to_remaining: self.apply_choice_to_remaining,
+                            };
+                            if let Err(e) = choice_sender.send(answer) {
                                 println!("{e}");
                             }
+                            self.apply_choice_to_remaining = false;
                         }
                     }
                 });
@@ -101,31 +296,160 @@ impl LegacyCommandsApp {
 
             ui.separator();
 
+            // ジョブキュー
+            let run_requested = self.job_queue.lock().show(ui, &mut self.console_filter);
+            if run_requested {
+                self.start_queue();
+            }
+
+            if let Some((summary, runner)) = self.history.show(ui) {
+                // 取り消しでは DAP 上のファイルも戻す
+                self.job_queue.lock().push(summary, runner, true);
+                self.start_queue();
+            }
+
+            ui.separator();
+
             // Console area
             ui.label("Console:");
             ui.add_space(5.0);
-            self.console.lock().show(ui);
+            self.di_registry.cui().progress().show(ui);
+            self.console.lock().show(ui, self.console_filter);
         });
     }
-}
 
-fn run_command(
-    current_page: &mut dyn CommandPage,
-    console: Arc<Mutex<Console>>,
-    command_state: Arc<Mutex<CommandState>>,
-    di_registry: Arc<DIRegistry>,
-) {
-    *command_state.lock() = CommandState::Running;
+    /// DB に再接続できたら、接続できない間に待機させたジョブの実行を始める
+    ///
+    /// 他のページを表示中でも動くよう、ページによらず毎フレーム呼ぶ。
+    pub fn resume_queue_on_reconnect(&self) {
+        if !self.db_health.is_available() || !self.start_deferred.swap(false, Ordering::SeqCst) {
+            return;
+        }
 
-    let command_handle = current_page.run_command(di_registry.clone());
+        self.console
+            .lock()
+            .add_log("DB に接続できたため、待機中のジョブを実行します".to_owned());
+        self.start_queue();
+    }
 
-    tokio::spawn(async move {
-        let command_result = command_handle.await;
+    /// 登録済みの DAP が接続されたら自動同期を始め、終わったら結果をまとめて通知する
+    ///
+    /// 他のページを表示中でも動くよう、ページによらず毎フレーム呼ぶ。
+    pub fn update_auto_sync(&mut self) {
+        let connected = matches!(self.dap.status(), DapStatus::Connected { .. });
+        let just_connected = connected && !self.dap_was_connected;
+        self.dap_was_connected = connected;
 
-        *command_state.lock() = CommandState::NotRunning;
+        if just_connected && self.auto_sync.enabled && self.auto_sync_session.is_none() {
+            self.console
+                .lock()
+                .add_log("DAP が接続されたため、自動同期を開始します".to_owned());
+            self.start_auto_sync();
+        }
 
-        if let Err(e) = command_result {
-            console.lock().add_error(e.to_string());
+        let Some(session) = &self.auto_sync_session else {
+            return;
+        };
+        let Some(report) = session.report(&self.job_queue.lock()) else {
+            return;
+        };
+        self.auto_sync_session = None;
+
+        {
+            let mut console = self.console.lock();
+            let message = format!("{}\n{}", report.title, report.detail);
+            if report.succeeded {
+                console.add_log(message);
+            } else {
+                console.add_warn(message);
+            }
         }
-    });
+        self.notifier
+            .notify_message(report.title, report.detail, report.color);
+    }
+
+    fn start_auto_sync(&mut self) {
+        let selection = PlaylistSelection::load(&self.profile).unwrap_or_else(|e| {
+            self.console.lock().add_warn(format!(
+                "プレイリストの選択を読み込めないため、全てのプレイリストを書き込みます: {e:#}"
+            ));
+            PlaylistSelection::default()
+        });
+        let session =
+            AutoSyncSession::start(&mut self.job_queue.lock(), &self.auto_sync, &selection);
+        match session {
+            Ok(session) => {
+                self.auto_sync_session = Some(session);
+                self.start_queue();
+            }
+            Err(e) => self
+                .console
+                .lock()
+                .add_error(format!("自動同期を開始できません: {e:#}")),
+        }
+    }
+
+    /// レガシーコマンド以外の処理で起きた問題をコンソールに出す
+    pub fn add_warning(&self, text: String) {
+        self.console.lock().add_warn(text);
+    }
+
+    /// ジョブの実行中か
+    pub fn is_busy(&self) -> bool {
+        self.job_queue.lock().is_working()
+    }
+
+    /// ジョブの終了通知とキューの状態を表示
+    ///
+    /// 他のページを表示中でも気づけるよう、ページによらず毎フレーム呼ぶ。
+    pub fn show_notifications(&mut self, ctx: &egui::Context) {
+        self.notifier.show(ctx);
+
+        let title = {
+            let queue = self.job_queue.lock();
+            if queue.is_job_running() {
+                format!("Murack Sync - 実行中 (待機 {} 件)", queue.pending_count())
+            } else {
+                "Murack Sync".to_owned()
+            }
+        };
+        if title != self.window_title {
+            ctx.send_viewport_cmd(egui::ViewportCommand::Title(title.clone()));
+            self.window_title = title;
+        }
+    }
+
+    /// 現在のページの入力内容でジョブをキューに追加
+    fn enqueue_current_page(&self) {
+        let page = &self.navigation.current_page;
+        self.job_queue.lock().push(
+            page.job_summary(),
+            page.create_runner(),
+            page.requires_dap(),
+        );
+    }
+
+    /// キューの処理を開始
+    ///
+    /// DB に接続できない間は開始せず、ジョブは待機させておく。
+    /// 再接続したら `resume_queue_on_reconnect` で開始する。
+    fn start_queue(&self) {
+        if !self.db_health.is_available() {
+            self.start_deferred.store(true, Ordering::SeqCst);
+            self.console
+                .lock()
+                .add_warn("DB に接続できないため、再接続するまでジョブを待機させます".to_owned());
+            return;
+        }
+
+        job_queue::start_worker(
+            self.job_queue.clone(),
+            self.console.clone(),
+            self.command_state.clone(),
+            self.di_registry.clone(),
+            self.path_source.clone(),
+            self.notifier.clone(),
+            self.dap.clone(),
+        );
+    }
 }
@@ -0,0 +1,142 @@
+use std::{
+    collections::BTreeMap,
+    path::PathBuf,
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+};
+
+use eframe::egui::mutex::Mutex;
+use sqlx::PgPool;
+
+use crate::legacy_commands::console::Console;
+
+/// ライブラリパスの補完候補 1 件
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PathCandidate {
+    pub name: String,
+    pub is_dir: bool,
+}
+
+/// ライブラリパスの補完・存在確認に使う情報源
+///
+/// PC ライブラリのファイルシステムと、DB に登録済みの曲パスの両方を参照する。
+pub struct LibraryPathSource {
+    pc_lib: PathBuf,
+    db_pool: Arc<PgPool>,
+    /// 読み込みに失敗したときの出力先
+    console: Arc<Mutex<Console>>,
+    /// バイト順に並んだ DB の曲パス
+    db_paths: Arc<Mutex<Vec<String>>>,
+    /// DB の曲パスを読み込み直すたびに増える (存在確認のキャッシュの無効化に使う)
+    generation: Arc<AtomicU64>,
+}
+
+impl LibraryPathSource {
+    pub fn new(pc_lib: PathBuf, db_pool: Arc<PgPool>, console: Arc<Mutex<Console>>) -> Self {
+        let source = Self {
+            pc_lib,
+            db_pool,
+            console,
+            db_paths: Arc::default(),
+            generation: Arc::default(),
+        };
+        source.reload_db_paths();
+        source
+    }
+
+    /// DB に登録済みの曲パスを読み込み直す
+    ///
+    /// コマンドでライブラリが変更された後に呼ぶ。
+    pub fn reload_db_paths(&self) {
+        let db_pool = self.db_pool.clone();
+        let db_paths = self.db_paths.clone();
+        let generation = self.generation.clone();
+        let console = self.console.clone();
+
+        tokio::spawn(async move {
+            // exists() で二分探索するので、DB の照合順序ではなくバイト順に並べる
+            let result: sqlx::Result<Vec<String>> =
+                sqlx::query_scalar(r#"SELECT path FROM tracks ORDER BY path COLLATE "C""#)
+                    .fetch_all(&*db_pool)
+                    .await;
+
+            match result {
+                Ok(paths) => {
+                    *db_paths.lock() = paths;
+                    generation.fetch_add(1, Ordering::SeqCst);
+                }
+                // 補完が効かなくなるだけなので、コマンドの実行は妨げない
+                Err(e) => console
+                    .lock()
+                    .add_warn(format!("パスの補完候補を読み込めませんでした: {e}")),
+            }
+        });
+    }
+
+    /// 指定ディレクトリ直下の補完候補を取得
+    ///
+    /// `dir` は空文字列 (ライブラリのルート) か、`/` で終わるライブラリパス。
+    pub fn children(&self, dir: &str) -> Vec<PathCandidate> {
+        // 名前順に並べつつ、PC と DB の重複をまとめる
+        let mut candidates = BTreeMap::<String, bool>::new();
+
+        if let Ok(entries) = std::fs::read_dir(self.pc_lib.join(dir)) {
+            for entry in entries.flatten() {
+                let name = entry.file_name().to_string_lossy().into_owned();
+                let is_dir = entry.file_type().is_ok_and(|t| t.is_dir());
+                candidates.insert(name, is_dir);
+            }
+        }
+
+        for path in self.db_paths.lock().iter() {
+            let Some(rest) = path.strip_prefix(dir) else {
+                continue;
+            };
+
+            match rest.split_once('/') {
+                Some((name, _)) => {
+                    candidates.insert(name.to_owned(), true);
+                }
+                None => {
+                    candidates.entry(rest.to_owned()).or_insert(false);
+                }
+            }
+        }
+
+        candidates
+            .into_iter()
+            .filter(|(name, _)| !name.is_empty())
+            .map(|(name, is_dir)| PathCandidate { name, is_dir })
+            .collect()
+    }
+
+    /// DB の曲パスを読み込んだ回数
+    ///
+    /// 変わっていなければ、同じパスの `exists` の結果も変わらない。
+    pub fn generation(&self) -> u64 {
+        self.generation.load(Ordering::SeqCst)
+    }
+
+    /// ライブラリパスが PC か DB のどちらかに存在するか
+    pub fn exists(&self, path: &str) -> bool {
+        let path = path.trim_end_matches('/');
+        if path.is_empty() {
+            return true;
+        }
+
+        if self.pc_lib.join(path).exists() {
+            return true;
+        }
+
+        // バイト順に並んでいるので、path 以上の最初の要素だけ見ればよい
+        let dir_prefix = format!("{path}/");
+        let db_paths = self.db_paths.lock();
+        let index = db_paths.partition_point(|p| p.as_str() < path);
+        db_paths[index..]
+            .iter()
+            .take_while(|p| p.starts_with(path))
+            .any(|p| p == path || p.starts_with(&dir_prefix))
+    }
+}
@@ -0,0 +1,89 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+
+use crate::config;
+
+/// 1 ファイルの最大サイズ。超えたら新しいファイルに切り替える
+const MAX_FILE_BYTES: u64 = 5 * 1024 * 1024;
+
+/// 切り替え後に残す古いファイルの数
+const KEPT_OLD_FILES: usize = 5;
+
+/// コンソールの出力を保存するログファイル
+///
+/// データディレクトリの logs/console.log に追記し、
+/// 大きくなったら console.1.log, console.2.log, ... と順に古いものへずらす。
+pub struct LogFile {
+    dir: PathBuf,
+    file: File,
+    size: u64,
+}
+
+impl LogFile {
+    pub fn open_default() -> anyhow::Result<Self> {
+        Self::open(&config::data_dir()?.join("logs"))
+    }
+
+    pub fn open(dir: &Path) -> anyhow::Result<Self> {
+        fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+
+        let path = current_path(dir);
+        let file =
+            open_append(&path).with_context(|| format!("Failed to open {}", path.display()))?;
+        let size = file.metadata()?.len();
+
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            file,
+            size,
+        })
+    }
+
+    /// 1 行追記する
+    ///
+    /// 異常終了しても残るよう、行ごとにファイルへ書き込む。
+    pub fn write_line(&mut self, line: &str) -> io::Result<()> {
+        let bytes = line.len() as u64 + 1;
+        if self.size > 0 && self.size + bytes > MAX_FILE_BYTES {
+            self.rotate()?;
+        }
+
+        writeln!(self.file, "{line}")?;
+        self.size += bytes;
+
+        Ok(())
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        let _ = fs::remove_file(old_path(&self.dir, KEPT_OLD_FILES));
+        for n in (1..KEPT_OLD_FILES).rev() {
+            let from = old_path(&self.dir, n);
+            if from.exists() {
+                fs::rename(from, old_path(&self.dir, n + 1))?;
+            }
+        }
+        fs::rename(current_path(&self.dir), old_path(&self.dir, 1))?;
+
+        self.file = open_append(&current_path(&self.dir))?;
+        self.size = 0;
+
+        Ok(())
+    }
+}
+
+fn current_path(dir: &Path) -> PathBuf {
+    dir.join("console.log")
+}
+
+fn old_path(dir: &Path, n: usize) -> PathBuf {
+    dir.join(format!("console.{n}.log"))
+}
+
+fn open_append(path: &Path) -> io::Result<File> {
+    OpenOptions::new().create(true).append(true).open(path)
+}
@@ -1,23 +1,42 @@
-use eframe::egui::{self, RichText};
+use std::{path::PathBuf, sync::Arc};
 
-use crate::legacy_commands::command_pages::{
-    CommandPage, PageAdd, PageCheck, PageMove, PagePlaylist, PageRemove, PageType,
+use eframe::egui::{self, RichText, mutex::Mutex};
+use murack_core_app::Config;
+use sqlx::PgPool;
+
+use crate::{
+    legacy_commands::{
+        check_report::CheckReport,
+        command_pages::{
+            CommandPage, PageAdd, PageCheck, PageMove, PagePlaylist, PageRemove, PageType,
+        },
+    },
+    sync_settings::SyncSettings,
 };
 
 pub struct LegacyCommandsNavigation {
     pub current_page: Box<dyn CommandPage>,
+    context: PageContext,
+}
+
+/// ページの作成時に渡す、ページを切り替えても保持する情報
+struct PageContext {
+    settings: Arc<SyncSettings>,
+    check_report: Arc<Mutex<CheckReport>>,
+    config: Arc<Config>,
+    /// `config` を読み込んだ設定ファイル
+    config_path: PathBuf,
+    db_pool: Arc<PgPool>,
+    /// プレイリストの選択を保存するプロファイル
+    profile: String,
 }
 
 impl LegacyCommandsNavigation {
-    pub fn show_tab(&mut self, ui: &mut egui::Ui, enabled: bool) {
+    pub fn show_tab(&mut self, ui: &mut egui::Ui) {
         let old_type = self.current_page.page_type();
         let mut current_type = old_type;
 
         ui.horizontal(|ui| {
-            if !enabled {
-                ui.disable();
-            }
-
             ui.selectable_value(&mut current_type, PageType::Add, button_text("add"));
             ui.selectable_value(
                 &mut current_type,
@@ -30,7 +49,7 @@ impl LegacyCommandsNavigation {
         });
 
         if old_type != current_type {
-            self.current_page = default_page_by_type(&current_type);
+            self.current_page = default_page_by_type(&current_type, &self.context);
         }
     }
 }
@@ -39,20 +58,42 @@ fn button_text(text: &str) -> RichText {
     RichText::new(text).heading()
 }
 
-impl Default for LegacyCommandsNavigation {
-    fn default() -> Self {
+impl LegacyCommandsNavigation {
+    pub fn new(
+        settings: Arc<SyncSettings>,
+        config: Arc<Config>,
+        config_path: PathBuf,
+        db_pool: Arc<PgPool>,
+        profile: String,
+    ) -> Self {
         Self {
             current_page: Box::new(PageAdd::default()),
+            context: PageContext {
+                settings,
+                check_report: Arc::default(),
+                config,
+                config_path,
+                db_pool,
+                profile,
+            },
         }
     }
 }
 
-pub fn default_page_by_type(page_type: &PageType) -> Box<dyn CommandPage> {
+fn default_page_by_type(page_type: &PageType, context: &PageContext) -> Box<dyn CommandPage> {
     match page_type {
         PageType::Add => Box::new(PageAdd::default()),
-        PageType::Playlist => Box::new(PagePlaylist {}),
+        PageType::Playlist => Box::new(PagePlaylist::new(
+            context.config.clone(),
+            context.config_path.clone(),
+            context.db_pool.clone(),
+            context.profile.clone(),
+        )),
         PageType::Move => Box::new(PageMove::default()),
         PageType::Remove => Box::new(PageRemove::default()),
-        PageType::Check => Box::new(PageCheck::default()),
+        PageType::Check => Box::new(PageCheck::new(
+            context.settings.check_resolve_policy,
+            context.check_report.clone(),
+        )),
     }
 }
@@ -0,0 +1,306 @@
+use std::{collections::BTreeSet, sync::Arc};
+
+use anyhow::anyhow;
+use eframe::egui::{self, mutex::Mutex};
+use murack_core_app::command::{CommandAddArgs, CommandMoveArgs};
+use murack_core_domain::NonEmptyString;
+use serde_json::json;
+
+use crate::legacy_commands::{
+    command_pages::CommandRunner,
+    console::Console,
+    dry_run,
+    operation_journal::{OperationJournal, OperationRecord},
+};
+
+/// 一覧に表示する操作履歴の件数
+const HISTORY_LIMIT: i64 = 30;
+
+/// 操作履歴の一覧と、直前の操作の取り消し
+pub struct OperationHistory {
+    journal: Arc<OperationJournal>,
+    /// 読み込めなかったことを知らせるコンソール
+    console: Arc<Mutex<Console>>,
+    records: Arc<Mutex<Vec<OperationRecord>>>,
+    /// 取り消しのジョブを追加済みで、まだ終わっていない操作の ID
+    undoing: Arc<Mutex<BTreeSet<i64>>>,
+    /// 取り消しできなかった理由などのメッセージ
+    status: Option<String>,
+}
+
+impl OperationHistory {
+    pub fn new(journal: Arc<OperationJournal>, console: Arc<Mutex<Console>>) -> Self {
+        let history = Self {
+            journal,
+            console,
+            records: Arc::default(),
+            undoing: Arc::default(),
+            status: None,
+        };
+        history.reload();
+        history
+    }
+
+    /// 操作履歴を読み込み直す
+    pub fn reload(&self) {
+        let journal = self.journal.clone();
+        let records = self.records.clone();
+        let console = self.console.clone();
+
+        tokio::spawn(async move {
+            match journal.recent(HISTORY_LIMIT).await {
+                Ok(recent) => *records.lock() = recent,
+                Err(e) => console
+                    .lock()
+                    .add_error(format!("操作履歴を読み込めませんでした: {e}")),
+            }
+        });
+    }
+
+    /// 操作履歴を表示
+    ///
+    /// 取り消しが要求されたら、取り消しのジョブを返す。
+    pub fn show(&mut self, ui: &mut egui::Ui) -> Option<(String, CommandRunner)> {
+        let mut undo_job = None;
+
+        egui::CollapsingHeader::new("操作履歴")
+            .default_open(false)
+            .show(ui, |ui| {
+                ui.horizontal(|ui| {
+                    if ui.button("最後の操作を取り消す").clicked() {
+                        match self.undo_last() {
+                            Ok(job) => {
+                                self.status = None;
+                                undo_job = Some(job);
+                            }
+                            Err(e) => self.status = Some(format!("{e:#}")),
+                        }
+                    }
+                    if ui.button("再読み込み").clicked() {
+                        self.reload();
+                    }
+                    if let Some(status) = &self.status {
+                        ui.label(status);
+                    }
+                });
+
+                egui::ScrollArea::vertical()
+                    .id_salt("operation_history")
+                    .max_height(160.0)
+                    .show(ui, |ui| {
+                        let undoing = self.undoing.lock();
+                        for record in &*self.records.lock() {
+                            show_record(ui, record, undoing.contains(&record.id));
+                        }
+                    });
+            });
+
+        undo_job
+    }
+
+    /// 最後に成功した未取り消しの操作を取り消すジョブを作成
+    ///
+    /// 同じ操作を二重に取り消さないよう、ジョブが終わるまでその操作は取り消し中にする。
+    fn undo_last(&self) -> anyhow::Result<(String, CommandRunner)> {
+        let records = self.records.lock();
+        let record = records
+            .iter()
+            .find(|record| record.succeeded() && !record.undone && record.command != "undo")
+            .ok_or_else(|| anyhow!("取り消しできる操作がありません"))?;
+
+        if !self.undoing.lock().insert(record.id) {
+            return Err(anyhow!(
+                "#{} の取り消しは実行待ちまたは実行中です",
+                record.id
+            ));
+        }
+        let guard = UndoInProgress {
+            undoing: self.undoing.clone(),
+            id: record.id,
+        };
+
+        let (summary, runner) = undo_job(record)?;
+        let records = self.records.clone();
+        let runner: CommandRunner = Box::new(move |di_registry| {
+            let handle = runner(di_registry);
+            tokio::spawn(async move {
+                let result = handle.await?;
+                // 一覧を読み込み直すまでの間に、同じ操作を再び取り消さないようにする
+                if result.is_ok() {
+                    let mut records = records.lock();
+                    if let Some(record) = records.iter_mut().find(|r| r.id == guard.id) {
+                        record.undone = true;
+                    }
+                }
+                drop(guard);
+                result
+            })
+        });
+
+        Ok((summary, runner))
+    }
+}
+
+/// 取り消し中の操作の印
+///
+/// ジョブが終わった時のほか、実行されずにキューから取り除かれた時にも外す。
+struct UndoInProgress {
+    undoing: Arc<Mutex<BTreeSet<i64>>>,
+    id: i64,
+}
+
+impl Drop for UndoInProgress {
+    fn drop(&mut self) {
+        self.undoing.lock().remove(&self.id);
+    }
+}
+
+fn show_record(ui: &mut egui::Ui, record: &OperationRecord, undoing: bool) {
+    let executed_at = chrono::DateTime::parse_from_rfc3339(&record.executed_at)
+        .map(|t| t.format("%Y-%m-%d %H:%M:%S").to_string())
+        .unwrap_or_else(|_| record.executed_at.clone());
+
+    let mut text = format!(
+        "#{} {executed_at} {} {}",
+        record.id,
+        record.command,
+        record.args_summary()
+    );
+    if !record.succeeded() {
+        text.push_str(" [失敗]");
+    }
+    if record.undone {
+        text.push_str(" [取り消し済み]");
+    } else if undoing {
+        text.push_str(" [取り消し中]");
+    }
+
+    let effects = record.effects_list();
+    let mut hover = match &record.error {
+        Some(error) => format!("エラー: {error}\n"),
+        None => String::new(),
+    };
+    if effects.is_empty() {
+        hover.push_str("変更なし");
+    } else {
+        hover.push_str(&effects.join("\n"));
+    }
+
+    let label = if record.undone {
+        egui::RichText::new(text).weak()
+    } else {
+        egui::RichText::new(text)
+    };
+    ui.label(label).on_hover_text(hover);
+}
+
+/// 操作を取り消すジョブを作成
+///
+/// move は逆方向の move で戻す。
+/// remove は PC のファイルがゴミ箱から戻されていれば、add で登録し直す。
+/// 再生回数などの DB のみにある情報は戻らない。
+fn undo_job(record: &OperationRecord) -> anyhow::Result<(String, CommandRunner)> {
+    let args = record.args_json();
+    let arg = |key: &str| -> anyhow::Result<String> {
+        args[key]
+            .as_str()
+            .map(str::to_owned)
+            .ok_or_else(|| anyhow!("#{} の記録に {key} がありません", record.id))
+    };
+    let id = record.id;
+
+    match record.command.as_str() {
+        "move" => {
+            let src_path = arg("src_path")?;
+            let dest_path = arg("dest_path")?;
+            let summary = format!("undo #{id}: move {dest_path} → {src_path}");
+
+            Ok((summary, undo_move_runner(id, dest_path, src_path)))
+        }
+        "remove" => {
+            let path = arg("path")?;
+            let summary = format!("undo #{id}: add {path}");
+
+            Ok((summary, undo_remove_runner(id, path)))
+        }
+        command => Err(anyhow!("#{id} の {command} は取り消しできません")),
+    }
+}
+
+fn undo_move_runner(id: i64, src_path: String, dest_path: String) -> CommandRunner {
+    Box::new(move |di_registry| {
+        tokio::spawn(async move {
+            let src = non_empty(&src_path)?;
+            let dest = non_empty(&dest_path)?;
+
+            let config = di_registry.config();
+            if config.pc_lib.join(&dest_path).exists() {
+                return Err(anyhow!(
+                    "{dest_path} が既に存在するため、移動を取り消せません"
+                ));
+            }
+
+            let db_pool = di_registry.db_pool();
+            let plan = dry_run::plan_move(&config, &db_pool, &src_path, &dest_path).await?;
+
+            let command = di_registry.command_move(CommandMoveArgs {
+                src_path: src,
+                dest_path: dest,
+            });
+
+            let journal = di_registry.journal();
+            journal
+                .run_planned(
+                    "undo",
+                    json!({ "operation_id": id, "src_path": src_path, "dest_path": dest_path }),
+                    &plan,
+                    &db_pool,
+                    command.run(&db_pool),
+                )
+                .await?;
+            journal.mark_undone(id).await?;
+
+            Ok(())
+        })
+    })
+}
+
+fn undo_remove_runner(id: i64, path: String) -> CommandRunner {
+    Box::new(move |di_registry| {
+        tokio::spawn(async move {
+            let track_path = non_empty(&path)?;
+
+            let config = di_registry.config();
+            if !config.pc_lib.join(&path).exists() {
+                return Err(anyhow!(
+                    "PC に {path} がありません。ゴミ箱から元の場所に戻してから、再度取り消してください"
+                ));
+            }
+
+            let db_pool = di_registry.db_pool();
+            let plan = dry_run::plan_add(&config, &db_pool, &path).await?;
+
+            let command = di_registry.command_add(CommandAddArgs { path: track_path });
+
+            let journal = di_registry.journal();
+            journal
+                .run_planned(
+                    "undo",
+                    json!({ "operation_id": id, "path": path }),
+                    &plan,
+                    &db_pool,
+                    command.run(&db_pool),
+                )
+                .await?;
+            journal.mark_undone(id).await?;
+
+            Ok(())
+        })
+    })
+}
+
+fn non_empty(path: &str) -> anyhow::Result<NonEmptyString> {
+    path.to_owned()
+        .try_into()
+        .map_err(|_| anyhow!("記録されたパスが空です"))
+}
@@ -0,0 +1,186 @@
+use std::{future::Future, path::Path};
+
+use anyhow::Context;
+use eframe::egui::mutex::Mutex;
+use serde_json::Value;
+use sqlx::{
+    PgPool, SqlitePool,
+    sqlite::{SqliteConnectOptions, SqlitePoolOptions},
+};
+
+use crate::{config, legacy_commands::dry_run::DryRunPlan};
+
+/// 操作履歴 1 件
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct OperationRecord {
+    pub id: i64,
+    pub executed_at: String,
+    /// コマンド名 (add, move, remove, check, playlist)
+    pub command: String,
+    /// コマンドの引数 (JSON オブジェクト)
+    pub args: String,
+    /// コマンドの実行後に確認できた変更内容 (JSON の文字列配列)
+    pub effects: String,
+    /// 失敗した場合のエラーメッセージ
+    pub error: Option<String>,
+    /// この操作が取り消し済みか
+    pub undone: bool,
+}
+
+impl OperationRecord {
+    pub fn succeeded(&self) -> bool {
+        self.error.is_none()
+    }
+
+    pub fn args_json(&self) -> Value {
+        serde_json::from_str(&self.args).unwrap_or_default()
+    }
+
+    pub fn effects_list(&self) -> Vec<String> {
+        serde_json::from_str(&self.effects).unwrap_or_default()
+    }
+
+    /// 一覧表示用の引数の要約
+    pub fn args_summary(&self) -> String {
+        match self.args_json() {
+            Value::Object(map) => map
+                .iter()
+                .map(|(key, value)| match value {
+                    Value::String(s) => format!("{key}={s}"),
+                    _ => format!("{key}={value}"),
+                })
+                .collect::<Vec<_>>()
+                .join(" "),
+            _ => String::new(),
+        }
+    }
+}
+
+/// コマンドの実行内容を記録する操作履歴
+///
+/// 取り消しで別の DB や DAP に対して操作しないよう、プロファイルごとの SQLite ファイルに保存する。
+pub struct OperationJournal {
+    pool: SqlitePool,
+    /// まだ利用者に知らせていない、記録できなかった理由
+    failures: Mutex<Vec<String>>,
+}
+
+impl OperationJournal {
+    /// データディレクトリの journals/<プロファイル名>.sqlite を開く
+    ///
+    /// 以前の全プロファイル共通の journal.sqlite は、どのプロファイルの操作か分からないので使わない。
+    pub async fn open_profile(profile: &str) -> anyhow::Result<Self> {
+        let dir = config::data_dir()?.join("journals");
+        std::fs::create_dir_all(&dir)
+            .with_context(|| format!("Failed to create {}", dir.display()))?;
+
+        Self::open(&dir.join(format!("{profile}.sqlite"))).await
+    }
+
+    pub async fn open(path: &Path) -> anyhow::Result<Self> {
+        let options = SqliteConnectOptions::new()
+            .filename(path)
+            .create_if_missing(true);
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect_with(options)
+            .await
+            .with_context(|| format!("Failed to open {}", path.display()))?;
+
+        sqlx::query(
+            "CREATE TABLE IF NOT EXISTS operations (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                executed_at TEXT NOT NULL,
+                command TEXT NOT NULL,
+                args TEXT NOT NULL,
+                effects TEXT NOT NULL,
+                error TEXT,
+                undone BOOLEAN NOT NULL DEFAULT FALSE
+            )",
+        )
+        .execute(&pool)
+        .await?;
+
+        Ok(Self {
+            pool,
+            failures: Mutex::default(),
+        })
+    }
+
+    /// コマンドの実行結果を記録
+    ///
+    /// 記録に失敗してもコマンド自体の結果には影響させず、`take_failures` で知らせる。
+    pub async fn record(
+        &self,
+        command: &str,
+        args: Value,
+        effects: Vec<String>,
+        result: &anyhow::Result<()>,
+    ) {
+        let insert_result = sqlx::query(
+            "INSERT INTO operations (executed_at, command, args, effects, error) VALUES (?, ?, ?, ?, ?)",
+        )
+        .bind(chrono::Local::now().to_rfc3339())
+        .bind(command)
+        .bind(args.to_string())
+        .bind(Value::from(effects).to_string())
+        .bind(result.as_ref().err().map(|e| format!("{e:#}")))
+        .execute(&self.pool)
+        .await;
+
+        if let Err(e) = insert_result {
+            self.failures
+                .lock()
+                .push(format!("{command} を操作履歴に記録できませんでした: {e}"));
+        }
+    }
+
+    /// 記録できなかった理由を取り出す
+    ///
+    /// コマンドの実行後に呼び、GUI ならコンソール、CLI なら標準エラー出力に表示する。
+    pub fn take_failures(&self) -> Vec<String> {
+        std::mem::take(&mut *self.failures.lock())
+    }
+
+    /// コマンドを実行し、その結果と実行予定のうち確認できた変更を記録
+    pub async fn run_planned(
+        &self,
+        command: &str,
+        args: Value,
+        plan: &DryRunPlan,
+        db_pool: &PgPool,
+        run: impl Future<Output = anyhow::Result<()>>,
+    ) -> anyhow::Result<()> {
+        let result = run.await;
+        let effects = match plan.confirmed_effects(db_pool, result.is_ok()).await {
+            Ok(effects) => effects,
+            Err(e) => {
+                // DB の変更は確認できないので、ファイルの変更だけを記録する
+                self.failures.lock().push(format!(
+                    "{command} による DB の変更を確認できないため、操作履歴にはファイルの変更だけを記録します: {e}"
+                ));
+                plan.confirmed_file_effects(result.is_ok())
+            }
+        };
+        self.record(command, args, effects, &result).await;
+        result
+    }
+
+    /// 新しい順に操作履歴を取得
+    pub async fn recent(&self, limit: i64) -> sqlx::Result<Vec<OperationRecord>> {
+        sqlx::query_as("SELECT * FROM operations ORDER BY id DESC LIMIT ?")
+            .bind(limit)
+            .fetch_all(&self.pool)
+            .await
+    }
+
+    /// 操作を取り消し済みにする
+    pub async fn mark_undone(&self, id: i64) -> sqlx::Result<()> {
+        sqlx::query("UPDATE operations SET undone = TRUE WHERE id = ?")
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+}
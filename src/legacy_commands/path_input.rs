@@ -0,0 +1,163 @@
+use eframe::egui::{self, PopupCloseBehavior};
+
+use crate::legacy_commands::library_path_source::{LibraryPathSource, PathCandidate};
+
+/// ライブラリパスの入力欄
+///
+/// 入力中のディレクトリ直下の候補をポップアップで表示し、
+/// 存在しないパスが入力されていれば警告を表示する。
+#[derive(Default)]
+pub struct PathInput {
+    text: String,
+    /// 存在しないパスを許容するか (移動先など)
+    allow_missing: bool,
+
+    /// 補完候補のキャッシュ (ディレクトリ, 候補)
+    candidates_cache: Option<(String, Vec<PathCandidate>)>,
+    /// 存在確認のキャッシュ (入力内容, DB の曲パスの世代, 存在するか)
+    exists_cache: Option<(String, u64, bool)>,
+}
+
+impl PathInput {
+    /// 存在しないパスの入力を許容する入力欄を作成
+    pub fn allowing_missing() -> Self {
+        Self {
+            allow_missing: true,
+            ..Default::default()
+        }
+    }
+
+    /// 入力されたライブラリパス
+    ///
+    /// 候補選択で付いた末尾の `/` は取り除く。
+    pub fn value(&self) -> String {
+        self.text.trim().trim_end_matches('/').to_owned()
+    }
+
+    pub fn show(&mut self, ui: &mut egui::Ui, label: &str, source: &LibraryPathSource) {
+        ui.horizontal(|ui| {
+            ui.label(label);
+
+            let response = ui.text_edit_singleline(&mut self.text);
+            let popup_id = response.id.with("path_candidates");
+
+            if response.gained_focus() {
+                // フォーカスし直したら最新の状態から候補を作り直す
+                self.candidates_cache = None;
+            }
+            if response.gained_focus() || response.changed() {
+                ui.memory_mut(|memory| memory.open_popup(popup_id));
+            }
+
+            let mut keep_focus = false;
+            egui::popup_below_widget(
+                ui,
+                popup_id,
+                &response,
+                PopupCloseBehavior::CloseOnClickOutside,
+                |ui| {
+                    ui.set_min_width(response.rect.width());
+                    keep_focus = self.show_candidates(ui, source);
+                },
+            );
+
+            if keep_focus {
+                // ディレクトリを選んだら、続けてその中を選べるようにする
+                response.request_focus();
+                ui.memory_mut(|memory| memory.open_popup(popup_id));
+            }
+
+            if !self.allow_missing && !self.text.trim().is_empty() && !self.exists(source) {
+                ui.colored_label(egui::Color32::LIGHT_RED, "⚠ 存在しないパスです");
+            }
+        });
+    }
+
+    /// 入力されたパスが存在するか
+    ///
+    /// 毎フレーム呼ばれるので、入力内容と DB の曲パスが変わらない間は前回の結果を使う。
+    fn exists(&mut self, source: &LibraryPathSource) -> bool {
+        let generation = source.generation();
+        let text = self.text.trim();
+        if let Some((cached_text, cached_generation, exists)) = &self.exists_cache {
+            if cached_text == text && *cached_generation == generation {
+                return *exists;
+            }
+        }
+
+        // value() と同じく、前後の空白を除いたパスを確かめる
+        let exists = source.exists(text);
+        self.exists_cache = Some((text.to_owned(), generation, exists));
+        exists
+    }
+
+    /// 補完候補の一覧を表示
+    ///
+    /// ディレクトリが選ばれたら true を返す。
+    fn show_candidates(&mut self, ui: &mut egui::Ui, source: &LibraryPathSource) -> bool {
+        let text = self.text.trim().to_owned();
+        let (dir, name_prefix) = match text.rfind('/') {
+            Some(i) => text.split_at(i + 1),
+            None => ("", text.as_str()),
+        };
+
+        let cached = self
+            .candidates_cache
+            .as_ref()
+            .is_some_and(|(cached_dir, _)| cached_dir == dir);
+        if !cached {
+            self.candidates_cache = Some((dir.to_owned(), source.children(dir)));
+        }
+        let Some((_, candidates)) = &self.candidates_cache else {
+            return false;
+        };
+
+        let mut selected = None;
+
+        egui::ScrollArea::vertical()
+            .max_height(240.0)
+            .show(ui, |ui| {
+                if !dir.is_empty() && ui.selectable_label(false, "📁 ..").clicked() {
+                    // 1 つ上のディレクトリへ
+                    let parent = dir.trim_end_matches('/');
+                    let parent = match parent.rfind('/') {
+                        Some(i) => &parent[..=i],
+                        None => "",
+                    };
+                    selected = Some((parent.to_owned(), true));
+                }
+
+                let mut shown_any = false;
+                for candidate in candidates
+                    .iter()
+                    .filter(|c| c.name.starts_with(name_prefix))
+                {
+                    shown_any = true;
+
+                    let icon = if candidate.is_dir { "📁" } else { "🎵" };
+                    if ui
+                        .selectable_label(false, format!("{icon} {}", candidate.name))
+                        .clicked()
+                    {
+                        let mut path = format!("{dir}{}", candidate.name);
+                        if candidate.is_dir {
+                            path.push('/');
+                        }
+                        selected = Some((path, candidate.is_dir));
+                    }
+                }
+
+                if !shown_any {
+                    ui.weak("候補なし");
+                }
+            });
+
+        match selected {
+            Some((path, is_dir)) => {
+                self.text = path;
+                is_dir
+            }
+            None => false,
+        }
+    }
+}
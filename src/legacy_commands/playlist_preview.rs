@@ -0,0 +1,603 @@
+use std::{
+    collections::{BTreeMap, BTreeSet, HashSet},
+    fmt::Arguments,
+    path::{Component, Path, PathBuf},
+    sync::Arc,
+};
+
+use anyhow::{Context, anyhow};
+use eframe::egui::{self, mutex::Mutex};
+use murack_core_app::{Config, command::CommandPlaylist, cui::Cui};
+use sqlx::PgPool;
+
+use crate::legacy_commands::{
+    console::Console,
+    playlist_selection::PlaylistSelection,
+    sandbox::{self, SandboxDb},
+};
+
+/// DAP に書き込むプレイリストのファイル 1 件の、DAP 上のファイルとの差分
+///
+/// 書き込まれる内容は、playlist コマンドを試しに実行して求める。
+#[derive(Debug, Clone)]
+pub struct PlaylistDiff {
+    /// プレイリストフォルダからの相対パス
+    pub file: PathBuf,
+    /// DAP に追加される曲のライブラリパス
+    pub added: Vec<String>,
+    /// DAP から消える曲のライブラリパス
+    pub removed: Vec<String>,
+    /// 書き込まれる曲数
+    pub new_count: usize,
+    /// DAP 上の曲数 (ファイルがなければ None)
+    pub device_count: Option<usize>,
+}
+
+impl PlaylistDiff {
+    /// 書き込まれる曲と DAP 上の曲を比べる
+    pub fn new(file: PathBuf, new_paths: &[String], device_paths: Option<&[String]>) -> Self {
+        let new_set: HashSet<&String> = new_paths.iter().collect();
+        let device_set: HashSet<&String> = device_paths.iter().copied().flatten().collect();
+
+        Self {
+            added: new_paths
+                .iter()
+                .filter(|path| !device_set.contains(path))
+                .cloned()
+                .collect(),
+            removed: device_paths
+                .iter()
+                .copied()
+                .flatten()
+                .filter(|path| !new_set.contains(path))
+                .cloned()
+                .collect(),
+            new_count: new_paths.len(),
+            device_count: device_paths.map(<[String]>::len),
+            file,
+        }
+    }
+
+    pub fn name(&self) -> String {
+        self.file.display().to_string()
+    }
+
+    pub fn is_unchanged(&self) -> bool {
+        self.device_count.is_some() && self.added.is_empty() && self.removed.is_empty()
+    }
+
+    /// DAP 上では曲があるのに、空のプレイリストで上書きされるか
+    pub fn empties(&self) -> bool {
+        self.new_count == 0 && self.device_count.is_some_and(|count| count > 0)
+    }
+
+    pub fn describe(&self) -> String {
+        match self.device_count {
+            None => format!("新規 ({} 曲)", self.new_count),
+            Some(_) if self.is_unchanged() => "変更なし".to_owned(),
+            Some(device_count) => format!(
+                "+{} / -{} ({} 曲 → {} 曲)",
+                self.added.len(),
+                self.removed.len(),
+                device_count,
+                self.new_count
+            ),
+        }
+    }
+}
+
+/// DAP に書き込まれるプレイリストと、DAP 上のファイルを比べる
+///
+/// スマートプレイリストの曲は DB に保存されている曲と違うことがあるので、
+/// playlist コマンドを試しに実行した結果と比べる。
+/// 試しに実行するときは、ファイルは全て一時フォルダに書き出し、DB の変更は取り消す。
+pub async fn preview(
+    config: &Config,
+    config_path: &Path,
+    db_pool: &PgPool,
+    excluded_ids: &[i32],
+) -> anyhow::Result<Vec<PlaylistDiff>> {
+    let scratch = tempfile::Builder::new()
+        .prefix("murack-sync-playlist-preview-")
+        .tempdir()
+        .context("Failed to create a temporary directory")?;
+    let scratch_config = sandbox::scratch_config(config_path, scratch.path())?;
+
+    let sandbox = SandboxDb::connect(db_pool, excluded_ids).await?;
+    let command = CommandPlaylist {
+        config: &scratch_config,
+        cui: &SilentCui,
+    };
+    let result = command.run(sandbox.pool()).await;
+    sandbox.rollback().await?;
+    result.context("Failed to run the playlist command for the preview")?;
+
+    let new_files = read_playlist_files(&scratch_config.dap_playlist, &scratch_config.dap_lib)?;
+    let device_files = read_playlist_files(&config.dap_playlist, &config.dap_lib)?;
+
+    Ok(new_files
+        .iter()
+        .map(|(file, new_paths)| {
+            PlaylistDiff::new(
+                file.clone(),
+                new_paths,
+                device_files.get(file).map(Vec::as_slice),
+            )
+        })
+        .collect())
+}
+
+/// 出力を捨て、確認には答えない Cui
+struct SilentCui;
+
+impl Cui for SilentCui {
+    fn out(&self, _args: Arguments) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    fn outln(&self, _args: Arguments) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    fn err(&self, _args: Arguments) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    fn errln(&self, _args: Arguments) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    fn input_case(&self, _cases: &[char], message: &str) -> anyhow::Result<char> {
+        Err(anyhow!("プレビュー中は確認に答えられません: {message}"))
+    }
+}
+
+/// プレイリストフォルダ以下の m3u ファイルの曲 (フォルダからの相対パス → ライブラリパス)
+fn read_playlist_files(
+    dap_playlist: &Path,
+    dap_lib: &Path,
+) -> anyhow::Result<BTreeMap<PathBuf, Vec<String>>> {
+    playlist_files(dap_playlist)
+        .into_iter()
+        .map(|file| {
+            let paths = read_m3u(&dap_playlist.join(&file), dap_lib)?;
+            Ok((file, paths))
+        })
+        .collect()
+}
+
+/// プレイリストフォルダ以下の m3u ファイルの、フォルダからの相対パス
+fn playlist_files(dap_playlist: &Path) -> Vec<PathBuf> {
+    let mut files = vec![];
+    let mut dirs = vec![dap_playlist.to_path_buf()];
+
+    while let Some(dir) = dirs.pop() {
+        let Ok(entries) = std::fs::read_dir(&dir) else {
+            continue;
+        };
+
+        for path in entries.filter_map(|entry| entry.ok().map(|entry| entry.path())) {
+            if path.is_dir() {
+                dirs.push(path);
+                continue;
+            }
+
+            let is_m3u = path
+                .extension()
+                .and_then(|ext| ext.to_str())
+                .is_some_and(|ext| {
+                    ext.eq_ignore_ascii_case("m3u") || ext.eq_ignore_ascii_case("m3u8")
+                });
+            if let (true, Ok(file)) = (is_m3u, path.strip_prefix(dap_playlist)) {
+                files.push(file.to_path_buf());
+            }
+        }
+    }
+
+    files.sort();
+    files
+}
+
+/// m3u ファイルの曲を、ライブラリパスにして読み込む
+fn read_m3u(path: &Path, dap_lib: &Path) -> anyhow::Result<Vec<String>> {
+    let bytes =
+        std::fs::read(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    let base_dir = path.parent().unwrap_or(Path::new(""));
+
+    Ok(String::from_utf8_lossy(&bytes)
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| library_path(line, base_dir, dap_lib))
+        .collect())
+}
+
+/// m3u の 1 行をライブラリパスにする
+///
+/// 相対パスはプレイリストのあるフォルダから解決する。
+/// DAP のライブラリ外を指す行はそのまま返す。
+fn library_path(line: &str, base_dir: &Path, dap_lib: &Path) -> String {
+    let entry = PathBuf::from(line.replace('\\', "/"));
+    let absolute = normalize(&base_dir.join(entry));
+
+    match absolute.strip_prefix(dap_lib) {
+        Ok(relative) => relative.to_string_lossy().into_owned(),
+        Err(_) => line.to_owned(),
+    }
+}
+
+/// `.` と `..` を取り除く (シンボリックリンクは解決しない)
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            component => normalized.push(component),
+        }
+    }
+    normalized
+}
+
+/// 書き込みをスキップするプレイリストの、実行前の DAP 上のファイル
+///
+/// playlist コマンドは全てのプレイリストを書き出すので、実行後にこの内容へ戻す。
+/// 強制終了でタスクが破棄された場合も、破棄されるときに戻す。
+pub struct SkippedPlaylists {
+    dap_playlist: PathBuf,
+    /// まだ戻していないファイル
+    files: Vec<SkippedFile>,
+    /// 破棄されるときに戻せなかったことを知らせるコンソール
+    console: Arc<Mutex<Console>>,
+}
+
+struct SkippedFile {
+    /// プレイリストフォルダからの相対パス
+    file: PathBuf,
+    /// 実行前の内容 (なかったら None)
+    original: Option<Vec<u8>>,
+}
+
+impl SkippedPlaylists {
+    pub fn take(
+        dap_playlist: &Path,
+        files: &[PathBuf],
+        console: Arc<Mutex<Console>>,
+    ) -> anyhow::Result<Self> {
+        let files = files
+            .iter()
+            .map(|file| {
+                let path = dap_playlist.join(file);
+                let original = match std::fs::read(&path) {
+                    Ok(bytes) => Some(bytes),
+                    Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+                    Err(e) => {
+                        return Err(e)
+                            .with_context(|| format!("Failed to read {}", path.display()));
+                    }
+                };
+                Ok(SkippedFile {
+                    file: file.clone(),
+                    original,
+                })
+            })
+            .collect::<anyhow::Result<_>>()?;
+
+        Ok(Self {
+            dap_playlist: dap_playlist.to_path_buf(),
+            files,
+            console,
+        })
+    }
+
+    /// スキップしたプレイリストのファイルを実行前の状態に戻す
+    pub fn restore(mut self) -> anyhow::Result<()> {
+        self.restore_files()
+    }
+
+    /// 1 つ戻せなくても残りは戻し、最初のエラーを返す
+    fn restore_files(&mut self) -> anyhow::Result<()> {
+        let mut result = Ok(());
+
+        for file in std::mem::take(&mut self.files) {
+            let path = self.dap_playlist.join(&file.file);
+            let restored = match file.original {
+                Some(bytes) => std::fs::write(&path, bytes)
+                    .with_context(|| format!("Failed to write {}", path.display())),
+                None if path.exists() => std::fs::remove_file(&path)
+                    .with_context(|| format!("Failed to remove {}", path.display())),
+                None => Ok(()),
+            };
+            result = result.and(restored);
+        }
+
+        result
+    }
+}
+
+impl Drop for SkippedPlaylists {
+    fn drop(&mut self) {
+        if let Err(e) = self.restore_files() {
+            self.console.lock().add_error(format!(
+                "書き込みをスキップしたプレイリストを元に戻せませんでした: {e:#}"
+            ));
+        }
+    }
+}
+
+/// プレビューの実行状態
+#[derive(Default)]
+enum PreviewState {
+    #[default]
+    Idle,
+    Running,
+    Done {
+        /// プレビューしたときの除外の選択
+        excluded: BTreeSet<i32>,
+        diffs: Vec<PlaylistDiff>,
+        /// 書き込まないプレイリストのファイル
+        skipped: BTreeSet<PathBuf>,
+    },
+    Failed(String),
+}
+
+/// 実行前に、プレイリストごとの変更を確認して書き込むか選ぶ
+#[derive(Default)]
+pub struct PlaylistPreview {
+    state: Arc<Mutex<PreviewState>>,
+}
+
+impl PlaylistPreview {
+    /// スキップするプレイリストのファイル (プレイリストフォルダからの相対パス)
+    ///
+    /// プレビュー後に除外の選択を変えた場合は、何もスキップしない。
+    pub fn skipped_files(&self, selection: &PlaylistSelection) -> Vec<PathBuf> {
+        match &*self.state.lock() {
+            PreviewState::Done {
+                excluded, skipped, ..
+            } if *excluded == selection.excluded => skipped.iter().cloned().collect(),
+            _ => vec![],
+        }
+    }
+
+    pub fn show(
+        &self,
+        ui: &mut egui::Ui,
+        config: &Arc<Config>,
+        config_path: &Path,
+        db_pool: &Arc<PgPool>,
+        selection: &PlaylistSelection,
+    ) {
+        ui.horizontal(|ui| {
+            let running = matches!(&*self.state.lock(), PreviewState::Running);
+            if ui
+                .add_enabled(!running, egui::Button::new("プレビュー"))
+                .on_hover_text("DAP 上のプレイリストと比べます")
+                .clicked()
+            {
+                self.start(
+                    ui.ctx().clone(),
+                    config.clone(),
+                    config_path.to_path_buf(),
+                    db_pool.clone(),
+                    selection,
+                );
+            }
+            if running {
+                ui.spinner();
+            }
+        });
+
+        let mut state = self.state.lock();
+        match &mut *state {
+            PreviewState::Idle | PreviewState::Running => {}
+            PreviewState::Failed(message) => {
+                ui.colored_label(egui::Color32::LIGHT_RED, &*message);
+            }
+            PreviewState::Done { excluded, .. } if *excluded != selection.excluded => {
+                ui.weak("書き込むプレイリストの選択が変わりました。再度プレビューしてください");
+            }
+            PreviewState::Done { diffs, skipped, .. } => show_diffs(ui, diffs, skipped),
+        }
+    }
+
+    fn start(
+        &self,
+        ctx: egui::Context,
+        config: Arc<Config>,
+        config_path: PathBuf,
+        db_pool: Arc<PgPool>,
+        selection: &PlaylistSelection,
+    ) {
+        let state = self.state.clone();
+        let selection = selection.clone();
+        *state.lock() = PreviewState::Running;
+
+        tokio::spawn(async move {
+            let result = async {
+                let excluded_ids = selection.resolve(&db_pool).await?;
+                preview(&config, &config_path, &db_pool, &excluded_ids).await
+            }
+            .await;
+
+            *state.lock() = match result {
+                Ok(diffs) => {
+                    // 空になるプレイリストは、確認するまで書き込まない
+                    let skipped = diffs
+                        .iter()
+                        .filter(|diff| diff.empties())
+                        .map(|diff| diff.file.clone())
+                        .collect();
+                    PreviewState::Done {
+                        excluded: selection.excluded,
+                        diffs,
+                        skipped,
+                    }
+                }
+                Err(e) => PreviewState::Failed(format!("{e:#}")),
+            };
+            ctx.request_repaint();
+        });
+    }
+}
+
+fn show_diffs(ui: &mut egui::Ui, diffs: &[PlaylistDiff], skipped: &mut BTreeSet<PathBuf>) {
+    let changed = diffs.iter().filter(|diff| !diff.is_unchanged()).count();
+    ui.label(format!(
+        "変更あり {} 件 / 変更なし {} 件 / スキップ {} 件",
+        changed,
+        diffs.len() - changed,
+        skipped.len()
+    ));
+
+    egui::ScrollArea::vertical()
+        .id_salt("playlist_preview")
+        .max_height(300.0)
+        .show(ui, |ui| {
+            for diff in diffs {
+                ui.horizontal(|ui| {
+                    let mut write = !skipped.contains(&diff.file);
+                    if ui.checkbox(&mut write, diff.name()).changed() {
+                        if write {
+                            skipped.remove(&diff.file);
+                        } else {
+                            skipped.insert(diff.file.clone());
+                        }
+                    }
+
+                    if diff.empties() {
+                        ui.colored_label(egui::Color32::LIGHT_RED, "⚠ 空になります");
+                    }
+                    if diff.is_unchanged() {
+                        ui.weak(diff.describe());
+                    } else {
+                        ui.label(diff.describe());
+                    }
+                });
+
+                if !diff.added.is_empty() || !diff.removed.is_empty() {
+                    ui.indent(("playlist_diff", &diff.file), |ui| {
+                        egui::CollapsingHeader::new("曲の差分")
+                            .id_salt(("playlist_diff_tracks", &diff.file))
+                            .show(ui, |ui| {
+                                for path in &diff.added {
+                                    ui.colored_label(
+                                        egui::Color32::LIGHT_GREEN,
+                                        format!("+ {path}"),
+                                    );
+                                }
+                                for path in &diff.removed {
+                                    ui.colored_label(egui::Color32::LIGHT_RED, format!("- {path}"));
+                                }
+                            });
+                    });
+                }
+            }
+        });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn paths(paths: &[&str]) -> Vec<String> {
+        paths.iter().map(|path| (*path).to_owned()).collect()
+    }
+
+    #[test]
+    fn normalize_removes_dot_components() {
+        assert_eq!(
+            normalize(Path::new("/dap/playlists/./../music/a.flac")),
+            PathBuf::from("/dap/music/a.flac")
+        );
+    }
+
+    #[test]
+    fn library_path_resolves_relative_lines_from_the_playlist_folder() {
+        let base_dir = Path::new("/dap/playlists/folder");
+        let dap_lib = Path::new("/dap/music");
+
+        assert_eq!(
+            library_path("../../music/artist/a.flac", base_dir, dap_lib),
+            "artist/a.flac"
+        );
+        assert_eq!(
+            library_path("..\\..\\music\\artist\\a.flac", base_dir, dap_lib),
+            "artist/a.flac"
+        );
+        assert_eq!(
+            library_path("/dap/music/artist/a.flac", base_dir, dap_lib),
+            "artist/a.flac"
+        );
+    }
+
+    #[test]
+    fn library_path_keeps_lines_outside_the_library() {
+        assert_eq!(
+            library_path(
+                "/other/a.flac",
+                Path::new("/dap/playlists"),
+                Path::new("/dap/music")
+            ),
+            "/other/a.flac"
+        );
+    }
+
+    #[test]
+    fn diff_lists_added_and_removed_tracks() {
+        let device = paths(&["a", "b"]);
+        let diff = PlaylistDiff::new(
+            PathBuf::from("folder/list.m3u"),
+            &paths(&["b", "c"]),
+            Some(&device),
+        );
+
+        assert_eq!(diff.added, paths(&["c"]));
+        assert_eq!(diff.removed, paths(&["a"]));
+        assert_eq!(diff.describe(), "+1 / -1 (2 曲 → 2 曲)");
+        assert!(!diff.is_unchanged());
+        assert!(!diff.empties());
+    }
+
+    #[test]
+    fn diff_detects_a_playlist_emptied_on_the_device() {
+        let device = paths(&["a"]);
+        let diff = PlaylistDiff::new(PathBuf::from("list.m3u"), &[], Some(&device));
+
+        assert!(diff.empties());
+    }
+
+    #[test]
+    fn diff_without_a_device_file_is_new() {
+        let diff = PlaylistDiff::new(PathBuf::from("list.m3u"), &paths(&["a"]), None);
+
+        assert_eq!(diff.describe(), "新規 (1 曲)");
+        assert!(!diff.is_unchanged());
+        assert!(!diff.empties());
+    }
+
+    #[test]
+    fn skipped_playlists_are_restored_when_dropped() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("folder")).unwrap();
+        std::fs::write(dir.path().join("folder/list.m3u"), "old").unwrap();
+
+        let skipped = SkippedPlaylists::take(
+            dir.path(),
+            &[PathBuf::from("folder/list.m3u"), PathBuf::from("new.m3u")],
+            Arc::default(),
+        )
+        .unwrap();
+        std::fs::write(dir.path().join("folder/list.m3u"), "written").unwrap();
+        std::fs::write(dir.path().join("new.m3u"), "written").unwrap();
+        drop(skipped);
+
+        assert_eq!(
+            std::fs::read_to_string(dir.path().join("folder/list.m3u")).unwrap(),
+            "old"
+        );
+        assert!(!dir.path().join("new.m3u").exists());
+    }
+}
@@ -0,0 +1,272 @@
+use std::{collections::BTreeSet, future::Future};
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use sqlx::{PgPool, postgres::PgPoolOptions};
+
+use crate::config;
+
+/// DB のプレイリスト 1 件
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct PlaylistNode {
+    pub id: i32,
+    pub parent_id: Option<i32>,
+    pub name: String,
+    /// DB 上で DAP に保存する設定になっているか
+    pub save_dap: bool,
+}
+
+/// プレイリストを親子順に取得
+pub async fn fetch_playlists(db_pool: &PgPool) -> anyhow::Result<Vec<PlaylistNode>> {
+    let playlists = sqlx::query_as(
+        "SELECT id, parent_id, name, save_dap FROM playlists ORDER BY parent_id NULLS FIRST, in_folder_order",
+    )
+    .fetch_all(db_pool)
+    .await?;
+
+    Ok(playlists)
+}
+
+/// DAP に書き込まないプレイリスト・フォルダの選択
+///
+/// DAP ごとに容量が違うので、プロファイルごとに保存する。
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PlaylistSelection {
+    /// 除外するプレイリストの ID (フォルダならその中身も除外する)
+    pub excluded: BTreeSet<i32>,
+}
+
+impl PlaylistSelection {
+    /// プロファイルの選択を読み込む (なければ全て書き込む)
+    pub fn load(profile: &str) -> anyhow::Result<Self> {
+        let path = config::playlist_selection_path(profile)?;
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+
+        let text = std::fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        toml::from_str(&text).with_context(|| format!("Failed to parse {}", path.display()))
+    }
+
+    pub fn save(&self, profile: &str) -> anyhow::Result<()> {
+        let path = config::playlist_selection_path(profile)?;
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)
+                .with_context(|| format!("Failed to create {}", dir.display()))?;
+        }
+
+        let text = toml::to_string_pretty(self)?;
+        std::fs::write(&path, text).with_context(|| format!("Failed to write {}", path.display()))
+    }
+
+    /// 除外されているか (親のフォルダが除外されている場合も含む)
+    pub fn is_excluded(&self, playlists: &[PlaylistNode], id: i32) -> bool {
+        let mut current = Some(id);
+        while let Some(id) = current {
+            if self.excluded.contains(&id) {
+                return true;
+            }
+            current = playlists
+                .iter()
+                .find(|playlist| playlist.id == id)
+                .and_then(|playlist| playlist.parent_id);
+        }
+        false
+    }
+
+    /// 除外されるプレイリストの ID (フォルダの中身を含む)
+    pub fn excluded_ids(&self, playlists: &[PlaylistNode]) -> Vec<i32> {
+        playlists
+            .iter()
+            .filter(|playlist| self.is_excluded(playlists, playlist.id))
+            .map(|playlist| playlist.id)
+            .collect()
+    }
+
+    /// 現在の DB のプレイリストから、除外される ID を求める
+    pub async fn resolve(&self, db_pool: &PgPool) -> anyhow::Result<Vec<i32>> {
+        if self.excluded.is_empty() {
+            return Ok(vec![]);
+        }
+        let playlists = fetch_playlists(db_pool).await?;
+        Ok(self.excluded_ids(&playlists))
+    }
+
+    /// フォルダ (またはプレイリスト) とその中身を書き込む対象に戻す
+    pub fn include(&mut self, playlists: &[PlaylistNode], id: i32) {
+        self.excluded
+            .retain(|excluded| *excluded != id && !is_descendant(playlists, *excluded, id));
+    }
+}
+
+/// `id` が `ancestor` のフォルダの中にあるか
+fn is_descendant(playlists: &[PlaylistNode], id: i32, ancestor: i32) -> bool {
+    let parent_of = |id: i32| {
+        playlists
+            .iter()
+            .find(|playlist| playlist.id == id)
+            .and_then(|playlist| playlist.parent_id)
+    };
+
+    let mut current = parent_of(id);
+    while let Some(parent) = current {
+        if parent == ancestor {
+            return true;
+        }
+        current = parent_of(parent);
+    }
+    false
+}
+
+/// 除外したプレイリストを DAP に書き込まない設定にして `run` を実行する
+///
+/// playlist コマンドは DB の save_dap を見て書き込むプレイリストを決めるので、
+/// 除外したものの save_dap を false に見せる接続を `run` に渡す。
+/// DB の値は変えないので、他のクライアントや中止の影響を受けない。
+pub async fn run_with_exclusions<F, Fut>(
+    db_pool: &PgPool,
+    excluded_ids: &[i32],
+    run: F,
+) -> anyhow::Result<()>
+where
+    F: FnOnce(PgPool) -> Fut,
+    Fut: Future<Output = anyhow::Result<()>>,
+{
+    if excluded_ids.is_empty() {
+        return run(db_pool.clone()).await;
+    }
+
+    let view_sql = exclusion_view_sql(db_pool, excluded_ids).await?;
+    let pool = PgPoolOptions::new()
+        .after_connect(move |conn, _meta| {
+            let view_sql = view_sql.clone();
+            Box::pin(async move {
+                sqlx::Executor::execute(conn, view_sql.as_str()).await?;
+                Ok(())
+            })
+        })
+        .connect_with((*db_pool.connect_options()).clone())
+        .await
+        .context("Failed to connect to the DB")?;
+
+    let result = run(pool.clone()).await;
+    pool.close().await;
+    result
+}
+
+/// 除外したプレイリストの save_dap を false に見せる一時ビューを作る SQL
+///
+/// 一時ビューは同じ名前のテーブルより優先して参照され、接続を閉じると消える。
+pub async fn exclusion_view_sql(db_pool: &PgPool, excluded_ids: &[i32]) -> anyhow::Result<String> {
+    let (schema, columns): (String, Vec<String>) = sqlx::query_as(
+        "SELECT current_schema()::text, array_agg(column_name::text ORDER BY ordinal_position) FROM information_schema.columns WHERE table_schema = current_schema() AND table_name = 'playlists'",
+    )
+    .fetch_one(db_pool)
+    .await
+    .context("Failed to read the columns of playlists")?;
+
+    Ok(build_exclusion_view_sql(&schema, &columns, excluded_ids))
+}
+
+fn build_exclusion_view_sql(schema: &str, columns: &[String], excluded_ids: &[i32]) -> String {
+    let ids = excluded_ids
+        .iter()
+        .map(i32::to_string)
+        .collect::<Vec<_>>()
+        .join(",");
+    let select_list = columns
+        .iter()
+        .map(|column| match column.as_str() {
+            "save_dap" => {
+                format!("save_dap AND NOT (id = ANY(ARRAY[{ids}]::integer[])) AS save_dap")
+            }
+            _ => quote_identifier(column),
+        })
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    format!(
+        "CREATE TEMP VIEW playlists AS SELECT {select_list} FROM {}.playlists",
+        quote_identifier(schema)
+    )
+}
+
+fn quote_identifier(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn node(id: i32, parent_id: Option<i32>) -> PlaylistNode {
+        PlaylistNode {
+            id,
+            parent_id,
+            name: format!("playlist {id}"),
+            save_dap: true,
+        }
+    }
+
+    /// 1 ┬ 2 ─ 3
+    ///   └ 4
+    /// 5
+    fn tree() -> Vec<PlaylistNode> {
+        vec![
+            node(1, None),
+            node(2, Some(1)),
+            node(3, Some(2)),
+            node(4, Some(1)),
+            node(5, None),
+        ]
+    }
+
+    fn selection(excluded: &[i32]) -> PlaylistSelection {
+        PlaylistSelection {
+            excluded: excluded.iter().copied().collect(),
+        }
+    }
+
+    #[test]
+    fn excluding_a_folder_excludes_its_descendants() {
+        let playlists = tree();
+        let selection = selection(&[2]);
+
+        assert!(selection.is_excluded(&playlists, 3));
+        assert!(!selection.is_excluded(&playlists, 1));
+        assert!(!selection.is_excluded(&playlists, 4));
+        assert_eq!(selection.excluded_ids(&playlists), vec![2, 3]);
+    }
+
+    #[test]
+    fn include_also_includes_excluded_descendants() {
+        let playlists = tree();
+        let mut selection = selection(&[1, 3, 5]);
+
+        selection.include(&playlists, 1);
+
+        assert_eq!(selection.excluded, [5].into_iter().collect());
+    }
+
+    #[test]
+    fn include_keeps_exclusions_outside_the_folder() {
+        let playlists = tree();
+        let mut selection = selection(&[3, 4]);
+
+        selection.include(&playlists, 2);
+
+        assert_eq!(selection.excluded, [4].into_iter().collect());
+    }
+
+    #[test]
+    fn exclusion_view_masks_only_save_dap() {
+        let columns = ["id", "name", "save_dap"].map(str::to_owned);
+
+        assert_eq!(
+            build_exclusion_view_sql("public", &columns, &[3, 5]),
+            "CREATE TEMP VIEW playlists AS SELECT \"id\", \"name\", save_dap AND NOT (id = ANY(ARRAY[3,5]::integer[])) AS save_dap FROM \"public\".playlists"
+        );
+    }
+}
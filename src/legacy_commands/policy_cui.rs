@@ -0,0 +1,306 @@
+use std::{fmt::Arguments, str::FromStr};
+
+use anyhow::{Result, anyhow};
+use eframe::egui::mutex::Mutex;
+use murack_core_app::cui::Cui;
+use serde::{Deserialize, Serialize};
+
+use crate::legacy_commands::resolve_step::{ResolveStep, StepCui};
+
+/// check コマンドで齟齬が見つかった時の解決方針
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ResolvePolicy {
+    /// 毎回ユーザーに確認する
+    #[default]
+    Ask,
+    /// 常に PC の内容で DB を上書き
+    PcWins,
+    /// 常に DB の内容で PC を上書き
+    DbWins,
+    /// 全て解決せずに次へ
+    SkipAll,
+    /// 最初の齟齬で解決処理を中止
+    AbortOnFirst,
+}
+
+impl ResolvePolicy {
+    pub const ALL: [ResolvePolicy; 5] = [
+        ResolvePolicy::Ask,
+        ResolvePolicy::PcWins,
+        ResolvePolicy::DbWins,
+        ResolvePolicy::SkipAll,
+        ResolvePolicy::AbortOnFirst,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            ResolvePolicy::Ask => "毎回確認",
+            ResolvePolicy::PcWins => "常にPCからDBへ上書き",
+            ResolvePolicy::DbWins => "常にDBからPCへ上書き",
+            ResolvePolicy::SkipAll => "全て解決せずに次へ",
+            ResolvePolicy::AbortOnFirst => "最初の齟齬で中止",
+        }
+    }
+
+    /// 選択肢の中から、この方針で自動的に選ぶものを決める
+    ///
+    /// 方針に対応する選択肢がなければ、別の選択肢で代用せずに None (ユーザーに確認する)。
+    /// 最初の齟齬で中止する方針で中止の選択肢がなければ、エラーにしてコマンドを中止する。
+    fn choose(&self, cases: &[char]) -> Result<Option<char>> {
+        let preferred = match self {
+            ResolvePolicy::Ask => return Ok(None),
+            ResolvePolicy::PcWins => '1',
+            ResolvePolicy::DbWins => '2',
+            ResolvePolicy::SkipAll => '0',
+            ResolvePolicy::AbortOnFirst => '-',
+        };
+
+        if cases.contains(&preferred) {
+            return Ok(Some(preferred));
+        }
+        if *self == ResolvePolicy::AbortOnFirst {
+            return Err(anyhow!(
+                "齟齬が見つかったため中止しました ({})",
+                self.label()
+            ));
+        }
+        Ok(None)
+    }
+}
+
+impl FromStr for ResolvePolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "ask" => Ok(ResolvePolicy::Ask),
+            "pc_wins" => Ok(ResolvePolicy::PcWins),
+            "db_wins" => Ok(ResolvePolicy::DbWins),
+            "skip_all" => Ok(ResolvePolicy::SkipAll),
+            "abort_on_first" => Ok(ResolvePolicy::AbortOnFirst),
+            _ => Err(anyhow!(
+                "unknown policy: {s} (ask, pc_wins, db_wins, skip_all, abort_on_first)"
+            )),
+        }
+    }
+}
+
+/// 選択肢 1 回分の決定内容
+#[derive(Debug, Clone)]
+pub struct Decision {
+    /// 確認元の解決処理 (分からない場合は None)
+    pub step: Option<ResolveStep>,
+    /// 確認で選べた選択肢
+    pub cases: Vec<char>,
+    pub message: String,
+    pub choice: char,
+    /// 方針に従って自動で選んだか
+    pub automatic: bool,
+}
+
+impl Decision {
+    /// 操作履歴に記録する変更内容 (実際に解決した場合のみ)
+    pub fn effect(&self) -> Option<String> {
+        let direction = match self.choice {
+            '1' => "PC→DB",
+            '2' => "DB→PC",
+            _ => return None,
+        };
+        let message = self.message.lines().next().unwrap_or_default();
+        Some(format!("{direction}: {message}"))
+    }
+}
+
+/// 特定の確認 1 件だけに適用する解決方針
+#[derive(Debug, Clone, Copy)]
+pub struct ResolveTarget {
+    pub step: ResolveStep,
+    /// その解決処理の確認のうち、何件目 (0 始まり) か
+    pub ordinal: usize,
+    pub policy: ResolvePolicy,
+}
+
+/// 解決方針に従って `input_case` に自動で答える Cui
+///
+/// 出力は内側の Cui にそのまま渡す。自動で選べない選択肢は内側の Cui で確認する。
+pub struct PolicyCui<'cui, C: StepCui> {
+    inner: &'cui C,
+    policy: ResolvePolicy,
+    /// `policy` の代わりに別の方針を適用する確認
+    target: Option<ResolveTarget>,
+    decisions: Mutex<Vec<Decision>>,
+}
+
+impl<'cui, C: StepCui> PolicyCui<'cui, C> {
+    pub fn new(inner: &'cui C, policy: ResolvePolicy) -> Self {
+        Self {
+            inner,
+            policy,
+            target: None,
+            decisions: Mutex::default(),
+        }
+    }
+
+    /// `target` の確認だけ、その方針で答える
+    pub fn with_target(mut self, target: ResolveTarget) -> Self {
+        self.target = Some(target);
+        self
+    }
+
+    pub fn decisions(&self) -> Vec<Decision> {
+        self.decisions.lock().clone()
+    }
+
+    pub fn decision_count(&self) -> usize {
+        self.decisions.lock().len()
+    }
+
+    /// `start` 件目以降の決定内容
+    pub fn decisions_since(&self, start: usize) -> Vec<Decision> {
+        self.decisions.lock()[start..].to_vec()
+    }
+
+    /// この確認に適用する方針
+    fn policy_for(&self, step: Option<ResolveStep>) -> ResolvePolicy {
+        let Some(target) = self.target else {
+            return self.policy;
+        };
+        if step != Some(target.step) {
+            return self.policy;
+        }
+
+        let ordinal = self
+            .decisions
+            .lock()
+            .iter()
+            .filter(|decision| decision.step == step)
+            .count();
+        if ordinal == target.ordinal {
+            target.policy
+        } else {
+            self.policy
+        }
+    }
+
+    /// 自動で選んだ件数の要約を内側の Cui に出力
+    pub fn output_summary(&self) -> Result<()> {
+        let decisions = self.decisions.lock();
+        let count = |choice: char| {
+            decisions
+                .iter()
+                .filter(|d| d.automatic && d.choice == choice)
+                .count()
+        };
+
+        let automatic_count = decisions.iter().filter(|d| d.automatic).count();
+        if automatic_count == 0 {
+            return Ok(());
+        }
+
+        self.inner.outln(format_args!(
+            "自動解決 ({}): {automatic_count} 件 (PC→DB: {}, DB→PC: {}, スキップ: {}, 中止: {})",
+            self.policy.label(),
+            count('1'),
+            count('2'),
+            count('0'),
+            count('-'),
+        ))
+    }
+}
+
+impl<C: StepCui> Cui for PolicyCui<'_, C> {
+    fn out(&self, args: Arguments) -> anyhow::Result<()> {
+        self.inner.out(args)
+    }
+
+    fn outln(&self, args: Arguments) -> anyhow::Result<()> {
+        self.inner.outln(args)
+    }
+
+    fn err(&self, args: Arguments) -> anyhow::Result<()> {
+        self.inner.err(args)
+    }
+
+    fn errln(&self, args: Arguments) -> anyhow::Result<()> {
+        self.inner.errln(args)
+    }
+
+    fn input_case(&self, cases: &[char], message: &str) -> Result<char> {
+        self.decide(None, cases, message)
+    }
+}
+
+impl<C: StepCui> StepCui for PolicyCui<'_, C> {
+    fn input_case_in(&self, step: ResolveStep, cases: &[char], message: &str) -> Result<char> {
+        self.decide(Some(step), cases, message)
+    }
+}
+
+impl<C: StepCui> PolicyCui<'_, C> {
+    /// 方針に従って選び、選べなければ内側の Cui で確認する
+    fn decide(&self, step: Option<ResolveStep>, cases: &[char], message: &str) -> Result<char> {
+        let policy = self.policy_for(step);
+        let chosen = policy.choose(cases).or_else(|e| {
+            self.inner.outln(format_args!("{message}"))?;
+            Err(e)
+        })?;
+        let (choice, automatic) = match chosen {
+            Some(choice) => {
+                self.inner.outln(format_args!("{message}"))?;
+                self.inner
+                    .outln(format_args!("→ {} により {choice} を選択", policy.label()))?;
+                (choice, true)
+            }
+            None => {
+                let choice = match step {
+                    Some(step) => self.inner.input_case_in(step, cases, message)?,
+                    None => self.inner.input_case(cases, message)?,
+                };
+                (choice, false)
+            }
+        };
+
+        self.decisions.lock().push(Decision {
+            step,
+            cases: cases.to_vec(),
+            message: message.to_owned(),
+            choice,
+            automatic,
+        });
+
+        Ok(choice)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_str_accepts_the_serialized_names() {
+        for policy in ResolvePolicy::ALL {
+            let name = serde_json::to_value(policy).unwrap();
+            assert_eq!(
+                name.as_str().unwrap().parse::<ResolvePolicy>().unwrap(),
+                policy
+            );
+        }
+    }
+
+    #[test]
+    fn from_str_rejects_unknown_names() {
+        assert!("PcWins".parse::<ResolvePolicy>().is_err());
+        assert!("".parse::<ResolvePolicy>().is_err());
+    }
+
+    #[test]
+    fn choose_asks_when_the_preferred_case_is_missing() {
+        assert_eq!(
+            ResolvePolicy::PcWins.choose(&['1', '0']).unwrap(),
+            Some('1')
+        );
+        assert_eq!(ResolvePolicy::PcWins.choose(&['2', '0']).unwrap(), None);
+        assert!(ResolvePolicy::AbortOnFirst.choose(&['1']).is_err());
+    }
+}
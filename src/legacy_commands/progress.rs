@@ -0,0 +1,120 @@
+use std::time::{Duration, Instant};
+
+use eframe::egui::{self, mutex::Mutex};
+
+/// 実行中のコマンドの進捗
+///
+/// コマンドは処理の途中経過を知らせないので、件数は呼び出し側が 1 件ずつ進めた分だけ数える
+/// (曲ごとに実行する check など)。数えないコマンドでは経過時間だけを表示する。
+#[derive(Default)]
+pub struct Progress {
+    state: Mutex<Option<ProgressState>>,
+}
+
+struct ProgressState {
+    /// 処理予定の件数 (数えないコマンドなら None)
+    total: Option<usize>,
+    completed: usize,
+    /// 処理中の項目
+    current: Option<String>,
+    started_at: Instant,
+}
+
+impl ProgressState {
+    /// 残り時間の見積もり
+    fn eta(&self) -> Option<Duration> {
+        let total = self.total?;
+        if self.completed == 0 || self.completed >= total {
+            return None;
+        }
+
+        let per_item = self.started_at.elapsed() / self.completed as u32;
+        Some(per_item * (total - self.completed) as u32)
+    }
+}
+
+impl Progress {
+    /// 新しいコマンドの進捗を開始
+    pub fn begin(&self) {
+        *self.state.lock() = Some(ProgressState {
+            total: None,
+            completed: 0,
+            current: None,
+            started_at: Instant::now(),
+        });
+    }
+
+    /// 進捗を終了し、処理した件数を返す (数えないコマンドなら None)
+    pub fn finish(&self) -> Option<usize> {
+        self.state
+            .lock()
+            .take()
+            .and_then(|state| state.total.map(|_| state.completed))
+    }
+
+    /// 処理予定の件数を設定し、件数を数え始める
+    pub fn set_total(&self, total: usize) {
+        if let Some(state) = &mut *self.state.lock() {
+            state.total = Some(total);
+        }
+    }
+
+    /// 項目 1 件の処理を始める
+    pub fn begin_item(&self, name: &str) {
+        if let Some(state) = &mut *self.state.lock() {
+            state.current = Some(name.to_owned());
+        }
+    }
+
+    /// 処理中の項目が終わった
+    pub fn complete_item(&self) {
+        if let Some(state) = &mut *self.state.lock() {
+            state.completed += 1;
+            state.current = None;
+        }
+    }
+
+    /// 進捗バーを表示 (実行中のコマンドがなければ何も表示しない)
+    pub fn show(&self, ui: &mut egui::Ui) {
+        let state = self.state.lock();
+        let Some(state) = &*state else {
+            return;
+        };
+
+        let progress_bar = match state.total {
+            Some(total) if total > 0 => {
+                let fraction = state.completed.min(total) as f32 / total as f32;
+                egui::ProgressBar::new(fraction).text(format!("{} / {total}", state.completed))
+            }
+            _ => egui::ProgressBar::new(0.0).animate(true).text("実行中"),
+        };
+        ui.add(progress_bar);
+
+        ui.horizontal(|ui| {
+            ui.label(format!(
+                "経過 {}",
+                format_duration(state.started_at.elapsed())
+            ));
+            if let Some(eta) = state.eta() {
+                ui.label(format!("残り約 {}", format_duration(eta)));
+            }
+            if let Some(current) = &state.current {
+                ui.weak(current);
+            }
+        });
+    }
+}
+
+fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    if seconds >= 3600 {
+        format!(
+            "{}:{:02}:{:02}",
+            seconds / 3600,
+            seconds % 3600 / 60,
+            seconds % 60
+        )
+    } else {
+        format!("{}:{:02}", seconds / 60, seconds % 60)
+    }
+}
@@ -0,0 +1,93 @@
+use std::fmt::Arguments;
+
+use anyhow::Result;
+use murack_core_app::cui::Cui;
+use serde::Serialize;
+
+/// check コマンドの齟齬の解決処理の段階
+///
+/// 解決処理ごとに別の Cui を渡すので、確認メッセージの文面に頼らずに区別できる。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ResolveStep {
+    /// PC・DAP・DB のいずれかに曲がない (ResolveExistanceImpl)
+    Existance,
+    /// PC と DB のメタデータの不一致 (ResolveDataMatchImpl)
+    DataMatch,
+    /// PC と DAP のファイル内容の不一致 (ResolveDapImpl)
+    Dap,
+}
+
+impl ResolveStep {
+    pub const ALL: [ResolveStep; 3] = [
+        ResolveStep::Existance,
+        ResolveStep::DataMatch,
+        ResolveStep::Dap,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            ResolveStep::Existance => "存在確認",
+            ResolveStep::DataMatch => "メタデータ",
+            ResolveStep::Dap => "DAPファイル内容",
+        }
+    }
+}
+
+/// 確認がどの解決処理からのものかを受け取れる Cui
+pub trait StepCui: Cui {
+    fn input_case_in(&self, step: ResolveStep, cases: &[char], message: &str) -> Result<char>;
+}
+
+/// 解決処理 1 段階分に渡す Cui
+///
+/// 出力は内側の Cui にそのまま渡し、確認は段階を付けて内側の Cui に渡す。
+pub struct ResolveStepCui<'cui, C: StepCui> {
+    inner: &'cui C,
+    step: ResolveStep,
+}
+
+impl<C: StepCui> Cui for ResolveStepCui<'_, C> {
+    fn out(&self, args: Arguments) -> anyhow::Result<()> {
+        self.inner.out(args)
+    }
+
+    fn outln(&self, args: Arguments) -> anyhow::Result<()> {
+        self.inner.outln(args)
+    }
+
+    fn err(&self, args: Arguments) -> anyhow::Result<()> {
+        self.inner.err(args)
+    }
+
+    fn errln(&self, args: Arguments) -> anyhow::Result<()> {
+        self.inner.errln(args)
+    }
+
+    fn input_case(&self, cases: &[char], message: &str) -> Result<char> {
+        self.inner.input_case_in(self.step, cases, message)
+    }
+}
+
+/// check コマンドの解決処理ごとの Cui
+///
+/// check コマンドより長く生存させる必要があるので、呼び出し側で作って渡す。
+pub struct CheckCuis<'cui, C: StepCui> {
+    pub inner: &'cui C,
+    pub existance: ResolveStepCui<'cui, C>,
+    pub data_match: ResolveStepCui<'cui, C>,
+    pub dap: ResolveStepCui<'cui, C>,
+}
+
+impl<'cui, C: StepCui> CheckCuis<'cui, C> {
+    pub fn new(inner: &'cui C) -> Self {
+        let step_cui = |step| ResolveStepCui { inner, step };
+
+        Self {
+            inner,
+            existance: step_cui(ResolveStep::Existance),
+            data_match: step_cui(ResolveStep::DataMatch),
+            dap: step_cui(ResolveStep::Dap),
+        }
+    }
+}
@@ -0,0 +1,224 @@
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+    sync::{Arc, OnceLock},
+};
+
+use anyhow::{Context, anyhow};
+use murack_core_app::Config;
+use sqlx::{Connection, Executor, PgPool, postgres::PgPoolOptions};
+
+use crate::legacy_commands::playlist_selection;
+
+/// 変更を確定しない DB 接続
+///
+/// murack-core のコマンドは `PgPool` を受け取るので、接続 1 つだけのプールにする。
+/// 接続直後に BEGIN したトランザクションを最後まで確定せず、`rollback` で取り消す。
+/// コマンド内のトランザクションはその中のセーブポイントになる。
+///
+/// 接続が切れてもつなぎ直さない。つなぎ直すとトランザクションの外で実行されてしまうため。
+pub struct SandboxDb {
+    pool: PgPool,
+    /// 接続直後に始めたトランザクションの ID
+    txid: Arc<OnceLock<i64>>,
+}
+
+impl SandboxDb {
+    /// `db_pool` と同じ DB に接続する
+    ///
+    /// `excluded_ids` のプレイリストは、DAP に書き込まない設定に見せる。
+    pub async fn connect(db_pool: &PgPool, excluded_ids: &[i32]) -> anyhow::Result<Self> {
+        let view_sql = if excluded_ids.is_empty() {
+            None
+        } else {
+            Some(playlist_selection::exclusion_view_sql(db_pool, excluded_ids).await?)
+        };
+
+        let txid = Arc::new(OnceLock::new());
+        let txid_for_connect = txid.clone();
+        let pool = PgPoolOptions::new()
+            // 全ての変更を 1 つのトランザクションに収める
+            .max_connections(1)
+            .idle_timeout(None)
+            .max_lifetime(None)
+            .after_connect(move |conn, _meta| {
+                let view_sql = view_sql.clone();
+                let txid = txid_for_connect.clone();
+                Box::pin(async move {
+                    if txid.get().is_some() {
+                        return Err(sqlx::Error::Protocol(
+                            "試しに実行するための DB 接続が切れました".to_owned(),
+                        ));
+                    }
+
+                    // Transaction を破棄すると ROLLBACK されてしまうので、破棄せずに残す。
+                    // sqlx が入れ子の深さを覚えているので、コマンド内の begin はセーブポイントになる。
+                    std::mem::forget(conn.begin_with("BEGIN").await?);
+                    let id: i64 = sqlx::query_scalar("SELECT txid_current()")
+                        .fetch_one(&mut *conn)
+                        .await?;
+                    let _ = txid.set(id);
+
+                    if let Some(view_sql) = view_sql {
+                        conn.execute(view_sql.as_str()).await?;
+                    }
+                    Ok(())
+                })
+            })
+            .connect_with((*db_pool.connect_options()).clone())
+            .await
+            .context("Failed to connect to the DB")?;
+
+        Ok(Self { pool, txid })
+    }
+
+    /// コマンドに渡す接続
+    pub fn pool(&self) -> &PgPool {
+        &self.pool
+    }
+
+    /// 全ての変更を取り消して接続を閉じる
+    ///
+    /// トランザクションが途中で確定されていた場合はエラーにする。
+    pub async fn rollback(self) -> anyhow::Result<()> {
+        let result = self.rollback_connection().await;
+        self.pool.close().await;
+        result
+    }
+
+    async fn rollback_connection(&self) -> anyhow::Result<()> {
+        let mut conn = self
+            .pool
+            .acquire()
+            .await
+            .context("Failed to connect to the DB")?
+            .detach();
+
+        // コマンドが失敗してトランザクションが中断されている場合は読めないが、確定もされていない
+        let txid: Option<i64> = sqlx::query_scalar("SELECT txid_current()")
+            .fetch_one(&mut conn)
+            .await
+            .ok();
+        if txid.is_some() && self.txid.get() != txid.as_ref() {
+            return Err(anyhow!(
+                "試しに実行したトランザクションが途中で確定されました。DB の変更が取り消されていない可能性があります"
+            ));
+        }
+
+        conn.execute("ROLLBACK").await?;
+        conn.close().await?;
+        Ok(())
+    }
+}
+
+/// 代わりのファイルの内容の先頭 (続けて元のファイルのパスを書く)
+const PLACEHOLDER_PREFIX: &str = "murack-sync-placeholder:";
+
+/// ライブラリとプレイリストの保存先を `scratch` 以下の空のフォルダに置き換えた設定
+pub fn scratch_config(config_path: &Path, scratch: &Path) -> anyhow::Result<Config> {
+    let text = std::fs::read_to_string(config_path)
+        .with_context(|| format!("Failed to read {}", config_path.display()))?;
+    let mut table: toml::Table = toml::from_str(&text)
+        .with_context(|| format!("Failed to parse {}", config_path.display()))?;
+
+    for key in ["pc_lib", "dap_lib", "dap_playlist"] {
+        let dir = scratch.join(key);
+        std::fs::create_dir(&dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+        table.insert(
+            key.to_owned(),
+            toml::Value::String(dir.to_string_lossy().into_owned()),
+        );
+    }
+
+    let mut temp_file = tempfile::Builder::new()
+        .prefix("murack-sync-config-")
+        .suffix(".toml")
+        .tempfile_in(scratch)
+        .context("Failed to create a temporary config file")?;
+    temp_file
+        .write_all(toml::to_string_pretty(&table)?.as_bytes())
+        .and_then(|()| temp_file.flush())
+        .with_context(|| format!("Failed to write {}", temp_file.path().display()))?;
+
+    Config::load(temp_file.path())
+}
+
+/// 一時フォルダに置く、ライブラリのファイルの写し方
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MirrorKind {
+    /// 元のファイルへのシンボリックリンク (コマンドが内容を読める)
+    Link,
+    /// 元のファイルのパスだけを書いたファイル
+    ///
+    /// コピー先になり得るフォルダに使う。リンクだと、上書きしたときに元のファイルまで書き換わるため。
+    Placeholder,
+}
+
+/// `real_root` 以下の `path` (ファイルまたはフォルダ) を、`scratch_root` 以下の同じパスに写す
+///
+/// フォルダは作り直し、ファイルは `kind` の方法で置く。`path` がなければ親フォルダだけ作る。
+pub fn mirror(
+    real_root: &Path,
+    scratch_root: &Path,
+    path: &str,
+    kind: MirrorKind,
+) -> anyhow::Result<()> {
+    let real = real_root.join(path);
+    let scratch = scratch_root.join(path);
+
+    if let Some(parent) = scratch.parent() {
+        std::fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create {}", parent.display()))?;
+    }
+    if real.exists() {
+        mirror_entry(&real, &scratch, kind)?;
+    }
+    Ok(())
+}
+
+fn mirror_entry(real: &Path, scratch: &Path, kind: MirrorKind) -> anyhow::Result<()> {
+    if real.is_dir() {
+        std::fs::create_dir_all(scratch)
+            .with_context(|| format!("Failed to create {}", scratch.display()))?;
+        for entry in std::fs::read_dir(real)? {
+            let entry = entry?;
+            mirror_entry(&entry.path(), &scratch.join(entry.file_name()), kind)?;
+        }
+        return Ok(());
+    }
+
+    match kind {
+        MirrorKind::Link => link_file(real, scratch),
+        MirrorKind::Placeholder => std::fs::write(
+            scratch,
+            format!("{PLACEHOLDER_PREFIX}{}", real.to_string_lossy()),
+        ),
+    }
+    .with_context(|| format!("Failed to create {}", scratch.display()))
+}
+
+#[cfg(unix)]
+fn link_file(real: &Path, scratch: &Path) -> std::io::Result<()> {
+    std::os::unix::fs::symlink(real, scratch)
+}
+
+#[cfg(windows)]
+fn link_file(real: &Path, scratch: &Path) -> std::io::Result<()> {
+    std::os::windows::fs::symlink_file(real, scratch)
+}
+
+/// `mirror` で写したファイルなら、元のファイルのパス
+///
+/// コマンドが新たに書き込んだファイル (上書きした代わりのファイルを含む) なら None。
+pub fn mirrored_from(scratch: &Path) -> Option<PathBuf> {
+    let metadata = std::fs::symlink_metadata(scratch).ok()?;
+    if metadata.is_symlink() {
+        return std::fs::read_link(scratch).ok();
+    }
+    // 曲のファイルを丸ごと読まないよう、代わりのファイルの大きさのものだけ読む
+    if metadata.len() > 4096 {
+        return None;
+    }
+    let text = std::fs::read_to_string(scratch).ok()?;
+    text.strip_prefix(PLACEHOLDER_PREFIX).map(PathBuf::from)
+}
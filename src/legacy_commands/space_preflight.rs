@@ -0,0 +1,101 @@
+use std::sync::Arc;
+
+use eframe::egui::{self, mutex::Mutex};
+
+use crate::legacy_commands::{
+    command_pages::CommandPage,
+    di_registry::DIRegistry,
+    dry_run::{SpaceEstimate, format_bytes},
+    egui_cui::EguiCui,
+};
+
+/// 見積もりの実行状態
+#[derive(Default)]
+enum PreflightState {
+    #[default]
+    Idle,
+    Running,
+    /// 見積もったときのジョブの要約と結果
+    Done(String, Result<SpaceEstimate, String>),
+}
+
+/// 実行前に DAP への書き込み量と空き容量を比べる
+#[derive(Default)]
+pub struct SpacePreflight {
+    state: Arc<Mutex<PreflightState>>,
+}
+
+impl SpacePreflight {
+    /// 見積もりのボタンと結果を表示
+    ///
+    /// DAP に書き込まないページでは何も表示しない。
+    /// 入力内容を変えたら、前の結果は表示しない。
+    pub fn show(
+        &self,
+        ui: &mut egui::Ui,
+        page: &dyn CommandPage,
+        di_registry: &DIRegistry<EguiCui>,
+    ) {
+        let Some(planner) = page.create_planner() else {
+            return;
+        };
+        let summary = page.job_summary();
+
+        ui.horizontal(|ui| {
+            let running = matches!(&*self.state.lock(), PreflightState::Running);
+            if ui
+                .add_enabled(!running, egui::Button::new("空き容量を確認"))
+                .clicked()
+            {
+                let state = self.state.clone();
+                let config = di_registry.config();
+                let db_pool = di_registry.db_pool();
+                let summary = summary.clone();
+                let ctx = ui.ctx().clone();
+                *state.lock() = PreflightState::Running;
+
+                tokio::spawn(async move {
+                    let dap_lib = config.dap_lib.clone();
+                    let result = planner(config, db_pool)
+                        .await
+                        .map(|plan| SpaceEstimate::new(&plan, &dap_lib))
+                        .map_err(|e| format!("{e:#}"));
+                    *state.lock() = PreflightState::Done(summary, result);
+                    ctx.request_repaint();
+                });
+            }
+
+            match &*self.state.lock() {
+                PreflightState::Idle => {}
+                PreflightState::Running => {
+                    ui.spinner();
+                }
+                PreflightState::Done(checked, _) if *checked != summary => {}
+                PreflightState::Done(_, Ok(estimate)) => show_estimate(ui, estimate),
+                PreflightState::Done(_, Err(message)) => {
+                    ui.colored_label(egui::Color32::LIGHT_RED, message);
+                }
+            }
+        });
+    }
+}
+
+fn show_estimate(ui: &mut egui::Ui, estimate: &SpaceEstimate) {
+    let (color, prefix) = if !estimate.fits() {
+        (egui::Color32::LIGHT_RED, "✖ 容量不足: ")
+    } else if estimate.is_tight() {
+        (egui::Color32::LIGHT_YELLOW, "⚠ 空きが少なくなります: ")
+    } else {
+        (egui::Color32::LIGHT_GREEN, "✔ ")
+    };
+    ui.colored_label(color, format!("{prefix}{}", estimate.describe()));
+
+    if let Some(free) = estimate.free_bytes {
+        if estimate.fits() {
+            ui.weak(format!(
+                "(書き込み後の空き {})",
+                format_bytes(free - estimate.required_bytes)
+            ));
+        }
+    }
+}
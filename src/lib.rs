@@ -0,0 +1,13 @@
+pub mod backup;
+pub mod config;
+pub mod dap_device;
+pub mod database;
+pub mod db_health;
+pub mod legacy_commands;
+pub mod library_browser;
+pub mod profile_selector;
+pub mod schema;
+pub mod settings_editor;
+pub mod setup_wizard;
+pub mod startup;
+pub mod sync_settings;
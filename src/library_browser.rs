@@ -0,0 +1,4 @@
+mod library_browser_app;
+mod track_list;
+
+pub use library_browser_app::LibraryBrowserApp;
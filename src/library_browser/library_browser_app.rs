@@ -0,0 +1,209 @@
+use std::sync::Arc;
+
+use eframe::egui::{self, RichText, mutex::Mutex};
+use egui_extras::{Column, TableBuilder};
+use sqlx::PgPool;
+
+use crate::library_browser::track_list::{self, SortColumn, TrackRow};
+
+/// 曲一覧の読み込み状態
+#[derive(Default)]
+enum LoadState {
+    #[default]
+    NotLoaded,
+    Loading,
+    Loaded(Arc<Vec<TrackRow>>),
+    Failed(String),
+}
+
+/// DB の曲一覧を検索・閲覧するページ
+pub struct LibraryBrowserApp {
+    db_pool: Arc<PgPool>,
+    load_state: Arc<Mutex<LoadState>>,
+
+    filter: String,
+    sort_column: SortColumn,
+    sort_desc: bool,
+
+    /// フィルタ・ソート済みの表示行キャッシュ
+    ///
+    /// 元データ・フィルタ・ソート条件のいずれかが変わったら作り直す
+    visible: Option<VisibleRows>,
+}
+
+struct VisibleRows {
+    tracks: Arc<Vec<TrackRow>>,
+    filter: String,
+    sort_column: SortColumn,
+    sort_desc: bool,
+    indices: Vec<usize>,
+}
+
+impl LibraryBrowserApp {
+    pub fn new(db_pool: Arc<PgPool>) -> Self {
+        Self {
+            db_pool,
+            load_state: Arc::default(),
+            filter: String::new(),
+            sort_column: SortColumn::Path,
+            sort_desc: false,
+            visible: None,
+        }
+    }
+
+    pub fn show(&mut self, ui: &mut egui::Ui) {
+        // 初回表示時に読み込み開始
+        if matches!(&*self.load_state.lock(), LoadState::NotLoaded) {
+            self.reload(ui.ctx().clone());
+        }
+
+        ui.horizontal(|ui| {
+            ui.label("検索:");
+            ui.text_edit_singleline(&mut self.filter);
+
+            if ui.button("クリア").clicked() {
+                self.filter.clear();
+            }
+
+            let loading = matches!(&*self.load_state.lock(), LoadState::Loading);
+            if ui
+                .add_enabled(!loading, egui::Button::new("再読み込み"))
+                .clicked()
+            {
+                self.reload(ui.ctx().clone());
+            }
+        });
+
+        ui.separator();
+
+        let tracks = match &*self.load_state.lock() {
+            LoadState::NotLoaded | LoadState::Loading => {
+                ui.spinner();
+                return;
+            }
+            LoadState::Failed(message) => {
+                ui.colored_label(egui::Color32::LIGHT_RED, message);
+                return;
+            }
+            LoadState::Loaded(tracks) => tracks.clone(),
+        };
+
+        self.update_visible(&tracks);
+        let Some(visible) = &self.visible else {
+            return;
+        };
+
+        ui.label(format!("{} / {} 曲", visible.indices.len(), tracks.len()));
+        ui.add_space(4.0);
+
+        let mut clicked_column = None;
+        let row_height = ui.text_style_height(&egui::TextStyle::Body) + 4.0;
+
+        TableBuilder::new(ui)
+            .striped(true)
+            .resizable(true)
+            .cell_layout(egui::Layout::left_to_right(egui::Align::Center))
+            .column(Column::initial(180.0).at_least(40.0).clip(true))
+            .column(Column::initial(140.0).at_least(40.0).clip(true))
+            .column(Column::initial(140.0).at_least(40.0).clip(true))
+            .column(Column::initial(260.0).at_least(40.0).clip(true))
+            .column(Column::remainder().at_least(50.0))
+            .header(row_height + 4.0, |mut header| {
+                for column in SortColumn::ALL {
+                    header.col(|ui| {
+                        let mut text = column.label().to_owned();
+                        if column == self.sort_column {
+                            text.push_str(if self.sort_desc { " ▼" } else { " ▲" });
+                        }
+
+                        if ui
+                            .add(egui::Button::new(RichText::new(text).strong()).frame(false))
+                            .clicked()
+                        {
+                            clicked_column = Some(column);
+                        }
+                    });
+                }
+            })
+            .body(|body| {
+                body.rows(row_height, visible.indices.len(), |mut row| {
+                    let track = &tracks[visible.indices[row.index()]];
+
+                    row.col(|ui| {
+                        ui.label(&track.title);
+                    });
+                    row.col(|ui| {
+                        ui.label(&track.artist);
+                    });
+                    row.col(|ui| {
+                        ui.label(&track.album);
+                    });
+                    row.col(|ui| {
+                        // パスはクリックでクリップボードにコピーし、コマンドのフォームに貼り付けられるようにする
+                        let response = ui
+                            .add(egui::Label::new(&track.path).sense(egui::Sense::click()))
+                            .on_hover_text("クリックでパスをコピー");
+                        if response.clicked() {
+                            ui.ctx().copy_text(track.path.clone());
+                        }
+                    });
+                    row.col(|ui| {
+                        ui.label(track.duration_text());
+                    });
+                });
+            });
+
+        if let Some(column) = clicked_column {
+            if column == self.sort_column {
+                self.sort_desc = !self.sort_desc;
+            } else {
+                self.sort_column = column;
+                self.sort_desc = false;
+            }
+        }
+    }
+
+    /// DB から曲一覧を読み込み直す
+    fn reload(&mut self, ctx: egui::Context) {
+        *self.load_state.lock() = LoadState::Loading;
+
+        let db_pool = self.db_pool.clone();
+        let load_state = self.load_state.clone();
+
+        tokio::spawn(async move {
+            let new_state = match track_list::fetch_tracks(&db_pool).await {
+                Ok(tracks) => LoadState::Loaded(Arc::new(tracks)),
+                Err(e) => LoadState::Failed(format!("曲一覧の取得に失敗しました: {e}")),
+            };
+
+            *load_state.lock() = new_state;
+            ctx.request_repaint();
+        });
+    }
+
+    /// 表示行のキャッシュを必要に応じて作り直す
+    fn update_visible(&mut self, tracks: &Arc<Vec<TrackRow>>) {
+        let up_to_date = self.visible.as_ref().is_some_and(|visible| {
+            Arc::ptr_eq(&visible.tracks, tracks)
+                && visible.filter == self.filter
+                && visible.sort_column == self.sort_column
+                && visible.sort_desc == self.sort_desc
+        });
+        if up_to_date {
+            return;
+        }
+
+        self.visible = Some(VisibleRows {
+            tracks: tracks.clone(),
+            filter: self.filter.clone(),
+            sort_column: self.sort_column,
+            sort_desc: self.sort_desc,
+            indices: track_list::visible_indices(
+                tracks,
+                &self.filter,
+                self.sort_column,
+                self.sort_desc,
+            ),
+        });
+    }
+}
@@ -0,0 +1,186 @@
+use std::cmp::Ordering;
+
+use sqlx::PgPool;
+
+/// ライブラリブラウザに表示する曲 1 行分のデータ
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct TrackRow {
+    pub title: String,
+    pub artist: String,
+    pub album: String,
+    pub path: String,
+    /// 再生時間 (ミリ秒)
+    pub duration: i64,
+    /// フィルタ用に、表示する文字列を小文字化して連結したもの
+    #[sqlx(skip)]
+    search_text: String,
+}
+
+impl TrackRow {
+    /// 再生時間の表示用文字列 (m:ss)
+    pub fn duration_text(&self) -> String {
+        let total_seconds = self.duration.max(0) / 1000;
+        format!("{}:{:02}", total_seconds / 60, total_seconds % 60)
+    }
+
+    /// フィルタ文字列に一致するか (大文字小文字を区別しない)
+    ///
+    /// `filter` は小文字化済みであること。
+    fn matches(&self, filter: &str) -> bool {
+        self.search_text.contains(filter)
+    }
+
+    /// 読み込み時に 1 度だけ、フィルタ用の文字列を作る
+    fn build_search_text(&mut self) {
+        // 区切りの改行はフィルタ文字列に含まれないので、列をまたいで一致しない
+        self.search_text = [&self.title, &self.artist, &self.album, &self.path]
+            .iter()
+            .map(|s| s.to_lowercase())
+            .collect::<Vec<_>>()
+            .join("\n");
+    }
+}
+
+/// DB から全曲の一覧を取得
+///
+/// duration の整数型の違いを吸収するため、bigint にして読み込む。
+pub async fn fetch_tracks(db_pool: &PgPool) -> sqlx::Result<Vec<TrackRow>> {
+    let mut tracks: Vec<TrackRow> = sqlx::query_as(
+        "SELECT COALESCE(title, '') AS title, COALESCE(artist, '') AS artist, COALESCE(album, '') AS album, path, COALESCE(duration, 0)::bigint AS duration FROM tracks ORDER BY path",
+    )
+    .fetch_all(db_pool)
+    .await?;
+
+    for track in &mut tracks {
+        track.build_search_text();
+    }
+    Ok(tracks)
+}
+
+/// ソート対象の列
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum SortColumn {
+    Title,
+    Artist,
+    Album,
+    Path,
+    Duration,
+}
+
+impl SortColumn {
+    pub const ALL: [SortColumn; 5] = [
+        SortColumn::Title,
+        SortColumn::Artist,
+        SortColumn::Album,
+        SortColumn::Path,
+        SortColumn::Duration,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            SortColumn::Title => "タイトル",
+            SortColumn::Artist => "アーティスト",
+            SortColumn::Album => "アルバム",
+            SortColumn::Path => "パス",
+            SortColumn::Duration => "時間",
+        }
+    }
+
+    fn compare(&self, a: &TrackRow, b: &TrackRow) -> Ordering {
+        match self {
+            SortColumn::Title => a.title.cmp(&b.title),
+            SortColumn::Artist => a.artist.cmp(&b.artist),
+            SortColumn::Album => a.album.cmp(&b.album),
+            SortColumn::Path => a.path.cmp(&b.path),
+            SortColumn::Duration => a.duration.cmp(&b.duration),
+        }
+    }
+}
+
+/// フィルタ・ソートを適用した表示行のインデックス一覧を作成
+pub fn visible_indices(
+    tracks: &[TrackRow],
+    filter: &str,
+    sort_column: SortColumn,
+    sort_desc: bool,
+) -> Vec<usize> {
+    let filter = filter.trim().to_lowercase();
+
+    let mut indices: Vec<usize> = tracks
+        .iter()
+        .enumerate()
+        .filter(|(_, track)| filter.is_empty() || track.matches(&filter))
+        .map(|(i, _)| i)
+        .collect();
+
+    indices.sort_by(|&a, &b| {
+        // 同じ値ならパス順で安定させる
+        let ordering = sort_column
+            .compare(&tracks[a], &tracks[b])
+            .then_with(|| tracks[a].path.cmp(&tracks[b].path));
+
+        if sort_desc {
+            ordering.reverse()
+        } else {
+            ordering
+        }
+    });
+
+    indices
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn track(title: &str, artist: &str, path: &str, duration: i64) -> TrackRow {
+        let mut track = TrackRow {
+            title: title.to_owned(),
+            artist: artist.to_owned(),
+            album: String::new(),
+            path: path.to_owned(),
+            duration,
+            search_text: String::new(),
+        };
+        track.build_search_text();
+        track
+    }
+
+    #[test]
+    fn filter_ignores_case_and_surrounding_spaces() {
+        let tracks = [
+            track("Song", "Alice", "a.flac", 0),
+            track("Other", "Bob", "b.flac", 0),
+        ];
+
+        assert_eq!(
+            visible_indices(&tracks, " ALICE ", SortColumn::Title, false),
+            vec![0]
+        );
+    }
+
+    #[test]
+    fn filter_does_not_match_across_columns() {
+        let tracks = [track("ab", "cd", "x.flac", 0)];
+
+        assert!(visible_indices(&tracks, "bc", SortColumn::Title, false).is_empty());
+    }
+
+    #[test]
+    fn equal_values_are_ordered_by_path() {
+        let tracks = [
+            track("Same", "", "c.flac", 0),
+            track("Same", "", "a.flac", 0),
+            track("Earlier", "", "b.flac", 0),
+        ];
+
+        assert_eq!(
+            visible_indices(&tracks, "", SortColumn::Title, false),
+            vec![2, 1, 0]
+        );
+        assert_eq!(
+            visible_indices(&tracks, "", SortColumn::Title, true),
+            vec![0, 1, 2]
+        );
+    }
+}
@@ -1,30 +1,25 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")] // hide console window on Windows in release
 #![allow(rustdoc::missing_crate_level_docs)] // it's an example
 
-mod config;
-mod database;
-mod legacy_commands;
+use std::{sync::Arc, time::Duration};
 
-use std::sync::Arc;
-
-use eframe::egui;
+use eframe::egui::{self, mutex::Mutex};
 use murack_core_app::Config;
-use sqlx::PgPool;
-
-use crate::legacy_commands::LegacyCommandsApp;
+use murack_sync::{
+    backup::BackupApp, config, dap_device::DapMonitor, db_health::DbHealthMonitor,
+    legacy_commands::LegacyCommandsApp, library_browser::LibraryBrowserApp,
+    profile_selector::ProfileSelector, schema::SchemaMigrationApp,
+    settings_editor::SettingsEditorApp, setup_wizard::SetupWizardApp, startup::AppServices,
+};
 
 #[tokio::main]
 async fn main() -> eframe::Result {
     env_logger::init(); // Log to stderr (if you run with `RUST_LOG=debug`).
 
-    let config = config::load_config().map_err(|e| eframe::Error::AppCreation(e.into()))?;
-    let config = Arc::new(config);
+    let profile = config::active_profile();
 
-    // Connect to database
-    let db_pool = database::connect_db_pool(&config.database_url)
-        .await
-        .map_err(|e| eframe::Error::AppCreation(e.into()))?;
-    let db_pool = Arc::new(db_pool);
+    // 設定や DB に問題があっても、ウィンドウを開いてセットアップできるようにする
+    let services = AppServices::load(&profile).await;
 
     let options = eframe::NativeOptions {
         viewport: egui::ViewportBuilder::default().with_inner_size([800.0, 600.0]),
@@ -39,11 +34,18 @@ async fn main() -> eframe::Result {
 
             cc.egui_ctx.set_fonts(font_definitions());
 
-            Ok(Box::new(MurackSyncApp {
-                legacy_commands_app: LegacyCommandsApp::new(config.clone(), db_pool.clone()),
-                _config: config,
-                _db_pool: db_pool,
-            }))
+            let mut root_app = RootApp {
+                profile_selector: ProfileSelector::new(profile.clone()),
+                profile,
+                setup_wizard: None,
+                pending_start: None,
+                schema_migration: None,
+                app: None,
+                startup_error: None,
+            };
+            root_app.on_loaded(services, &cc.egui_ctx);
+
+            Ok(Box::new(root_app))
         }),
     )
 }
@@ -73,18 +75,246 @@ fn font_definitions() -> egui::FontDefinitions {
     fonts
 }
 
+/// 画面上部のタブで切り替えるページ
+#[derive(PartialEq, Clone, Copy)]
+enum MainPage {
+    Library,
+    LegacyCommands,
+    Settings,
+    Backup,
+}
+
+/// 起動処理の結果
+type PendingStart = Arc<Mutex<Option<anyhow::Result<AppServices>>>>;
+
+/// 起動できるまではセットアップウィザードを、起動後はアプリ本体を表示する
+struct RootApp {
+    /// 使用中 (切り替え中ならその先) のプロファイル
+    profile: String,
+    profile_selector: ProfileSelector,
+    setup_wizard: Option<SetupWizardApp>,
+    /// セットアップ後やプロファイル切り替え後の起動処理
+    pending_start: Option<PendingStart>,
+    /// スキーマが古い場合に、マイグレーションの適用を待っている起動処理の結果
+    schema_migration: Option<(AppServices, SchemaMigrationApp)>,
+    app: Option<MurackSyncApp>,
+    /// セットアップウィザードも表示できずに起動できなかった理由
+    startup_error: Option<String>,
+}
+
+impl RootApp {
+    fn start(&mut self, ctx: egui::Context) {
+        self.startup_error = None;
+        let pending_start = PendingStart::default();
+        self.pending_start = Some(pending_start.clone());
+
+        let profile = self.profile.clone();
+        tokio::spawn(async move {
+            let services = AppServices::load(&profile).await;
+            *pending_start.lock() = Some(services);
+            ctx.request_repaint();
+        });
+    }
+
+    /// 起動処理の結果を反映する
+    ///
+    /// 失敗したらそのプロファイルのセットアップウィザードを表示する。
+    fn on_loaded(&mut self, services: anyhow::Result<AppServices>, ctx: &egui::Context) {
+        match services {
+            Ok(services) => {
+                self.setup_wizard = None;
+
+                if services.schema.is_up_to_date() {
+                    self.app = Some(MurackSyncApp::new(services, ctx));
+                } else {
+                    let schema_migration =
+                        SchemaMigrationApp::new(services.db_pool.clone(), services.schema.clone());
+                    self.schema_migration = Some((services, schema_migration));
+                }
+            }
+            Err(e) => {
+                let reason = format!("{e:#}");
+                match &mut self.setup_wizard {
+                    Some(setup_wizard) => setup_wizard.restart(reason),
+                    None => match config::config_file_path(&self.profile) {
+                        Ok(config_path) => {
+                            self.setup_wizard = Some(SetupWizardApp::new(config_path, reason));
+                        }
+                        Err(path_error) => {
+                            self.startup_error = Some(format!(
+                                "{reason}\n\n設定ファイルの場所を決められないため、セットアップもできません: {path_error:#}"
+                            ));
+                        }
+                    },
+                }
+            }
+        }
+    }
+
+    /// 別のプロファイルに切り替える
+    ///
+    /// 今のアプリを破棄し、新しいプロファイルの設定と DB 接続で作り直す。
+    fn switch_profile(&mut self, profile: String, ctx: egui::Context) {
+        self.profile = profile;
+        self.app = None;
+        self.setup_wizard = None;
+        self.schema_migration = None;
+        self.start(ctx);
+    }
+}
+
+impl eframe::App for RootApp {
+    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        let busy =
+            self.pending_start.is_some() || self.app.as_ref().is_some_and(|app| app.is_busy());
+        let selected_profile = egui::TopBottomPanel::top("profile_bar")
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| self.profile_selector.show(ui, !busy))
+                    .inner
+            })
+            .inner;
+        if let Some(profile) = selected_profile {
+            self.switch_profile(profile, ctx.clone());
+        }
+
+        if let Some(app) = &mut self.app {
+            app.update(ctx);
+            return;
+        }
+
+        if let Some((_, schema_migration)) = &mut self.schema_migration {
+            let migrated = egui::CentralPanel::default()
+                .show(ctx, |ui| schema_migration.show(ui))
+                .inner;
+            if migrated {
+                if let Some((services, _)) = self.schema_migration.take() {
+                    self.app = Some(MurackSyncApp::new(services, ctx));
+                }
+            }
+            return;
+        }
+
+        if let Some(pending_start) = &self.pending_start {
+            let result = pending_start.lock().take();
+            let Some(services) = result else {
+                egui::CentralPanel::default().show(ctx, |ui| {
+                    ui.horizontal(|ui| {
+                        ui.spinner();
+                        ui.label("起動中...");
+                    });
+                });
+                ctx.request_repaint_after(Duration::from_millis(100));
+                return;
+            };
+
+            self.pending_start = None;
+            self.on_loaded(services, ctx);
+            return;
+        }
+
+        let Some(setup_wizard) = &mut self.setup_wizard else {
+            if let Some(startup_error) = &self.startup_error {
+                let retry = egui::CentralPanel::default()
+                    .show(ctx, |ui| {
+                        ui.heading("起動できませんでした");
+                        ui.colored_label(egui::Color32::LIGHT_RED, startup_error);
+                        ui.button("再試行").clicked()
+                    })
+                    .inner;
+                if retry {
+                    self.start(ctx.clone());
+                }
+            }
+            return;
+        };
+        let saved = egui::CentralPanel::default()
+            .show(ctx, |ui| setup_wizard.show(ui))
+            .inner;
+        if saved {
+            self.start(ctx.clone());
+        }
+    }
+}
+
 struct MurackSyncApp {
     // legacy commands 以外の正式版の機能で使う予定
     _config: Arc<Config>,
-    _db_pool: Arc<PgPool>,
 
+    main_page: MainPage,
+    db_health_monitor: DbHealthMonitor,
+    /// 破棄するまで DAP の監視を続ける
+    _dap_monitor: DapMonitor,
+    library_browser_app: LibraryBrowserApp,
     legacy_commands_app: LegacyCommandsApp,
+    settings_editor_app: SettingsEditorApp,
+    backup_app: BackupApp,
 }
 
-impl eframe::App for MurackSyncApp {
-    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
-        egui::CentralPanel::default().show(ctx, |ui| {
-            self.legacy_commands_app.show(ui);
+impl MurackSyncApp {
+    /// ジョブの実行中か (実行中はプロファイルを切り替えない)
+    fn is_busy(&self) -> bool {
+        self.legacy_commands_app.is_busy()
+    }
+
+    fn new(services: AppServices, ctx: &egui::Context) -> Self {
+        let db_health_monitor = DbHealthMonitor::start(services.db_pool.clone(), ctx.clone());
+        let dap_monitor = DapMonitor::start(
+            services.config.dap_lib.clone(),
+            services.profile.clone(),
+            ctx.clone(),
+        );
+
+        let legacy_commands_app =
+            LegacyCommandsApp::new(&services, db_health_monitor.health(), dap_monitor.device());
+
+        // 起動できたプロファイルを、次回の起動時に使う
+        if let Err(e) = config::save_active_profile(&services.profile) {
+            legacy_commands_app
+                .add_warning(format!("使用中のプロファイルを保存できませんでした: {e:#}"));
+        }
+
+        Self {
+            main_page: MainPage::LegacyCommands,
+            library_browser_app: LibraryBrowserApp::new(services.db_pool.clone()),
+            settings_editor_app: SettingsEditorApp::new(services.config_path.clone()),
+            backup_app: BackupApp::new(services.backups.clone(), services.db_pool.clone()),
+            legacy_commands_app,
+            db_health_monitor,
+            _dap_monitor: dap_monitor,
+            _config: services.config,
+        }
+    }
+
+    fn update(&mut self, ctx: &egui::Context) {
+        egui::TopBottomPanel::top("main_page_tab").show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                ui.selectable_value(&mut self.main_page, MainPage::Library, "ライブラリ");
+                ui.selectable_value(
+                    &mut self.main_page,
+                    MainPage::LegacyCommands,
+                    "レガシーコマンド",
+                );
+                ui.selectable_value(&mut self.main_page, MainPage::Settings, "設定");
+                ui.selectable_value(&mut self.main_page, MainPage::Backup, "バックアップ");
+
+                ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                    self.db_health_monitor.health().show_indicator(ui);
+                });
+            });
+        });
+
+        egui::CentralPanel::default().show(ctx, |ui| match self.main_page {
+            MainPage::Library => self.library_browser_app.show(ui),
+            MainPage::LegacyCommands => self.legacy_commands_app.show(ui),
+            MainPage::Settings => self.settings_editor_app.show(ui),
+            MainPage::Backup => {
+                let busy = self.legacy_commands_app.is_busy();
+                self.backup_app.show(ui, busy);
+            }
         });
+
+        self.legacy_commands_app.resume_queue_on_reconnect();
+        self.legacy_commands_app.update_auto_sync();
+        self.legacy_commands_app.show_notifications(ctx);
     }
 }
@@ -0,0 +1,109 @@
+use anyhow::anyhow;
+use eframe::egui;
+
+use crate::config;
+
+/// 画面上部のプロファイル切り替え
+pub struct ProfileSelector {
+    current: String,
+    profiles: Vec<String>,
+    /// 新規作成するプロファイル名の入力欄
+    new_profile_name: String,
+    /// 作成に失敗した場合などのメッセージ
+    status: Option<String>,
+}
+
+impl ProfileSelector {
+    pub fn new(current: String) -> Self {
+        let mut selector = Self {
+            current,
+            profiles: vec![],
+            new_profile_name: String::new(),
+            status: None,
+        };
+        selector.reload();
+        selector
+    }
+
+    fn reload(&mut self) {
+        match config::list_profiles() {
+            Ok(profiles) => self.profiles = profiles,
+            Err(e) => self.status = Some(format!("{e:#}")),
+        }
+        if !self.profiles.contains(&self.current) {
+            self.profiles.push(self.current.clone());
+        }
+    }
+
+    /// プロファイルの選択欄を表示
+    ///
+    /// 別のプロファイルが選ばれたら、その名前を返す。
+    /// `enabled` が false の間 (ジョブの実行中など) は切り替えられない。
+    pub fn show(&mut self, ui: &mut egui::Ui, enabled: bool) -> Option<String> {
+        let mut selected = None;
+
+        ui.add_enabled_ui(enabled, |ui| {
+            ui.label("プロファイル:");
+            egui::ComboBox::from_id_salt("profile_selector")
+                .selected_text(&self.current)
+                .show_ui(ui, |ui| {
+                    for profile in &self.profiles {
+                        if ui
+                            .selectable_label(*profile == self.current, profile)
+                            .clicked()
+                            && *profile != self.current
+                        {
+                            selected = Some(profile.clone());
+                        }
+                    }
+                });
+
+            ui.menu_button("+", |ui| {
+                ui.label("新しいプロファイル名 (英数字と - _):");
+                ui.text_edit_singleline(&mut self.new_profile_name);
+                ui.weak("現在のプロファイルの設定をコピーして作成します");
+
+                if ui.button("作成して切り替え").clicked() {
+                    match self.create_profile() {
+                        Ok(name) => {
+                            self.status = None;
+                            selected = Some(name);
+                            ui.close_menu();
+                        }
+                        Err(e) => self.status = Some(format!("{e:#}")),
+                    }
+                }
+            });
+        });
+
+        if let Some(status) = &self.status {
+            ui.colored_label(egui::Color32::LIGHT_RED, status);
+        }
+
+        if let Some(profile) = &selected {
+            self.current = profile.clone();
+        }
+        selected
+    }
+
+    /// 現在のプロファイルの設定ファイルをコピーして、新しいプロファイルを作る
+    fn create_profile(&mut self) -> anyhow::Result<String> {
+        let name = self.new_profile_name.trim().to_owned();
+        if !config::is_valid_profile_name(&name) {
+            return Err(anyhow!("プロファイル名に使えない文字が含まれています"));
+        }
+        if self.profiles.contains(&name) {
+            return Err(anyhow!("{name} は既にあります"));
+        }
+
+        let path = config::config_file_path(&name)?;
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::copy(config::config_file_path(&self.current)?, &path)?;
+
+        self.new_profile_name.clear();
+        self.reload();
+        Ok(name)
+    }
+}
@@ -0,0 +1,266 @@
+mod schema_migration_app;
+
+pub use schema_migration_app::SchemaMigrationApp;
+
+use std::collections::{HashMap, HashSet};
+
+use anyhow::{Context, anyhow};
+use sqlx::{
+    PgConnection, PgPool,
+    migrate::{Migration, Migrator},
+};
+
+/// バイナリに埋め込んだマイグレーション (リポジトリの migrations/)
+///
+/// 適用状況は `VERSION_TABLE` に記録し、sqlx の `Migrator::run` は使わない。
+pub static MIGRATOR: Migrator = sqlx::migrate!();
+
+/// 未適用のマイグレーション 1 件
+#[derive(Debug, Clone)]
+pub struct PendingMigration {
+    pub version: i64,
+    pub description: String,
+}
+
+/// このアプリのマイグレーションの適用状況を記録するテーブル
+///
+/// murack-core など他のツールのマイグレーションと混ざらないよう、sqlx 既定の
+/// _sqlx_migrations とは別のテーブルを使う。
+const VERSION_TABLE: &str = "murack_sync_migrations";
+
+/// このアプリの SQL が参照する murack-core の列 (テーブル名, 列名)
+///
+/// テーブル自体は murack-core が作成するので、マイグレーションではなく列の有無で確認する。
+/// このアプリのクエリで参照する列を増やしたら、ここにも追加する。
+const REQUIRED_COLUMNS: [(&str, &str); 13] = [
+    ("tracks", "id"),
+    ("tracks", "path"),
+    ("tracks", "title"),
+    ("tracks", "artist"),
+    ("tracks", "album"),
+    ("tracks", "duration"),
+    ("playlists", "id"),
+    ("playlists", "parent_id"),
+    ("playlists", "name"),
+    ("playlists", "save_dap"),
+    ("playlists", "in_folder_order"),
+    ("playlist_tracks", "playlist_id"),
+    ("playlist_tracks", "track_id"),
+];
+
+/// 接続先 DB のスキーマとアプリが期待するスキーマの比較結果
+#[derive(Debug, Clone)]
+pub struct SchemaStatus {
+    /// DB に適用済みの最新バージョン (未適用なら None)
+    pub current_version: Option<i64>,
+    /// アプリが期待するバージョン (マイグレーションがなければ None)
+    pub expected_version: Option<i64>,
+    pub pending: Vec<PendingMigration>,
+    /// マイグレーションを適用しても解決しない不整合
+    pub problems: Vec<String>,
+}
+
+impl SchemaStatus {
+    /// マイグレーションで解決できる状態か
+    pub fn is_compatible(&self) -> bool {
+        self.problems.is_empty()
+    }
+
+    /// そのままコマンドを実行してよい状態か
+    pub fn is_up_to_date(&self) -> bool {
+        self.is_compatible() && self.pending.is_empty()
+    }
+
+    /// 実行できない理由の説明 (問題がなければ None)
+    pub fn describe_problem(&self) -> Option<String> {
+        if !self.is_compatible() {
+            return Some(self.problems.join("\n"));
+        }
+        if !self.pending.is_empty() {
+            return Some(format!(
+                "DB のスキーマが古いままです (現在: {}, 必要: {})。未適用のマイグレーションが {} 件あります",
+                version_label(self.current_version),
+                version_label(self.expected_version),
+                self.pending.len()
+            ));
+        }
+        None
+    }
+}
+
+pub fn version_label(version: Option<i64>) -> String {
+    version.map_or("なし".to_owned(), |v| v.to_string())
+}
+
+/// DB のスキーマがこのアプリで使えるかを確認する
+///
+/// murack-core が作成するテーブルに必要な列があるかと、
+/// このアプリのマイグレーションの適用状況を確認する。
+pub async fn check_schema(pool: &PgPool) -> anyhow::Result<SchemaStatus> {
+    let mut problems = vec![];
+
+    let missing_columns = find_missing_columns(pool).await?;
+    if !missing_columns.is_empty() {
+        problems.push(format!(
+            "DB に必要な列がありません: {}。murack-core で DB を初期化・更新してください",
+            missing_columns.join(", ")
+        ));
+    }
+
+    let applied = applied_migrations(&mut *pool.acquire().await?).await?;
+
+    let embedded: HashMap<i64, &[u8]> = up_migrations()
+        .map(|migration| (migration.version, migration.checksum.as_ref()))
+        .collect();
+
+    for (version, checksum) in &applied {
+        match embedded.get(version) {
+            None => problems.push(format!(
+                "DB にこのアプリが知らないマイグレーション {version} が適用されています。アプリを更新してください"
+            )),
+            Some(expected) if *expected != checksum.as_slice() => problems.push(format!(
+                "マイグレーション {version} の内容が、DB に適用されたものと異なります"
+            )),
+            Some(_) => {}
+        }
+    }
+
+    let pending = up_migrations()
+        .filter(|migration| !applied.iter().any(|(v, _)| *v == migration.version))
+        .map(|migration| PendingMigration {
+            version: migration.version,
+            description: migration.description.to_string(),
+        })
+        .collect();
+
+    Ok(SchemaStatus {
+        current_version: applied.iter().map(|(v, _)| *v).max(),
+        expected_version: embedded.keys().copied().max(),
+        pending,
+        problems,
+    })
+}
+
+/// DB に適用済みのマイグレーションの最新バージョン (未適用なら None)
+///
+/// トランザクション内の他の読み込みと同じ時点のバージョンを得られるよう、接続を受け取る。
+pub async fn current_version(conn: &mut PgConnection) -> anyhow::Result<Option<i64>> {
+    Ok(applied_migrations(conn)
+        .await?
+        .iter()
+        .map(|(version, _)| *version)
+        .max())
+}
+
+/// 適用済みのマイグレーションの (バージョン, チェックサム)
+async fn applied_migrations(conn: &mut PgConnection) -> anyhow::Result<Vec<(i64, Vec<u8>)>> {
+    let has_table: bool = sqlx::query_scalar("SELECT to_regclass($1) IS NOT NULL")
+        .bind(VERSION_TABLE)
+        .fetch_one(&mut *conn)
+        .await
+        .context("Failed to check schema version")?;
+    if !has_table {
+        return Ok(vec![]);
+    }
+
+    let applied = sqlx::query_as(&format!(
+        "SELECT version, checksum FROM {VERSION_TABLE} ORDER BY version"
+    ))
+    .fetch_all(&mut *conn)
+    .await
+    .context("Failed to read applied migrations")?;
+    Ok(applied)
+}
+
+fn up_migrations() -> impl Iterator<Item = &'static Migration> {
+    MIGRATOR
+        .iter()
+        .filter(|migration| migration.migration_type.is_up_migration())
+}
+
+/// `REQUIRED_COLUMNS` のうち、DB にない列 ("テーブル.列" の形式)
+async fn find_missing_columns(pool: &PgPool) -> anyhow::Result<Vec<String>> {
+    let tables: Vec<&str> = REQUIRED_COLUMNS.iter().map(|(table, _)| *table).collect();
+    let existing: HashSet<(String, String)> = sqlx::query_as(
+        "SELECT table_name::text, column_name::text FROM information_schema.columns
+        WHERE table_schema = current_schema() AND table_name = ANY($1)",
+    )
+    .bind(&tables)
+    .fetch_all(pool)
+    .await
+    .context("Failed to read table columns")?
+    .into_iter()
+    .collect();
+
+    Ok(REQUIRED_COLUMNS
+        .iter()
+        .filter(|(table, column)| !existing.contains(&(table.to_string(), column.to_string())))
+        .map(|(table, column)| format!("{table}.{column}"))
+        .collect())
+}
+
+/// 未適用のマイグレーションを適用する
+///
+/// 1 件ずつトランザクション内で適用し、適用したバージョンを記録する。
+pub async fn apply_migrations(pool: &PgPool) -> anyhow::Result<()> {
+    sqlx::query(&format!(
+        "CREATE TABLE IF NOT EXISTS {VERSION_TABLE} (
+            version BIGINT PRIMARY KEY,
+            description TEXT NOT NULL,
+            checksum BYTEA NOT NULL,
+            applied_at TIMESTAMPTZ NOT NULL DEFAULT now()
+        )"
+    ))
+    .execute(pool)
+    .await
+    .context("Failed to create the migration table")?;
+
+    let status = check_schema(pool).await?;
+    if let Some(problem) = status.problems.first() {
+        return Err(anyhow!("{problem}"));
+    }
+
+    let pending: Vec<&Migration> = up_migrations()
+        .filter(|migration| {
+            status
+                .pending
+                .iter()
+                .any(|p| p.version == migration.version)
+        })
+        .collect();
+    for migration in pending {
+        apply_migration(pool, migration)
+            .await
+            .with_context(|| format!("Failed to apply migration {}", migration.version))?;
+    }
+
+    Ok(())
+}
+
+async fn apply_migration(pool: &PgPool, migration: &Migration) -> anyhow::Result<()> {
+    let mut tx = pool.begin().await?;
+
+    // 引数なしの文字列は simple query で実行されるので、複数の文を含められる
+    sqlx::Executor::execute(&mut *tx, migration.sql.as_ref()).await?;
+    sqlx::query(&format!(
+        "INSERT INTO {VERSION_TABLE} (version, description, checksum) VALUES ($1, $2, $3)"
+    ))
+    .bind(migration.version)
+    .bind(&*migration.description)
+    .bind(&*migration.checksum)
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn baseline_migration_is_embedded() {
+        assert!(up_migrations().next().is_some());
+    }
+}
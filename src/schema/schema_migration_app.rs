@@ -0,0 +1,129 @@
+use std::sync::Arc;
+
+use eframe::egui::{self, RichText, mutex::Mutex};
+use sqlx::PgPool;
+
+use super::{SchemaStatus, apply_migrations, check_schema, version_label};
+
+/// マイグレーションの適用状態
+#[derive(Default)]
+enum ApplyState {
+    #[default]
+    Idle,
+    Applying,
+    Failed(String),
+    /// 適用後に確認し直したスキーマ
+    Applied(SchemaStatus),
+}
+
+/// DB のスキーマが古いときに、マイグレーションの適用を促す
+pub struct SchemaMigrationApp {
+    db_pool: Arc<PgPool>,
+    status: SchemaStatus,
+    /// バックアップを取ったことの確認
+    backup_confirmed: bool,
+    apply_state: Arc<Mutex<ApplyState>>,
+}
+
+impl SchemaMigrationApp {
+    pub fn new(db_pool: Arc<PgPool>, status: SchemaStatus) -> Self {
+        Self {
+            db_pool,
+            status,
+            backup_confirmed: false,
+            apply_state: Arc::default(),
+        }
+    }
+
+    /// 確認画面を表示
+    ///
+    /// スキーマが最新になったら true を返す。
+    pub fn show(&mut self, ui: &mut egui::Ui) -> bool {
+        if let ApplyState::Applied(status) = &*self.apply_state.lock() {
+            self.status = status.clone();
+        }
+        if self.status.is_up_to_date() {
+            return true;
+        }
+
+        ui.label(RichText::new("データベースの更新").heading().strong());
+        ui.add_space(8.0);
+
+        ui.label(format!(
+            "スキーマのバージョン: 現在 {} / 必要 {}",
+            version_label(self.status.current_version),
+            version_label(self.status.expected_version)
+        ));
+        ui.add_space(4.0);
+
+        if !self.status.is_compatible() {
+            for problem in &self.status.problems {
+                ui.colored_label(egui::Color32::LIGHT_RED, format!("✖ {problem}"));
+            }
+            ui.add_space(4.0);
+            ui.label("このままでは起動できません。DB またはアプリを確認してください。");
+            return false;
+        }
+
+        ui.label("以下のマイグレーションが未適用です。");
+        egui::Grid::new("pending_migrations")
+            .num_columns(2)
+            .striped(true)
+            .show(ui, |ui| {
+                for migration in &self.status.pending {
+                    ui.monospace(migration.version.to_string());
+                    ui.label(&migration.description);
+                    ui.end_row();
+                }
+            });
+
+        ui.add_space(8.0);
+        ui.colored_label(
+            egui::Color32::LIGHT_YELLOW,
+            "⚠ 適用すると元に戻せません。先に DB のバックアップを取ってください (例: pg_dump)。",
+        );
+        ui.checkbox(&mut self.backup_confirmed, "バックアップを取りました");
+
+        ui.add_space(8.0);
+        let applying = matches!(&*self.apply_state.lock(), ApplyState::Applying);
+        ui.horizontal(|ui| {
+            if ui
+                .add_enabled(
+                    self.backup_confirmed && !applying,
+                    egui::Button::new(RichText::new("適用").strong()),
+                )
+                .clicked()
+            {
+                self.start_apply(ui.ctx().clone());
+            }
+            if applying {
+                ui.spinner();
+                ui.label("適用中...");
+            }
+        });
+
+        if let ApplyState::Failed(message) = &*self.apply_state.lock() {
+            ui.colored_label(egui::Color32::LIGHT_RED, message);
+        }
+
+        false
+    }
+
+    fn start_apply(&self, ctx: egui::Context) {
+        let apply_state = self.apply_state.clone();
+        let db_pool = self.db_pool.clone();
+        *apply_state.lock() = ApplyState::Applying;
+
+        tokio::spawn(async move {
+            let result = match apply_migrations(&db_pool).await {
+                Ok(()) => check_schema(&db_pool).await,
+                Err(e) => Err(e),
+            };
+            *apply_state.lock() = match result {
+                Ok(status) => ApplyState::Applied(status),
+                Err(e) => ApplyState::Failed(format!("{e:#}")),
+            };
+            ctx.request_repaint();
+        });
+    }
+}
@@ -0,0 +1,5 @@
+mod config_form;
+mod settings_editor_app;
+
+pub use config_form::{CheckResult, ConfigForm, check_database};
+pub use settings_editor_app::{SettingsEditorApp, show_check_results};
@@ -0,0 +1,135 @@
+use std::{path::PathBuf, sync::Arc};
+
+use eframe::egui::{self, RichText, mutex::Mutex};
+
+use crate::settings_editor::config_form::{CheckResult, ConfigForm};
+
+/// 検証の実行状態
+#[derive(Default)]
+enum CheckState {
+    #[default]
+    NotChecked,
+    Checking,
+    Checked(Vec<CheckResult>),
+}
+
+/// config.toml を編集するページ
+pub struct SettingsEditorApp {
+    config_path: PathBuf,
+    form: ConfigForm,
+    check_state: Arc<Mutex<CheckState>>,
+    /// 読み込み・保存の結果などのメッセージ
+    status: Option<String>,
+}
+
+impl SettingsEditorApp {
+    pub fn new(config_path: PathBuf) -> Self {
+        let mut app = Self {
+            form: ConfigForm::from_table(&toml::Table::new()),
+            config_path,
+            check_state: Arc::default(),
+            status: None,
+        };
+        app.reload();
+        app
+    }
+
+    /// 設定ファイルを読み込み直す
+    fn reload(&mut self) {
+        match ConfigForm::load(&self.config_path) {
+            Ok(form) => {
+                self.form = form;
+                self.status = None;
+            }
+            Err(e) => self.status = Some(format!("読み込みに失敗しました: {e:#}")),
+        }
+        *self.check_state.lock() = CheckState::NotChecked;
+    }
+
+    fn start_check(&self, ctx: egui::Context) {
+        let form = self.form.clone();
+        let check_state = self.check_state.clone();
+        *check_state.lock() = CheckState::Checking;
+
+        tokio::spawn(async move {
+            let results = form.check().await;
+            *check_state.lock() = CheckState::Checked(results);
+            ctx.request_repaint();
+        });
+    }
+
+    pub fn show(&mut self, ui: &mut egui::Ui) {
+        ui.label(RichText::new("設定").heading().strong());
+        ui.label(format!("設定ファイル: {}", self.config_path.display()));
+
+        ui.add_space(8.0);
+
+        egui::ScrollArea::vertical()
+            .id_salt("config_form")
+            .max_height(300.0)
+            .show(ui, |ui| {
+                self.form.show(ui);
+            });
+
+        ui.add_space(8.0);
+
+        ui.horizontal(|ui| {
+            let checking = matches!(&*self.check_state.lock(), CheckState::Checking);
+            if ui
+                .add_enabled(!checking, egui::Button::new("検証"))
+                .clicked()
+            {
+                self.start_check(ui.ctx().clone());
+            }
+
+            if ui.button("保存").clicked() {
+                self.status = Some(match self.form.save(&self.config_path) {
+                    Ok(()) => "保存しました。反映するにはアプリを再起動してください".to_owned(),
+                    Err(e) => format!("保存に失敗しました: {e:#}"),
+                });
+            }
+
+            if ui.button("再読み込み").clicked() {
+                self.reload();
+            }
+        });
+
+        if let Some(status) = &self.status {
+            ui.label(status);
+        }
+
+        ui.separator();
+
+        show_check_state(ui, &self.check_state.lock());
+    }
+}
+
+/// 検証結果を表示
+pub fn show_check_results(ui: &mut egui::Ui, results: &[CheckResult]) {
+    for check in results {
+        ui.horizontal(|ui| match &check.result {
+            Ok(()) => {
+                ui.colored_label(egui::Color32::LIGHT_GREEN, "✔");
+                ui.label(&check.label);
+            }
+            Err(message) => {
+                ui.colored_label(egui::Color32::LIGHT_RED, "✖");
+                ui.label(&check.label);
+                ui.weak(message);
+            }
+        });
+    }
+}
+
+fn show_check_state(ui: &mut egui::Ui, check_state: &CheckState) {
+    match check_state {
+        CheckState::NotChecked => {}
+        CheckState::Checking => {
+            ui.horizontal(|ui| {
+                ui.spinner();
+                ui.label("検証中...");
+            });
+        }
+        CheckState::Checked(results) => show_check_results(ui, results),
+    }
+}
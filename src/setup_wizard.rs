@@ -0,0 +1,3 @@
+mod setup_wizard_app;
+
+pub use setup_wizard_app::SetupWizardApp;
@@ -0,0 +1,247 @@
+use std::{path::PathBuf, sync::Arc};
+
+use eframe::egui::{self, RichText, mutex::Mutex};
+
+use crate::settings_editor::{ConfigForm, check_database};
+
+/// ライブラリの設定項目
+const LIBRARY_KEYS: [&str; 3] = ["pc_lib", "dap_lib", "dap_playlist"];
+
+/// DB の設定項目
+const DATABASE_KEYS: [&str; 1] = ["database_url"];
+
+/// ウィザードの手順
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum WizardStep {
+    Welcome,
+    Library,
+    Database,
+    Finish,
+}
+
+/// 接続テストの状態
+#[derive(Default)]
+enum ConnectionTest {
+    #[default]
+    NotTested,
+    Testing,
+    /// テストした URL と結果
+    Tested(String, Result<(), String>),
+}
+
+/// 設定ファイルがない・読み込めない、または DB に接続できないときのセットアップ
+pub struct SetupWizardApp {
+    config_path: PathBuf,
+    /// セットアップが必要になった理由
+    reason: String,
+    step: WizardStep,
+    form: ConfigForm,
+    connection_test: Arc<Mutex<ConnectionTest>>,
+    /// 保存に失敗した場合などのメッセージ
+    status: Option<String>,
+}
+
+impl SetupWizardApp {
+    pub fn new(config_path: PathBuf, reason: String) -> Self {
+        // 既存の設定があれば、それを元に修正してもらう
+        let form = ConfigForm::load(&config_path)
+            .unwrap_or_else(|_| ConfigForm::from_table(&toml::Table::new()));
+
+        Self {
+            config_path,
+            reason,
+            step: WizardStep::Welcome,
+            form,
+            connection_test: Arc::default(),
+            status: None,
+        }
+    }
+
+    /// 保存した設定での起動に失敗したときに、最初の手順からやり直す
+    pub fn restart(&mut self, reason: String) {
+        self.reason = reason;
+        self.step = WizardStep::Welcome;
+        self.status = None;
+    }
+
+    /// ウィザードを表示
+    ///
+    /// 設定ファイルを保存したら true を返す。
+    pub fn show(&mut self, ui: &mut egui::Ui) -> bool {
+        ui.label(RichText::new("初期設定").heading().strong());
+        ui.add_space(8.0);
+
+        let saved = match self.step {
+            WizardStep::Welcome => {
+                self.show_welcome(ui);
+                false
+            }
+            WizardStep::Library => {
+                self.show_library(ui);
+                false
+            }
+            WizardStep::Database => {
+                self.show_database(ui);
+                false
+            }
+            WizardStep::Finish => self.show_finish(ui),
+        };
+
+        if let Some(status) = &self.status {
+            ui.colored_label(egui::Color32::LIGHT_RED, status);
+        }
+
+        saved
+    }
+
+    fn show_welcome(&mut self, ui: &mut egui::Ui) {
+        ui.label("Murack Sync を起動できませんでした。設定を作成・修正します。");
+        ui.add_space(4.0);
+        ui.weak(&self.reason);
+        ui.add_space(4.0);
+        ui.label(format!(
+            "設定は {} に保存されます。",
+            self.config_path.display()
+        ));
+
+        ui.add_space(8.0);
+        if ui.button("次へ").clicked() {
+            self.step = WizardStep::Library;
+        }
+    }
+
+    fn show_library(&mut self, ui: &mut egui::Ui) {
+        ui.label("PC と DAP のライブラリの場所を入力してください。");
+        ui.add_space(4.0);
+        self.form.show_fields(ui, Some(&LIBRARY_KEYS));
+
+        ui.add_space(4.0);
+        for key in LIBRARY_KEYS {
+            match self.form.text(key) {
+                Some(path) if !path.is_empty() && !std::path::Path::new(path).is_dir() => {
+                    ui.colored_label(
+                        egui::Color32::LIGHT_YELLOW,
+                        format!("⚠ {path} が見つかりません (DAP が未接続なら後で接続してください)"),
+                    );
+                }
+                _ => {}
+            }
+        }
+
+        let filled = LIBRARY_KEYS
+            .iter()
+            .all(|key| self.form.text(key).is_some_and(|path| !path.is_empty()));
+
+        ui.add_space(8.0);
+        ui.horizontal(|ui| {
+            if ui.button("戻る").clicked() {
+                self.step = WizardStep::Welcome;
+            }
+            if ui.add_enabled(filled, egui::Button::new("次へ")).clicked() {
+                self.step = WizardStep::Database;
+            }
+        });
+    }
+
+    fn show_database(&mut self, ui: &mut egui::Ui) {
+        ui.label("データベースの URL を入力し、接続をテストしてください。");
+        ui.add_space(4.0);
+        self.form.show_fields(ui, Some(&DATABASE_KEYS));
+
+        let url = self
+            .form
+            .text("database_url")
+            .unwrap_or_default()
+            .to_owned();
+
+        ui.add_space(4.0);
+        let passed = {
+            let connection_test = self.connection_test.lock();
+            match &*connection_test {
+                ConnectionTest::NotTested => false,
+                ConnectionTest::Testing => {
+                    ui.horizontal(|ui| {
+                        ui.spinner();
+                        ui.label("接続中...");
+                    });
+                    false
+                }
+                // URL を変更したらテストし直す
+                ConnectionTest::Tested(tested_url, _) if *tested_url != url => false,
+                ConnectionTest::Tested(_, Ok(())) => {
+                    ui.colored_label(egui::Color32::LIGHT_GREEN, "✔ 接続できました");
+                    true
+                }
+                ConnectionTest::Tested(_, Err(message)) => {
+                    ui.colored_label(egui::Color32::LIGHT_RED, format!("✖ {message}"));
+                    false
+                }
+            }
+        };
+
+        ui.add_space(8.0);
+        ui.horizontal(|ui| {
+            if ui.button("戻る").clicked() {
+                self.step = WizardStep::Library;
+            }
+
+            let testing = matches!(&*self.connection_test.lock(), ConnectionTest::Testing);
+            if ui
+                .add_enabled(!testing && !url.is_empty(), egui::Button::new("接続テスト"))
+                .clicked()
+            {
+                self.start_connection_test(url.clone(), ui.ctx().clone());
+            }
+
+            if ui.add_enabled(passed, egui::Button::new("次へ")).clicked() {
+                self.step = WizardStep::Finish;
+            }
+        });
+    }
+
+    fn start_connection_test(&self, url: String, ctx: egui::Context) {
+        let connection_test = self.connection_test.clone();
+        *connection_test.lock() = ConnectionTest::Testing;
+
+        tokio::spawn(async move {
+            let result = check_database(&url).await;
+            *connection_test.lock() = ConnectionTest::Tested(url, result);
+            ctx.request_repaint();
+        });
+    }
+
+    fn show_finish(&mut self, ui: &mut egui::Ui) -> bool {
+        ui.label("以下の設定で保存して起動します。");
+        ui.add_space(4.0);
+
+        egui::Grid::new("setup_summary")
+            .num_columns(2)
+            .show(ui, |ui| {
+                for key in LIBRARY_KEYS.iter().chain(&DATABASE_KEYS) {
+                    ui.label(*key);
+                    ui.label(self.form.text(key).unwrap_or_default());
+                    ui.end_row();
+                }
+            });
+
+        let mut saved = false;
+
+        ui.add_space(8.0);
+        ui.horizontal(|ui| {
+            if ui.button("戻る").clicked() {
+                self.step = WizardStep::Database;
+            }
+            if ui.button(RichText::new("保存して起動").strong()).clicked() {
+                match self.form.save(&self.config_path) {
+                    Ok(()) => {
+                        self.status = None;
+                        saved = true;
+                    }
+                    Err(e) => self.status = Some(format!("保存に失敗しました: {e:#}")),
+                }
+            }
+        });
+
+        saved
+    }
+}
@@ -0,0 +1,50 @@
+use std::{path::PathBuf, sync::Arc};
+
+use murack_core_app::Config;
+use sqlx::PgPool;
+
+use crate::{
+    backup::BackupStore,
+    config, database,
+    legacy_commands::operation_journal::OperationJournal,
+    schema::{self, SchemaStatus},
+    sync_settings::SyncSettings,
+};
+
+/// 起動時に読み込む設定と接続
+pub struct AppServices {
+    pub profile: String,
+    pub config_path: PathBuf,
+    pub config: Arc<Config>,
+    pub settings: Arc<SyncSettings>,
+    pub db_pool: Arc<PgPool>,
+    pub journal: Arc<OperationJournal>,
+    pub backups: Arc<BackupStore>,
+    /// 接続先 DB のスキーマの状態 (古ければ使う前にマイグレーションが必要)
+    pub schema: SchemaStatus,
+}
+
+impl AppServices {
+    /// プロファイルの設定ファイルを読み込み、DB に接続する
+    pub async fn load(profile: &str) -> anyhow::Result<Self> {
+        let config_path = config::config_file_path(profile)?;
+        let config = config::load_config(profile)?;
+        let settings = config::load_sync_settings()?;
+
+        let db_pool = database::connect_db_pool(&config.database_url).await?;
+        let schema = schema::check_schema(&db_pool).await?;
+        let journal = OperationJournal::open_profile(profile).await?;
+        let backups = BackupStore::open(profile)?;
+
+        Ok(Self {
+            profile: profile.to_owned(),
+            config_path,
+            config: Arc::new(config),
+            settings: Arc::new(settings),
+            db_pool: Arc::new(db_pool),
+            journal: Arc::new(journal),
+            backups: Arc::new(backups),
+            schema,
+        })
+    }
+}
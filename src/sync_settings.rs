@@ -0,0 +1,46 @@
+use std::path::Path;
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+
+use crate::legacy_commands::{auto_sync::AutoSyncSettings, policy_cui::ResolvePolicy};
+
+/// murack-sync 独自の設定
+///
+/// murack-core の `Config` とは別ファイル (sync.toml) に保存する。
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SyncSettings {
+    /// check コマンドの齟齬の解決方針の既定値
+    pub check_resolve_policy: ResolvePolicy,
+    /// ジョブの終了時にデスクトップ通知を出すか (Linux のみ)
+    pub desktop_notification: bool,
+    /// move・remove や齟齬を解決する check の前に、自動で DB をバックアップするか
+    pub auto_backup_before_destructive: bool,
+    /// DAP の接続時の自動同期
+    pub auto_sync: AutoSyncSettings,
+    /// 前回使ったプロファイル (None なら既定のプロファイル)
+    pub active_profile: Option<String>,
+}
+
+impl SyncSettings {
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        toml::from_str(&text).with_context(|| format!("Failed to parse {}", path.display()))
+    }
+
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)
+                .with_context(|| format!("Failed to create {}", dir.display()))?;
+        }
+
+        let text = toml::to_string_pretty(self)?;
+        std::fs::write(path, text).with_context(|| format!("Failed to write {}", path.display()))
+    }
+}